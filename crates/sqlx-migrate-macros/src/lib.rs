@@ -0,0 +1,50 @@
+//! Procedural macros for [sqlx-migrate](https://docs.rs/sqlx-migrate),
+//! use them through the `register` feature of `sqlx-migrate`.
+#![warn(clippy::pedantic)]
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, ItemFn, LitStr};
+
+/// Register the migration returned by the function, see `sqlx_migrate::register`.
+///
+/// The registered migrations are ordered by their keys, the key defaults
+/// to the name of the function and can be set with `key = "..."`.
+#[proc_macro_attribute]
+pub fn register_migration(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let func = parse_macro_input!(item as ItemFn);
+
+    let mut key = None;
+
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("key") {
+            key = Some(meta.value()?.parse::<LitStr>()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported attribute, expected `key`"))
+        }
+    });
+
+    parse_macro_input!(attr with parser);
+
+    if !func.sig.inputs.is_empty() || func.sig.asyncness.is_some() {
+        return syn::Error::new_spanned(
+            &func.sig,
+            "migration functions must not be async nor have arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let ident = &func.sig.ident;
+    let key = key.unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+
+    quote! {
+        #func
+
+        ::sqlx_migrate::register::inventory::submit! {
+            ::sqlx_migrate::register::RegisteredMigration::new(#key, #ident)
+        }
+    }
+    .into()
+}
@@ -5,14 +5,29 @@
     dead_code,
     unused_variables
 )]
-use crate::{db, prelude::*, DatabaseType, DEFAULT_MIGRATIONS_TABLE};
-use clap::Parser;
+use crate::{
+    db,
+    history::{encode_hex, MigrationHistory},
+    lint::{self, LintRule, Linter, Severity},
+    migrate_ignore::{MigrateIgnore, IGNORE_FILE},
+    prelude::*,
+    report, ChecksumSampling, DatabaseType, Error, HistoryDiff, MigrationPhase, MigrationsDirs,
+    Rehearsal, SchemaDrift, StatementLogging, DEFAULT_MIGRATIONS_TABLE,
+};
+use clap::{CommandFactory, FromArgMatches, Parser};
 use comfy_table::{Cell, CellAlignment, ContentArrangement, Table};
 use filetime::FileTime;
+use futures_core::future::LocalBoxFuture;
 use regex::Regex;
 use sqlx::{ConnectOptions, Database, Executor};
-use std::{fs, io, path::Path, process, str::FromStr, time::Duration};
-use time::{format_description, OffsetDateTime};
+use std::{
+    fmt::Write,
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tracing_subscriber::{
     fmt::format::FmtSpan, prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt,
     EnvFilter,
@@ -33,25 +48,100 @@ pub struct Migrate {
     /// Skip verifying migration checksums.
     #[clap(long, alias = "no-verify-checksum", global(true))]
     pub no_verify_checksums: bool,
+    /// Verify the checksums of only some applied migrations,
+    /// `last:<N>` for the last N or `random:<N>` for N random ones.
+    ///
+    /// `check --ci` always verifies all of them.
+    #[clap(long, value_name = "SAMPLING", default_value = "all", global(true))]
+    pub checksum_sampling: ChecksumSampling,
     /// Skip verifying migration names.
     #[clap(long, alias = "no-verify-name", global(true))]
     pub no_verify_names: bool,
+    /// Refuse to run if the database has more applied migrations than this binary,
+    /// e.g. after a deployment was rolled back.
+    #[clap(long, global(true))]
+    pub refuse_newer_database: bool,
     /// Skip loading .env files.
     #[clap(long, global(true))]
     pub no_env_file: bool,
+    /// The async runtime, `multi` for migrations that spawn tasks
+    /// that must run on other threads.
+    #[clap(long, value_enum, default_value = "current", global(true))]
+    pub runtime: Runtime,
     /// Log all SQL statements.
     #[clap(long, global(true))]
     pub log_statements: bool,
+    /// Append the statements executed by migrations to the given file.
+    #[clap(long, value_name = "FILE", global(true))]
+    pub statement_log: Option<PathBuf>,
     /// Database URL, if not given the `DATABASE_URL` environment variable will be used.
     #[clap(long, visible_alias = "db-url", global(true))]
     pub database_url: Option<String>,
+    /// Database URL of a read replica used by operations that do not modify the database
+    /// (`status`, `check`, `doctor`, `drift`, `diff` and `history export`),
+    /// if not given the `DATABASE_REPLICA_URL` environment variable will be used.
+    #[clap(long, global(true))]
+    pub replica_url: Option<String>,
     /// The name of the migrations table.
     #[clap(long, default_value = DEFAULT_MIGRATIONS_TABLE, global(true))]
     pub migrations_table: String,
+    /// An SQL file that creates the migrations table if it does not exist,
+    /// `{table}` is replaced with the name of the migrations table.
+    #[clap(long, value_name = "FILE", global(true))]
+    pub migrations_table_ddl: Option<PathBuf>,
+    /// A PostgreSQL extension that is created before migrating if it does not exist,
+    /// can be given multiple times.
+    #[clap(long = "extension", value_name = "NAME", global(true))]
+    pub extensions: Vec<String>,
+    /// Create the schema of the migrations table and the schemas in the
+    /// PostgreSQL `search_path` if they do not exist.
+    #[clap(long, global(true))]
+    pub create_schemas: bool,
+    /// A parameter that migrations can read from the `Params` extension,
+    /// can be given multiple times.
+    #[clap(long = "param", value_name = "KEY=VALUE", value_parser = parse_param, global(true))]
+    pub params: Vec<(String, String)>,
+    /// Hold the migration lock while migrating, and verify that
+    /// it is still held at the given interval (e.g. `30s`).
+    #[clap(long, value_name = "INTERVAL", value_parser = humantime::parse_duration, global(true))]
+    pub lock_heartbeat: Option<Duration>,
+    /// Reconnect at most this many times if the connection is lost while
+    /// migrating, and resume with the first pending migration.
+    #[clap(long, value_name = "ATTEMPTS", default_value_t = 0, global(true))]
+    pub reconnect_attempts: u32,
+    /// Record the execution time of every run of a migration,
+    /// see `report timings`.
+    #[clap(long, global(true))]
+    pub record_runs: bool,
+    /// A webhook URL that receives JSON notifications when migrations
+    /// are started, applied or failed.
+    #[cfg(feature = "notify")]
+    #[clap(long, global(true))]
+    pub webhook_url: Option<String>,
     #[clap(subcommand)]
     pub operation: Operation,
 }
 
+impl Migrate {
+    /// Add the operations to an existing application as the `migrate` subcommand,
+    /// e.g. `myapp migrate status`.
+    ///
+    /// The matches of the subcommand can be passed to [`Runner::from_matches`].
+    #[must_use]
+    pub fn augment_subcommand(cmd: clap::Command) -> clap::Command {
+        Self::augment_subcommand_named(cmd, "migrate")
+    }
+
+    /// Same as [`Migrate::augment_subcommand`], but with the given subcommand name.
+    #[must_use]
+    pub fn augment_subcommand_named(
+        cmd: clap::Command,
+        name: impl Into<clap::builder::Str>,
+    ) -> clap::Command {
+        cmd.subcommand(Self::command().name(name))
+    }
+}
+
 /// A command-line operation.
 #[derive(Debug, clap::Subcommand)]
 pub enum Operation {
@@ -69,6 +159,42 @@ pub enum Operation {
         /// with the given version.
         #[clap(long, conflicts_with = "name")]
         version: Option<u64>,
+
+        /// Apply the migrations of the given deployment phase.
+        ///
+        /// With `pre`, migrations are applied up to the first
+        /// pending `post` migration.
+        #[clap(long, conflicts_with_all = ["name", "version"], value_enum)]
+        phase: Option<MigrationPhase>,
+
+        /// Apply the migration with the given version even if
+        /// it is reported as dangerous by the linter.
+        #[clap(long, value_name = "VERSION")]
+        unsafe_allow: Vec<u64>,
+
+        /// Record the migration with the given version as applied
+        /// without executing it.
+        #[clap(long, value_name = "VERSION", conflicts_with = "phase")]
+        skip: Vec<u64>,
+
+        /// Run `ANALYZE` on the tables that a migration modified
+        /// by more than the given number of rows.
+        #[clap(long, value_name = "ROWS")]
+        analyze_threshold: Option<u64>,
+
+        /// Apply the pending migrations in a transaction that is rolled back,
+        /// and report their durations and failures without persisting anything.
+        #[clap(long, conflicts_with_all = ["phase", "skip"])]
+        rehearse: bool,
+
+        /// Apply all migrations up to and including the version
+        /// tagged as the given release, see `release tag`.
+        #[clap(
+            long,
+            value_name = "RELEASE",
+            conflicts_with_all = ["name", "version", "phase", "skip", "rehearse"]
+        )]
+        to_release: Option<String>,
     },
     /// Revert the given migration and all subsequent ones.
     ///
@@ -84,6 +210,21 @@ pub enum Operation {
         /// the given version.
         #[clap(long, conflicts_with = "name")]
         version: Option<u64>,
+
+        /// Revert all migrations after the version tagged
+        /// as the given release, see `release tag`.
+        #[clap(long, value_name = "RELEASE", conflicts_with_all = ["name", "version"])]
+        to_release: Option<String>,
+
+        /// Remove migrations without down migrations from the migrations
+        /// table without reverting their changes instead of failing.
+        #[clap(long)]
+        allow_irreversible: bool,
+
+        /// Revert migrations marked as irreversible boundaries,
+        /// e.g. cleanups that removed data the down migrations cannot restore.
+        #[clap(long)]
+        cross_boundary: bool,
     },
     /// Forcibly set a given migration.
     ///
@@ -98,12 +239,165 @@ pub enum Operation {
         #[clap(long, conflicts_with = "name", required_unless_present("name"))]
         version: Option<u64>,
     },
+    /// Mark a single migration as applied or unapplied.
+    ///
+    /// This does not apply nor revert the migration, and
+    /// no other migrations are changed.
+    Mark {
+        /// The version of the migration.
+        #[clap(long)]
+        version: u64,
+        /// Record the migration as applied, it must be
+        /// the next migration to be applied.
+        #[clap(
+            long,
+            conflicts_with = "unapplied",
+            required_unless_present("unapplied")
+        )]
+        applied: bool,
+        /// Remove the migration from the applied migrations,
+        /// it must be the last applied migration.
+        #[clap(long, conflicts_with = "applied", required_unless_present("applied"))]
+        unapplied: bool,
+    },
+    /// Repair the applied migrations in the database.
+    ///
+    /// Migrations applied with a previous name (alias) are renamed.
+    Repair {},
+    /// Detect schema changes in the database that were not made by migrations.
+    ///
+    /// The applied migrations are executed in an empty scratch database
+    /// and the resulting schema is compared to the schema of the database,
+    /// all changes in the scratch database are rolled back.
+    Drift {
+        /// Database URL of the empty scratch database.
+        #[clap(long)]
+        scratch_url: String,
+    },
+    /// Apply all migrations to an empty scratch database and report failures.
+    ///
+    /// PostgreSQL migrations are applied in a temporary schema, SQLite migrations
+    /// in an in-memory database, the migrations table and the schema of the
+    /// database are not changed.
+    Rehearse {},
+    /// Compare the applied migrations with the ones applied in another database.
+    ///
+    /// Differences in applied versions, names and checksums are listed,
+    /// the local migrations are not used.
+    Diff {
+        /// Database URL of the other database.
+        #[clap(long, value_name = "URL")]
+        other: String,
+    },
+    /// Remove applied migrations from the database that are missing locally.
+    ///
+    /// Without `--force` only the migrations that would be removed are listed.
+    Prune {},
+    /// Squash the first migrations into a single baseline SQL migration.
+    ///
+    /// The baseline is generated from the current database schema, the database
+    /// must be migrated up to and including the last squashed migration.
+    /// The files of the squashed migrations are removed.
+    ///
+    /// Databases that already applied the squashed migrations
+    /// can be reconciled with `set` afterwards.
+    #[cfg(debug_assertions)]
+    Squash {
+        /// Squash all migrations up to and including the migration
+        /// with the given version.
+        #[clap(long)]
+        through: u64,
+        /// The name of the baseline migration.
+        #[clap(long, default_value = "baseline")]
+        name: String,
+    },
+    /// List the tables locked exclusively by migrations that are not yet applied.
+    ///
+    /// The locked tables block all reads and writes while the migration
+    /// is running, large tables or table rewrites might warrant a maintenance window.
+    /// Only PostgreSQL is supported.
+    Locks {
+        /// Also list the query plans of the `INSERT`, `UPDATE`, `DELETE`
+        /// and `MERGE` statements, e.g. to spot full table scans of backfills.
+        #[clap(long)]
+        explain: bool,
+    },
+    /// Export or import the applied migrations recorded
+    /// in the migrations table.
+    History {
+        #[clap(subcommand)]
+        operation: HistoryOperation,
+    },
+    /// Tag the applied version as a release, or list the tagged releases.
+    Release {
+        #[clap(subcommand)]
+        operation: ReleaseOperation,
+    },
+    /// Report on the migrations recorded in the database.
+    Report {
+        #[clap(subcommand)]
+        operation: ReportOperation,
+    },
+    /// Render the migrations as Markdown.
+    ///
+    /// The version, name, date, description and source
+    /// of every migration is included.
+    #[clap(visible_aliases = &["doc"])]
+    Docs {
+        /// Write the Markdown to the given file instead of the standard output.
+        #[clap(long, short = 'o')]
+        output: Option<PathBuf>,
+    },
+    /// Lint the SQL executed by the migrations for dangerous patterns.
+    ///
+    /// The rules are configured by the `.migratelint` files in the migrations
+    /// directories, with lines such as `drop-column = allow`, the options
+    /// below take precedence.
+    ///
+    /// Exits with an error if any lint with the `error` severity is found.
+    Lint {
+        /// Disable the given rules.
+        #[clap(long, value_name = "RULE")]
+        allow: Vec<LintRule>,
+        /// Report the given rules as warnings.
+        #[clap(long, value_name = "RULE")]
+        warn: Vec<LintRule>,
+        /// Report the given rules as errors.
+        #[clap(long, value_name = "RULE")]
+        deny: Vec<LintRule>,
+    },
     /// Verify migrations and print errors.
     #[clap(visible_aliases = &["verify", "validate"])]
-    Check {},
+    Check {
+        /// Also fail if there are pending migrations, and exit with a code
+        /// specific to the problem: 2 for pending migrations, 3 for applied
+        /// migrations missing locally, 4 for name and 5 for checksum mismatches.
+        #[clap(long)]
+        ci: bool,
+        /// Check that the generated code at the given path is up to date
+        /// with the migrations directories instead of the database.
+        #[clap(long, value_name = "GENERATED_RS", conflicts_with = "ci")]
+        codegen: Option<PathBuf>,
+    },
+    /// Verify migrations and suggest fixes for the problems found.
+    Doctor {},
     /// List all migrations.
     #[clap(visible_aliases = &["list", "ls", "get"])]
-    Status {},
+    Status {
+        /// Also show when the migrations were created and their source files.
+        #[clap(long)]
+        wide: bool,
+
+        /// Warn if more than the given number of migrations are not applied,
+        /// e.g. for environments that stopped being migrated.
+        #[clap(long, value_name = "COUNT")]
+        behind_threshold: Option<u64>,
+
+        /// Only list the local migrations with their source files
+        /// without connecting to the database.
+        #[clap(long, conflicts_with = "behind_threshold")]
+        offline: bool,
+    },
     /// Add a new migration.
     ///
     /// The migrations default to Rust files.
@@ -127,6 +421,27 @@ pub enum Operation {
             value_enum
         )]
         ty: DatabaseType,
+        /// Create SQL specific to the given databases instead of generic SQL,
+        /// e.g. `name.migrate.pg.sql` and `name.migrate.sqlite.sql`.
+        ///
+        /// Can be given multiple times.
+        #[clap(long = "dialect", value_enum, requires = "sql")]
+        dialects: Vec<DatabaseType>,
+        /// Start from a built-in scaffold of a common operation
+        /// instead of an empty Rust migration.
+        #[clap(long, value_enum, conflicts_with = "sql")]
+        from_template: Option<Scaffold>,
+        /// Write the revert SQL of the existing SQL migration with the given name
+        /// instead of adding a new migration.
+        ///
+        /// `CREATE TABLE`, `CREATE INDEX` and `ADD COLUMN` statements are reverted,
+        /// other statements are left as TODO comments to be reverted manually.
+        #[clap(long, conflicts_with_all = ["reversible", "dialects", "from_template"])]
+        auto_revert: bool,
+        /// The directory to add the migration to,
+        /// the first migrations directory by default.
+        #[clap(long, conflicts_with = "auto_revert")]
+        dir: Option<PathBuf>,
         /// The name of the migration.
         ///
         /// It must be across all migrations.
@@ -134,183 +449,919 @@ pub enum Operation {
     },
 }
 
-/// Run a CLI application that provides operations with the
-/// given migrations.
+/// The tokio runtime that runs the operations, see [`Migrate::runtime`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Runtime {
+    /// A single-threaded runtime.
+    #[default]
+    Current,
+    /// A multi-threaded runtime, e.g. for migrations that backfill
+    /// data concurrently with spawned tasks.
+    Multi,
+}
+
+/// A built-in scaffold for new Rust migrations, see [`Operation::Add`].
+///
+/// The table, column and index names are placeholders to be replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Scaffold {
+    /// Create a table, the revert migration drops it.
+    CreateTable,
+    /// Add a nullable column and backfill it in batches.
+    AddColumn,
+    /// Create an index concurrently after the migration is committed.
+    CreateIndex,
+}
+
+impl Scaffold {
+    fn up_body(self) -> &'static str {
+        match self {
+            Scaffold::CreateTable => {
+                r#"    ctx.tx()
+        .execute(
+            r"
+            CREATE TABLE my_table (
+                id BIGINT PRIMARY KEY,
+                created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            ",
+        )
+        .await?;
+
+    Ok(())"#
+            }
+            Scaffold::AddColumn => {
+                r#"    // The column is nullable so that adding it does not rewrite the table.
+    ctx.tx()
+        .execute("ALTER TABLE my_table ADD COLUMN my_column TEXT")
+        .await?;
+
+    // The batch size is bound as `$1`, the query must not
+    // process the same rows twice.
+    ctx.batch(
+        r"
+        UPDATE my_table SET my_column = 'value'
+        WHERE id IN (SELECT id FROM my_table WHERE my_column IS NULL LIMIT $1)
+        ",
+        1000,
+    )
+    .await?;
+
+    Ok(())"#
+            }
+            Scaffold::CreateIndex => {
+                r#"    // `CONCURRENTLY` does not block writes, but it cannot run in a transaction,
+    // so the index is created after the migration is committed.
+    ctx.after_commit("CREATE INDEX CONCURRENTLY IF NOT EXISTS my_table_my_column_idx ON my_table (my_column)");
+
+    Ok(())"#
+            }
+        }
+    }
+
+    fn down_body(self) -> &'static str {
+        match self {
+            Scaffold::CreateTable => {
+                r#"    ctx.tx().execute("DROP TABLE my_table").await?;
+
+    Ok(())"#
+            }
+            Scaffold::AddColumn => {
+                r#"    ctx.tx()
+        .execute("ALTER TABLE my_table DROP COLUMN my_column")
+        .await?;
+
+    Ok(())"#
+            }
+            Scaffold::CreateIndex => {
+                r#"    ctx.after_commit("DROP INDEX CONCURRENTLY IF EXISTS my_table_my_column_idx");
+
+    Ok(())"#
+            }
+        }
+    }
+}
+
+/// A command-line operation on the migration history.
+#[derive(Debug, clap::Subcommand)]
+pub enum HistoryOperation {
+    /// Write the applied migrations as JSON.
+    Export {
+        /// Write the history to the given file instead of the standard output.
+        #[clap(long, short = 'f')]
+        file: Option<PathBuf>,
+    },
+    /// Record the applied migrations from a JSON file written by `history export`.
+    ///
+    /// The migrations are not executed, existing records are
+    /// only replaced with the `--force` flag.
+    Import {
+        /// The exported history.
+        #[clap(long, short = 'f')]
+        file: PathBuf,
+    },
+}
+
+/// A command-line operation on the releases tagged in the database.
+#[derive(Debug, clap::Subcommand)]
+pub enum ReleaseOperation {
+    /// Tag the version of the last applied migration as a release,
+    /// an existing release with the same name is moved.
+    Tag {
+        /// The name of the release, e.g. `v2.3.0`.
+        name: String,
+        /// Tag the given version instead of the last applied one.
+        #[clap(long)]
+        version: Option<u64>,
+    },
+    /// List the tagged releases.
+    List {},
+}
+
+/// A command-line report on the migrations recorded in the database.
+#[derive(Debug, clap::Subcommand)]
+pub enum ReportOperation {
+    /// List the durations of the runs of every migration recorded with `--record-runs`.
+    ///
+    /// The last run is compared to the average of the previous ones,
+    /// so that migrations that got slower stand out.
+    Timings {
+        /// Database URL of another environment to include in the report,
+        /// can be given multiple times.
+        #[clap(long, value_name = "URL")]
+        other: Vec<String>,
+    },
+}
+
+impl Operation {
+    /// Whether the operation only reads the database,
+    /// these operations use the replica URL if it is given.
+    #[must_use]
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Operation::Check { .. }
+                | Operation::Doctor {}
+                | Operation::Status { .. }
+                | Operation::Drift { .. }
+                | Operation::Diff { .. }
+                | Operation::Report { .. }
+                | Operation::Release {
+                    operation: ReleaseOperation::List {}
+                }
+                | Operation::History {
+                    operation: HistoryOperation::Export { .. }
+                }
+        )
+    }
+}
+
+type ConnectFn<Db> =
+    Box<dyn FnOnce(&Migrate) -> LocalBoxFuture<'static, Result<Migrator<Db>, Error>>>;
+
+type SetupFn<Db> = Box<dyn FnOnce(&mut Migrator<Db>)>;
+
+/// The result of a successful CLI operation.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CliOutcome {
+    /// Migrations were applied, reverted or the version was set.
+    Summary(MigrationSummary),
+    /// The statuses of the migrations from `status`.
+    Status(Vec<MigrationStatus>),
+    /// Any other operation.
+    Done,
+}
+
+/// A CLI application that provides operations with the given migrations.
 ///
 /// When compiled with `debug_assertions`, it additionally allows modifying migrations
-/// at the given `migrations_path`.
+/// in the given `migrations_dirs`, new migrations are added to the first directory
+/// unless `--dir` is given.
 ///
-/// Although not required, `migrations` are expected to be originated from `migrations_path`.
+/// Multiple directories can be given, e.g. `&["migrations/core", "migrations/billing"]`,
+/// see [`MigrationsDirs`].
 ///
-/// # Panics
+/// Although not required, `migrations` are expected to be originated from `migrations_dirs`.
 ///
-/// This functon assumes that it has control over the entire application.
+/// ```no_run
+/// # fn migrations() -> Vec<sqlx_migrate::Migration<sqlx::Postgres>> { Vec::new() }
+/// use clap::Parser;
+/// use sqlx_migrate::cli::{Migrate, Runner};
 ///
-/// It will happily alter global state (tracing), panic, or terminate the process.
-pub fn run<Db>(
-    migrations_path: impl AsRef<Path>,
-    migrations: impl IntoIterator<Item = Migration<Db>>,
-) where
+/// Runner::new(Migrate::parse(), "migrations", migrations()).run();
+/// ```
+pub struct Runner<Db>
+where
     Db: Database,
     Db::Connection: db::Migrations,
-    for<'a> &'a mut Db::Connection: Executor<'a>,
 {
-    run_parsed(Migrate::parse(), migrations_path, migrations);
+    migrate: Migrate,
+    migrations_dirs: Vec<PathBuf>,
+    migrations: Vec<Migration<Db>>,
+    setup: Option<SetupFn<Db>>,
+    connect: Option<ConnectFn<Db>>,
 }
 
-/// Same as [`run`], but allows for parsing and inspecting [`Migrate`] beforehand.
-#[allow(clippy::missing_panics_doc)]
-pub fn run_parsed<Db>(
-    migrate: Migrate,
-    migrations_path: impl AsRef<Path>,
-    migrations: impl IntoIterator<Item = Migration<Db>>,
-) where
+impl<Db> Runner<Db>
+where
     Db: Database,
     Db::Connection: db::Migrations,
     for<'a> &'a mut Db::Connection: Executor<'a>,
 {
-    setup_logging(&migrate);
-
-    if !migrate.no_env_file {
-        if let Ok(cwd) = std::env::current_dir() {
-            let env_path = cwd.join(".env");
-            if env_path.is_file() {
-                tracing::info!(path = ?env_path, ".env file found");
-                if let Err(err) = dotenvy::from_path(&env_path) {
-                    tracing::warn!(path = ?env_path, error = %err, "failed to load .env file");
-                }
-            }
+    /// Create a runner for the parsed arguments, e.g. from [`Migrate::parse`].
+    pub fn new(
+        migrate: Migrate,
+        migrations_dirs: impl MigrationsDirs,
+        migrations: impl IntoIterator<Item = Migration<Db>>,
+    ) -> Self {
+        Self {
+            migrate,
+            migrations_dirs: migrations_dirs.migrations_dirs(),
+            migrations: migrations.into_iter().collect(),
+            setup: None,
+            connect: None,
+        }
+    }
+
+    /// Create a runner for the matches of a subcommand
+    /// added with [`Migrate::augment_subcommand`].
+    ///
+    /// ```no_run
+    /// # fn migrations() -> Vec<sqlx_migrate::Migration<sqlx::Postgres>> { Vec::new() }
+    /// use sqlx_migrate::cli::{Migrate, Runner};
+    ///
+    /// let matches = Migrate::augment_subcommand(clap::Command::new("myapp")).get_matches();
+    ///
+    /// if let Some(("migrate", matches)) = matches.subcommand() {
+    ///     Runner::from_matches(matches, "migrations", migrations())
+    ///         .unwrap_or_else(|error| panic!("{error}"))
+    ///         .run();
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Invalid matches are returned as [`Error::Cli`].
+    pub fn from_matches(
+        matches: &clap::ArgMatches,
+        migrations_dirs: impl MigrationsDirs,
+        migrations: impl IntoIterator<Item = Migration<Db>>,
+    ) -> Result<Self, Error> {
+        let migrate =
+            Migrate::from_arg_matches(matches).map_err(|error| Error::Cli(error.to_string()))?;
+
+        Ok(Self::new(migrate, migrations_dirs, migrations))
+    }
+
+    /// Call `setup` with the migrator before any operation, e.g. to add
+    /// extensions that parameterized migrations use with [`Migrator::set`].
+    ///
+    /// It is called after the migrator is configured from the arguments,
+    /// so it can also override [`MigratorOptions`].
+    #[must_use]
+    pub fn setup(mut self, setup: impl FnOnce(&mut Migrator<Db>) + 'static) -> Self {
+        self.setup = Some(Box::new(setup));
+        self
+    }
+
+    /// Create the migrator with `connect` instead of connecting to `DATABASE_URL`,
+    /// for applications with their own connection logic, e.g. authentication tokens,
+    /// unix sockets or TLS certificates from a secret store.
+    ///
+    /// `connect` is only called by operations that use the database,
+    /// the migrator is configured from the arguments afterwards,
+    /// except for the statement logging of the connection.
+    ///
+    /// ```no_run
+    /// # fn migrations() -> Vec<sqlx_migrate::Migration<sqlx::Postgres>> { Vec::new() }
+    /// use clap::Parser;
+    /// use sqlx::postgres::PgConnectOptions;
+    /// use sqlx_migrate::{cli::{Migrate, Runner}, Migrator};
+    ///
+    /// Runner::new(Migrate::parse(), "migrations", migrations())
+    ///     .connect(|_migrate| {
+    ///         Box::pin(async move {
+    ///             let options = PgConnectOptions::new().socket("/var/run/postgresql");
+    ///             Ok(Migrator::connect_with(&options).await?)
+    ///         })
+    ///     })
+    ///     .run();
+    /// ```
+    #[must_use]
+    pub fn connect(
+        mut self,
+        connect: impl FnOnce(&Migrate) -> LocalBoxFuture<'static, Result<Migrator<Db>, Error>> + 'static,
+    ) -> Self {
+        self.connect = Some(Box::new(connect));
+        self
+    }
+
+    /// Run the operation and terminate the process if it fails.
+    ///
+    /// # Panics
+    ///
+    /// This functon assumes that it has control over the entire application.
+    ///
+    /// It will happily alter global state (tracing), panic, or terminate the process.
+    pub fn run(self) {
+        setup_logging(&self.migrate);
+
+        let ci = matches!(self.migrate.operation, Operation::Check { ci: true, .. });
+
+        if let Err(error) = self.try_run() {
+            process::exit(if ci { ci_exit_code(&error) } else { 1 });
         }
     }
 
-    let migrations = migrations.into_iter().collect::<Vec<_>>();
+    /// Run the operation on a new runtime and return its outcome instead of
+    /// terminating the process, so that the CLI can be embedded in the command
+    /// tree of an application, e.g. `myapp db migrate`.
+    ///
+    /// Errors are still logged, but unlike [`Runner::run`] this does
+    /// not set up logging, it is left to the application.
+    ///
+    /// # Errors
+    ///
+    /// The error of the operation is returned, errors that are not
+    /// caused by the migrator are returned as [`Error::Cli`],
+    /// e.g. if the runtime cannot be created.
+    pub fn try_run(self) -> Result<CliOutcome, Error> {
+        let mut runtime = match self.migrate.runtime {
+            Runtime::Current => tokio::runtime::Builder::new_current_thread(),
+            Runtime::Multi => tokio::runtime::Builder::new_multi_thread(),
+        };
+
+        let runtime = runtime
+            .enable_all()
+            .build()
+            .map_err(|error| Error::Cli(format!("failed to create the runtime: {error}")))?;
+
+        runtime.block_on(self.try_run_async())
+    }
+
+    /// Same as [`Runner::try_run`], but the operation runs on the runtime
+    /// of the caller, for applications that are already running in tokio
+    /// where a new runtime cannot be created.
+    ///
+    /// [`Migrate::runtime`] is ignored.
+    ///
+    /// # Errors
+    ///
+    /// The error of the operation is returned, see [`Runner::try_run`].
+    pub async fn try_run_async(self) -> Result<CliOutcome, Error> {
+        load_env_file(&self.migrate);
+
+        execute(
+            self.migrate,
+            &self.migrations_dirs,
+            self.migrations,
+            self.setup.unwrap_or_else(|| Box::new(|_| {})),
+            self.connect,
+        )
+        .await
+    }
+}
+
+fn load_env_file(migrate: &Migrate) {
+    if migrate.no_env_file {
+        return;
+    }
 
-    tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()
-        .unwrap()
-        .block_on(execute(migrate, migrations_path.as_ref(), migrations));
+    if let Ok(cwd) = std::env::current_dir() {
+        let env_path = cwd.join(".env");
+        if env_path.is_file() {
+            tracing::info!(path = ?env_path, ".env file found");
+            if let Err(err) = dotenvy::from_path(&env_path) {
+                tracing::warn!(path = ?env_path, error = %err, "failed to load .env file");
+            }
+        }
+    }
 }
 
-async fn execute<Db>(migrate: Migrate, migrations_path: &Path, migrations: Vec<Migration<Db>>)
+async fn execute<Db>(
+    migrate: Migrate,
+    migrations_dirs: &[PathBuf],
+    migrations: Vec<Migration<Db>>,
+    setup: impl FnOnce(&mut Migrator<Db>),
+    connect: Option<ConnectFn<Db>>,
+) -> Result<CliOutcome, Error>
 where
     Db: Database,
     Db::Connection: db::Migrations,
     for<'a> &'a mut Db::Connection: Executor<'a>,
 {
     match &migrate.operation {
-        Operation::Migrate { name, version } => {
-            let migrator = setup_migrator(&migrate, migrations).await;
-            do_migrate(&migrate, migrator, name.as_deref(), *version).await;
+        Operation::Migrate {
+            name,
+            version,
+            rehearse: true,
+            ..
+        } => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            rehearse_pending(migrator, name.as_deref(), *version).await
+        }
+        Operation::Migrate {
+            to_release: Some(release),
+            ..
+        } => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            migrate_outcome(migrations_dirs, migrator.migrate_to_release(release).await)
+        }
+        Operation::Migrate {
+            name,
+            version,
+            phase,
+            skip,
+            ..
+        } => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            do_migrate(
+                &migrate,
+                migrations_dirs,
+                migrator,
+                name.as_deref(),
+                *version,
+                *phase,
+                skip,
+            )
+            .await
         }
-        Operation::Revert { name, version } => {
-            let migrator = setup_migrator(&migrate, migrations).await;
-            revert(&migrate, migrator, name.as_deref(), *version).await;
+        Operation::Revert {
+            name,
+            version,
+            to_release,
+            ..
+        } => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            revert(
+                &migrate,
+                migrations_dirs,
+                migrator,
+                name.as_deref(),
+                *version,
+                to_release.as_deref(),
+            )
+            .await
         }
         Operation::Set { name, version } => {
-            let migrator = setup_migrator(&migrate, migrations).await;
-            force(&migrate, migrator, name.as_deref(), *version).await;
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            force(&migrate, migrator, name.as_deref(), *version).await
+        }
+        Operation::Check {
+            codegen: Some(generated_rs),
+            ..
+        } => check_codegen(migrations_dirs, generated_rs),
+        Operation::Check { ci, .. } => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            check(&migrate, migrator, *ci).await
+        }
+        Operation::Doctor {} => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            doctor(&migrate, migrator).await
+        }
+        Operation::Status { offline: true, .. } => {
+            let local = MigrationStatus::local(&migrations);
+            println!("{}", report::local_table(&local));
+            Ok(CliOutcome::Status(local))
+        }
+        Operation::Status { wide, .. } => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            log_status(&migrate, migrator, *wide).await
+        }
+        Operation::Mark {
+            version, applied, ..
+        } => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            mark(&migrate, migrator, *version, *applied).await
+        }
+        Operation::Repair {} => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            repair(&migrate, migrator).await
+        }
+        Operation::Prune {} => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            prune(&migrate, migrator).await
+        }
+        Operation::Drift { scratch_url } => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            drift(migrator, scratch_url).await
+        }
+        Operation::Rehearse {} => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            rehearse(migrator).await
         }
-        Operation::Check {} => {
-            let migrator = setup_migrator(&migrate, migrations).await;
-            check(&migrate, migrator).await;
+        Operation::Diff { other } => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            diff(migrator, other).await
         }
-        Operation::Status {} => {
-            let migrator = setup_migrator(&migrate, migrations).await;
-            log_status(&migrate, migrator).await;
+        Operation::Lint { allow, warn, deny } => {
+            let mut linter = load_lint_config(migrations_dirs)?;
+
+            for rule in allow {
+                linter = linter.rule(*rule, None);
+            }
+
+            for rule in warn {
+                linter = linter.rule(*rule, Some(Severity::Warning));
+            }
+
+            for rule in deny {
+                linter = linter.rule(*rule, Some(Severity::Error));
+            }
+
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            lint(migrator, &linter).await
+        }
+        Operation::Locks { .. } => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            locks(migrator).await
         }
+        Operation::History { operation } => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            history(&migrate, migrator, operation).await
+        }
+        Operation::Release { operation } => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            release(migrator, operation).await
+        }
+        Operation::Report { operation } => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            report(&migrate, migrator, operation).await
+        }
+        Operation::Docs { output } => docs(migrations_dirs, &migrations, output.as_deref()),
+        #[cfg(debug_assertions)]
+        Operation::Squash { through, name } => {
+            let migrator = setup_migrator(&migrate, migrations, setup, connect).await?;
+            squash(&migrate, migrations_dirs, migrator, *through, name).await
+        }
+        #[cfg(debug_assertions)]
+        Operation::Add {
+            auto_revert: true,
+            name,
+            ..
+        } => auto_revert(migrations_dirs, name),
         #[cfg(debug_assertions)]
         Operation::Add {
             sql,
             reversible,
             name,
             ty,
-        } => add(&migrate, migrations_path, *sql, *reversible, name, *ty),
+            dialects,
+            from_template,
+            auto_revert: false,
+            dir,
+        } => add(
+            &migrate,
+            dir.as_deref()
+                .or_else(|| migrations_dirs.first().map(PathBuf::as_path))
+                .expect("at least one migrations directory is required"),
+            *sql,
+            *reversible,
+            name,
+            *ty,
+            dialects,
+            *from_template,
+        ),
     }
 }
 
-async fn check<Db>(_migrate: &Migrate, migrator: Migrator<Db>)
+async fn check<Db>(
+    _migrate: &Migrate,
+    migrator: Migrator<Db>,
+    ci: bool,
+) -> Result<CliOutcome, Error>
 where
     Db: Database,
     Db::Connection: db::Migrations,
     for<'a> &'a mut Db::Connection: Executor<'a>,
 {
-    match migrator.verify().await {
-        Ok(_) => {
+    let res = if ci {
+        migrator.check_strict().await
+    } else {
+        migrator.verify().await
+    };
+
+    match res {
+        Ok(()) => {
             tracing::info!("No issues found");
         }
+        Err(err @ Error::MigrationsTableInaccessible { .. }) if !ci => {
+            tracing::warn!(
+                error = %err,
+                "bookkeeping table inaccessible, only the local migrations were verified"
+            );
+        }
         Err(err) => {
             tracing::error!(error = %err, "error verifying migrations");
-            process::exit(1);
+            return Err(err);
         }
     }
+
+    Ok(CliOutcome::Done)
 }
 
-#[cfg(debug_assertions)]
-fn add(
-    _migrate: &Migrate,
-    migrations_path: &Path,
-    sql: bool,
-    reversible: bool,
-    name: &str,
-    ty: DatabaseType,
-) {
-    let now = OffsetDateTime::now_utc();
+/// Compare the generated code with the migrations directories.
+fn check_codegen(migrations_dirs: &[PathBuf], generated_rs: &Path) -> Result<CliOutcome, Error> {
+    let stale = match crate::validate_generated(migrations_dirs, generated_rs) {
+        Ok(stale) => stale,
+        Err(err) => {
+            tracing::error!(error = %err, path = %generated_rs.display(), "failed to read generated code");
+            return Err(Error::Cli(format!("failed to read generated code: {err}")));
+        }
+    };
 
-    let now_formatted = now
-        .format(&format_description::parse("[year][month][day][hour][minute][second]").unwrap())
-        .unwrap();
+    if stale.is_empty() {
+        tracing::info!("Generated code is up to date");
+        return Ok(CliOutcome::Done);
+    }
 
-    if !migrations_path.is_dir() {
-        tracing::error!("migrations path must be a directory");
-        process::exit(1);
+    for path in &stale.missing_files {
+        tracing::error!(path = %path.display(), "generated code references a missing file");
     }
 
-    let re = Regex::new("[A-Za-z_][A-Za-z_0-9]*").unwrap();
+    for path in &stale.ungenerated_files {
+        tracing::error!(path = %path.display(), "migration file is not part of the generated code");
+    }
 
-    if !re.is_match(name) {
-        tracing::error!(name, "invalid migration name");
-        process::exit(1);
+    tracing::error!(path = %generated_rs.display(), "{stale}");
+
+    Err(Error::Cli(stale.to_string()))
+}
+
+/// The exit code of `check --ci` for the given error.
+fn ci_exit_code(error: &Error) -> i32 {
+    match error {
+        Error::PendingMigrations { .. } => 2,
+        Error::MissingMigrations { .. } | Error::NewerDatabase { .. } => 3,
+        Error::NameMismatch { .. } => 4,
+        Error::ChecksumMismatch { .. } => 5,
+        _ => 1,
     }
+}
 
-    if sql {
-        let up_filename = format!("{}_{}.migrate.sql", &now_formatted, name);
+async fn doctor<Db>(_migrate: &Migrate, mut migrator: Migrator<Db>) -> Result<CliOutcome, Error>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    struct Finding {
+        problem: String,
+        cause: String,
+        fix: String,
+    }
 
-        if let Err(error) = fs::write(
-            migrations_path.join(&up_filename),
-            format!(
-                r#"-- Migration SQL for {name}
-"#,
-            ),
-        ) {
-            tracing::error!(error = %error, path = ?migrations_path.join(&up_filename), "failed to write file");
-            process::exit(1);
-        }
+    let mut problems = Vec::new();
 
-        if reversible {
-            let down_filename = format!("{}_{}.revert.sql", &now_formatted, name);
-            if let Err(error) = fs::write(
-                migrations_path.join(&down_filename),
-                format!(
-                    r#"-- Revert SQL for {name}
-"#,
-                ),
-            ) {
-                tracing::error!(error = %error, path = ?migrations_path.join(&down_filename), "failed to write file");
-                process::exit(1);
-            }
+    let missing_privileges = match migrator.preflight().await {
+        Ok(missing) => missing,
+        Err(error) => {
+            tracing::error!(error = %error, "error checking privileges");
+            return Err(error);
         }
+    };
 
-        tracing::info!(name, "added migration");
-    } else {
-        let up_filename = format!("{}_{}.migrate.rs", &now_formatted, name);
-
-        let sqlx_type = ty.sqlx_type();
+    for missing in missing_privileges {
+        problems.push(Finding {
+            problem: format!("missing privilege: {missing}"),
+            cause: "the migrations are run by a user without the privilege".into(),
+            fix: missing.grant.map_or_else(
+                || "run the migrations with a user that can write the database".into(),
+                |grant| format!("run `{grant}`"),
+            ),
+        });
+    }
+
+    let status = match migrator.status().await {
+        Ok(s) => s,
+        // The missing privileges are reported instead.
+        Err(Error::MigrationsTableInaccessible { .. }) if !problems.is_empty() => Vec::new(),
+        Err(Error::Database(error)) if !problems.is_empty() && db::is_permission_denied(&error) => {
+            Vec::new()
+        }
+        Err(error) => {
+            tracing::error!(error = %error, "error retrieving migration status");
+            return Err(error);
+        }
+    };
+
+    // The latest applied version that can be recorded again with `set`.
+    let applied_version = status
+        .iter()
+        .filter(|mig| mig.applied.is_some() && !mig.missing_local)
+        .map(|mig| mig.version)
+        .max()
+        .unwrap_or(0);
+
+    let missing: Vec<u64> = status
+        .iter()
+        .filter(|mig| mig.missing_local)
+        .map(|mig| mig.version)
+        .collect();
+
+    if let (Some(first), Some(last)) = (missing.first(), missing.last()) {
+        let versions = if first == last {
+            format!("version {first}")
+        } else {
+            format!("versions {first}-{last}")
+        };
+
+        problems.push(Finding {
+            problem: format!("applied migrations at {versions} are missing locally"),
+            cause: "the migrations were removed, or the database was migrated by a newer release"
+                .into(),
+            fix: "restore the migrations or upgrade to the newer release, \
+                  or run `prune --force` if they were removed intentionally"
+                .into(),
+        });
+    }
+
+    for mig in &status {
+        let Some(applied) = &mig.applied else {
+            continue;
+        };
+
+        if mig.missing_local {
+            continue;
+        }
+
+        if !mig.name_ok {
+            problems.push(Finding {
+                problem: format!(
+                    "name mismatch at version {}: applied as \"{}\", found \"{}\"",
+                    mig.version, applied.name, mig.name
+                ),
+                cause: "the migration was renamed, or migrations were reordered".into(),
+                fix: format!(
+                    "restore the order of migrations, or if the rename was intentional \
+                     add \"{}\" as an alias of the migration and run `repair`",
+                    applied.name
+                ),
+            });
+        }
+
+        if !mig.checksum_ok {
+            problems.push(Finding {
+                problem: format!(
+                    "checksum mismatch at version {} ({})",
+                    mig.version, mig.name
+                ),
+                cause: "the migration was modified after it was applied".into(),
+                fix: format!(
+                    "undo the changes, or run `set --version {applied_version} --force` \
+                     if the changes were intentional"
+                ),
+            });
+        }
+    }
+
+    let pending = status.iter().filter(|mig| mig.applied.is_none()).count();
+
+    for problem in &problems {
+        tracing::error!(
+            cause = %problem.cause,
+            fix = %problem.fix,
+            "{}",
+            problem.problem
+        );
+    }
+
+    if pending > 0 {
+        tracing::info!(
+            pending,
+            fix = "run `migrate` to apply them",
+            "there are pending migrations"
+        );
+    }
+
+    if problems.is_empty() {
+        tracing::info!("No issues found");
+    } else {
+        return Err(Error::Cli(format!("{} problems found", problems.len())));
+    }
+
+    Ok(CliOutcome::Done)
+}
+
+#[cfg(debug_assertions)]
+#[allow(clippy::too_many_arguments)]
+fn add(
+    _migrate: &Migrate,
+    migrations_path: &Path,
+    sql: bool,
+    reversible: bool,
+    name: &str,
+    ty: DatabaseType,
+    dialects: &[DatabaseType],
+    scaffold: Option<Scaffold>,
+) -> Result<CliOutcome, Error> {
+    let now_formatted = file_timestamp(SystemTime::now());
+
+    if !migrations_path.is_dir() {
+        tracing::error!("migrations path must be a directory");
+        return Err(Error::Cli("migrations path must be a directory".into()));
+    }
+
+    let re = Regex::new("[A-Za-z_][A-Za-z_0-9]*").unwrap();
+
+    if !re.is_match(name) {
+        tracing::error!(name, "invalid migration name");
+        return Err(Error::Cli("invalid migration name".into()));
+    }
+
+    match MigrateIgnore::load(migrations_path) {
+        Ok(ignore) => {
+            let up_path = migrations_path.join(format!(
+                "{now_formatted}_{name}.migrate.{}",
+                if sql { "sql" } else { "rs" }
+            ));
+
+            if ignore.is_ignored(&up_path) {
+                tracing::warn!(path = ?up_path, "the new migration is ignored by {IGNORE_FILE}");
+            }
+        }
+        Err(error) => {
+            tracing::warn!(error = %error, "invalid {IGNORE_FILE} file");
+        }
+    }
+
+    if sql {
+        let mut suffixes = Vec::with_capacity(dialects.len().max(1));
+
+        for dialect in dialects {
+            let Some(tag) = dialect.dialect_tag() else {
+                tracing::error!(database = ?dialect, "SQL cannot be specific to the database");
+                return Err(Error::Cli("SQL cannot be specific to the database".into()));
+            };
+
+            let suffix = format!(".{tag}");
+            if !suffixes.contains(&suffix) {
+                suffixes.push(suffix);
+            }
+        }
+
+        if suffixes.is_empty() {
+            suffixes.push(String::new());
+        }
+
+        for suffix in &suffixes {
+            let up_filename = format!("{}_{}.migrate{suffix}.sql", &now_formatted, name);
+
+            if let Err(error) = fs::write(
+                migrations_path.join(&up_filename),
+                format!(
+                    r#"-- Migration SQL for {name}
+"#,
+                ),
+            ) {
+                tracing::error!(error = %error, path = ?migrations_path.join(&up_filename), "failed to write file");
+                return Err(Error::Cli(format!(
+                    "failed to write file {}: {error}",
+                    migrations_path.join(&up_filename).display()
+                )));
+            }
+
+            if reversible {
+                let down_filename = format!("{}_{}.revert{suffix}.sql", &now_formatted, name);
+                if let Err(error) = fs::write(
+                    migrations_path.join(&down_filename),
+                    format!(
+                        r#"-- Revert SQL for {name}
+"#,
+                    ),
+                ) {
+                    tracing::error!(error = %error, path = ?migrations_path.join(&down_filename), "failed to write file");
+                    return Err(Error::Cli(format!(
+                        "failed to write file {}: {error}",
+                        migrations_path.join(&down_filename).display()
+                    )));
+                }
+            }
+        }
+
+        tracing::info!(name, "added migration");
+    } else {
+        let up_filename = format!("{}_{}.migrate.rs", &now_formatted, name);
+
+        let sqlx_type = ty.sqlx_type();
+
+        // Indexes are only created after commit, without executing anything.
+        let imports = if matches!(scaffold, Some(Scaffold::CreateTable | Scaffold::AddColumn)) {
+            format!("{{Executor, {sqlx_type}}}")
+        } else {
+            sqlx_type.to_string()
+        };
+
+        let up_body = scaffold.map_or(
+            "    // write your migration operations here\n    todo!()",
+            Scaffold::up_body,
+        );
 
         if let Err(error) = fs::write(
             migrations_path.join(&up_filename),
             format!(
-                r#"use sqlx::{sqlx_type};
+                r#"use sqlx::{imports};
 use sqlx_migrate::prelude::*;
 
 /// Executes migration `{name}` in the given migration context.
@@ -318,23 +1369,30 @@ use sqlx_migrate::prelude::*;
 // Do not modify the function name.
 // Do not modify the signature with the exception of the SQLx database type.
 pub async fn {name}(ctx: &mut MigrationContext<{sqlx_type}>) -> Result<(), MigrationError> {{
-    // write your migration operations here
-    todo!()
+{up_body}
 }}
 "#,
             ),
         ) {
             tracing::error!(error = %error, path = ?migrations_path.join(&up_filename), "failed to write file");
-            process::exit(1);
+            return Err(Error::Cli(format!(
+                "failed to write file {}: {error}",
+                migrations_path.join(&up_filename).display()
+            )));
         }
 
         if reversible {
             let down_filename = format!("{}_{}.revert.rs", &now_formatted, name);
 
+            let down_body = scaffold.map_or(
+                "    // write your revert operations here\n    todo!()",
+                Scaffold::down_body,
+            );
+
             if let Err(error) = fs::write(
                 migrations_path.join(&down_filename),
                 format!(
-                    r#"use sqlx::{sqlx_type};
+                    r#"use sqlx::{imports};
 use sqlx_migrate::prelude::*;
 
 /// Reverts migration `{name}` in the given migration context.
@@ -342,289 +1400,1088 @@ use sqlx_migrate::prelude::*;
 // Do not modify the function name.
 // Do not modify the signature with the exception of the SQLx database type.
 pub async fn revert_{name}(ctx: &mut MigrationContext<{sqlx_type}>) -> Result<(), MigrationError> {{
-    // write your revert operations here
-    todo!()
+{down_body}
 }}
 "#,
                 ),
             ) {
                 tracing::error!(error = %error, path = ?migrations_path.join(&down_filename), "failed to write file");
-                process::exit(1);
+                return Err(Error::Cli(format!(
+                    "failed to write file {}: {error}",
+                    migrations_path.join(&down_filename).display()
+                )));
             }
         }
     }
 
-    if let Err(err) = filetime::set_file_mtime(migrations_path, FileTime::now()) {
-        tracing::debug!(error = %err, "error updating the migrations directory");
-    }
+    touch_migrations_dir(migrations_path);
+
+    Ok(CliOutcome::Done)
 }
 
-async fn do_migrate<Db>(
-    _migrate: &Migrate,
-    migrator: Migrator<Db>,
-    name: Option<&str>,
-    version: Option<u64>,
-) where
-    Db: Database,
-    Db::Connection: db::Migrations,
-    for<'a> &'a mut Db::Connection: Executor<'a>,
-{
-    let version = match version {
-        Some(v) => Some(v),
-        None => match name {
-            Some(name) => {
-                if let Some((idx, _)) = migrator
-                    .local_migrations()
-                    .iter()
-                    .enumerate()
-                    .find(|mig| mig.1.name() == name)
-                {
-                    Some(idx as u64 + 1)
-                } else {
-                    tracing::error!(name = name, "migration not found");
-                    process::exit(1);
-                }
-            }
-            None => None,
-        },
+#[cfg(debug_assertions)]
+fn auto_revert(migrations_dirs: &[PathBuf], name: &str) -> Result<CliOutcome, Error> {
+    let files = match migration_files(migrations_dirs) {
+        Ok(files) => files,
+        Err(error) => {
+            tracing::error!(error = %error, "failed to read migrations");
+            return Err(Error::Cli(format!("failed to read migrations: {error}")));
+        }
     };
 
-    match version {
-        Some(version) => match migrator.migrate(version).await {
-            Ok(s) => print_summary(&s),
-            Err(error) => {
-                tracing::error!(error = %error, "error applying migrations");
-                process::exit(1);
-            }
-        },
-        None => match migrator.migrate_all().await {
-            Ok(s) => print_summary(&s),
-            Err(error) => {
-                tracing::error!(error = %error, "error applying migrations");
-                process::exit(1);
-            }
-        },
-    }
-}
+    let Some(migration) = files.into_iter().find(|f| f.name == name) else {
+        tracing::error!(name, "migration not found");
+        return Err(Error::MigrationNotFound {
+            name: name.to_string(),
+        });
+    };
 
-async fn revert<Db>(
-    migrate: &Migrate,
-    migrator: Migrator<Db>,
-    name: Option<&str>,
-    version: Option<u64>,
-) where
-    Db: Database,
-    Db::Connection: db::Migrations,
-    for<'a> &'a mut Db::Connection: Executor<'a>,
-{
-    if !migrate.force {
-        tracing::error!("the `--force` flag is required for this operation");
-        process::exit(1);
+    let up_paths = migration
+        .paths
+        .iter()
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == "sql")
+                && path
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .contains(".migrate.")
+        })
+        .collect::<Vec<_>>();
+
+    if up_paths.is_empty() {
+        tracing::error!(name, "only SQL migrations can be reverted automatically");
+        return Err(Error::Cli(
+            "only SQL migrations can be reverted automatically".into(),
+        ));
     }
 
-    let version = match version {
-        Some(v) => Some(v),
-        None => match name {
-            Some(name) => {
-                if let Some((idx, _)) = migrator
-                    .local_migrations()
-                    .iter()
-                    .enumerate()
-                    .find(|mig| mig.1.name() == name)
-                {
-                    Some(idx as u64 + 1)
-                } else {
-                    tracing::error!(name = name, "migration not found");
-                    process::exit(1);
-                }
-            }
-            None => None,
-        },
-    };
+    for up_path in up_paths {
+        let file_name = up_path.file_name().unwrap().to_string_lossy();
+        let down_path = up_path.with_file_name(file_name.replacen(".migrate.", ".revert.", 1));
 
-    match version {
-        Some(version) => match migrator.revert(version).await {
-            Ok(s) => print_summary(&s),
-            Err(error) => {
-                tracing::error!(error = %error, "error reverting migrations");
-                process::exit(1);
-            }
-        },
-        None => match migrator.revert_all().await {
-            Ok(s) => print_summary(&s),
+        if down_path.exists() {
+            tracing::error!(path = ?down_path, "the revert migration already exists");
+            return Err(Error::Cli("the revert migration already exists".into()));
+        }
+
+        let up_sql = match fs::read_to_string(up_path) {
+            Ok(sql) => sql,
             Err(error) => {
-                tracing::error!(error = %error, "error reverting migrations");
-                process::exit(1);
+                tracing::error!(error = %error, path = ?up_path, "failed to read file");
+                return Err(Error::Cli(format!(
+                    "failed to read file {}: {error}",
+                    up_path.display()
+                )));
             }
-        },
+        };
+
+        let revert_sql = crate::sql::revert_sql(&up_sql);
+
+        if revert_sql.contains("-- TODO") {
+            tracing::warn!(path = ?down_path, "some statements must be reverted manually");
+        }
+
+        if let Err(error) = fs::write(
+            &down_path,
+            format!("-- Revert SQL for {name}\n\n{revert_sql}"),
+        ) {
+            tracing::error!(error = %error, path = ?down_path, "failed to write file");
+            return Err(Error::Cli(format!(
+                "failed to write file {}: {error}",
+                down_path.display()
+            )));
+        }
     }
+
+    tracing::info!(name, "added revert migration");
+
+    touch_migrations_dir(&migration.dir);
+
+    Ok(CliOutcome::Done)
 }
 
-async fn force<Db>(
-    migrate: &Migrate,
-    migrator: Migrator<Db>,
-    name: Option<&str>,
-    version: Option<u64>,
-) where
+async fn repair<Db>(_migrate: &Migrate, migrator: Migrator<Db>) -> Result<CliOutcome, Error>
+where
     Db: Database,
     Db::Connection: db::Migrations,
     for<'a> &'a mut Db::Connection: Executor<'a>,
 {
-    if !migrate.force {
-        tracing::error!("the `--do-as-i-say` or `--force` flag is required for this operation");
-        process::exit(1);
-    }
-
-    let version = match version {
-        Some(v) => v,
-        None => {
-            if let Some((idx, _)) = migrator
-                .local_migrations()
-                .iter()
-                .enumerate()
-                .find(|mig| mig.1.name() == name.unwrap())
-            {
-                idx as u64 + 1
+    match migrator.repair().await {
+        Ok(summary) => {
+            if summary.renamed.is_empty() {
+                tracing::info!("nothing to repair");
             } else {
-                tracing::error!(name = name.unwrap(), "migration not found");
-                process::exit(1);
+                tracing::info!(renamed = summary.renamed.len(), "migrations repaired");
             }
         }
-    };
-
-    match migrator.force_version(version).await {
-        Ok(s) => print_summary(&s),
         Err(error) => {
-            tracing::error!(error = %error, "error updating migrations");
-            process::exit(1);
+            tracing::error!(error = %error, "error repairing migrations");
+            return Err(error);
         }
     }
+
+    Ok(CliOutcome::Done)
 }
 
-async fn log_status<Db>(_migrate: &Migrate, migrator: Migrator<Db>)
+async fn prune<Db>(migrate: &Migrate, migrator: Migrator<Db>) -> Result<CliOutcome, Error>
 where
     Db: Database,
     Db::Connection: db::Migrations,
     for<'a> &'a mut Db::Connection: Executor<'a>,
 {
-    fn mig_ok(status: &MigrationStatus) -> bool {
-        if status.missing_local {
-            return false;
-        }
-
-        match &status.applied {
-            Some(applied) => {
-                status.checksum_ok
-                    && status.name == applied.name
-                    && status.version == applied.version
+    let orphaned: Vec<db::AppliedMigration<'static>> = if migrate.force {
+        match migrator.prune().await {
+            Ok(pruned) => pruned,
+            Err(error) => {
+                tracing::error!(error = %error, "error pruning migrations");
+                return Err(error);
             }
-            None => true,
         }
-    }
-
-    let status = match migrator.status().await {
-        Ok(s) => s,
-        Err(error) => {
-            tracing::error!(error = %error, "error retrieving migration status");
-            process::exit(1);
+    } else {
+        match migrator.status().await {
+            Ok(status) => status
+                .into_iter()
+                .filter(|mig| mig.missing_local)
+                .filter_map(|mig| mig.applied)
+                .collect(),
+            Err(error) => {
+                tracing::error!(error = %error, "error retrieving migration status");
+                return Err(error);
+            }
         }
     };
 
-    let all_valid = status.iter().all(mig_ok);
+    if orphaned.is_empty() {
+        tracing::info!("no migrations to prune");
+        return Ok(CliOutcome::Done);
+    }
 
     let mut table = Table::new();
 
     table
         .set_content_arrangement(ContentArrangement::Dynamic)
         .set_header(Vec::from([
+            Cell::new("").set_alignment(CellAlignment::Center),
             Cell::new("Version").set_alignment(CellAlignment::Center),
             Cell::new("Name").set_alignment(CellAlignment::Center),
-            Cell::new("Applied").set_alignment(CellAlignment::Center),
-            Cell::new("Valid").set_alignment(CellAlignment::Center),
-            Cell::new("Revertible").set_alignment(CellAlignment::Center),
         ]));
 
-    for mig in status {
-        let ok = mig_ok(&mig);
-
+    for mig in &orphaned {
         table.add_row(Vec::from([
-            Cell::new(mig.version.to_string().as_str()).set_alignment(CellAlignment::Center),
+            Cell::new("-").set_alignment(CellAlignment::Center),
+            Cell::new(mig.version.to_string()).set_alignment(CellAlignment::Center),
             Cell::new(&mig.name).set_alignment(CellAlignment::Center),
-            Cell::new(if mig.applied.is_some() { "x" } else { "" })
-                .set_alignment(CellAlignment::Center),
-            Cell::new(if ok { "x" } else { "INVALID" }).set_alignment(CellAlignment::Center),
-            Cell::new(if mig.reversible { "x" } else { "" }).set_alignment(CellAlignment::Center),
         ]));
     }
 
-    println!("{}", table);
+    eprintln!("{table}");
 
-    if !all_valid {
-        process::exit(1);
+    if !migrate.force {
+        tracing::warn!("the listed migrations will be removed, use the `--force` flag to confirm");
+        return Err(Error::Cli("the `--force` flag is required".into()));
     }
+
+    Ok(CliOutcome::Done)
 }
 
-fn print_summary(summary: &MigrationSummary) {
+async fn drift<Db>(migrator: Migrator<Db>, scratch_url: &str) -> Result<CliOutcome, Error>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    let options =
+        match scratch_url.parse::<<<Db as Database>::Connection as sqlx::Connection>::Options>() {
+            Ok(opts) => opts.disable_statement_logging(),
+            Err(err) => {
+                tracing::error!(error = %err, "invalid scratch database URL");
+                return Err(Error::Database(err));
+            }
+        };
+
+    let scratch = match <Db::Connection as sqlx::Connection>::connect_with(&options).await {
+        Ok(conn) => conn,
+        Err(err) => {
+            tracing::error!(error = %err, "failed to create scratch database connection");
+            return Err(Error::Database(err));
+        }
+    };
+
+    let drift = match migrator.drift(scratch).await {
+        Ok(drift) => drift,
+        Err(error) => {
+            tracing::error!(error = %error, "error detecting schema drift");
+            return Err(error);
+        }
+    };
+
+    if drift.is_empty() {
+        tracing::info!("no schema drift detected");
+        return Ok(CliOutcome::Done);
+    }
+
     let mut table = Table::new();
 
     table
         .set_content_arrangement(ContentArrangement::Dynamic)
         .set_header(Vec::from([
-            Cell::new("Old Version").set_alignment(CellAlignment::Center),
-            Cell::new("New Version").set_alignment(CellAlignment::Center),
-            Cell::new("Applied Migrations").set_alignment(CellAlignment::Center),
-            Cell::new("Reverted Migrations").set_alignment(CellAlignment::Center),
+            Cell::new("").set_alignment(CellAlignment::Center),
+            Cell::new("Statement").set_alignment(CellAlignment::Center),
         ]));
 
-    let mut s = Vec::<Cell>::new();
-
-    s.push(match summary.old_version {
-        Some(v) => Cell::new(v.to_string()).set_alignment(CellAlignment::Center),
-        None => "".into(),
-    });
-
-    s.push(match summary.new_version {
-        Some(v) => Cell::new(v.to_string()).set_alignment(CellAlignment::Center),
-        None => "".into(),
-    });
+    for d in &drift {
+        let (marker, statement) = match d {
+            SchemaDrift::Missing(statement) => ("-", statement),
+            SchemaDrift::Unexpected(statement) => ("+", statement),
+        };
 
-    s.push(match (summary.old_version, summary.new_version) {
-        (Some(old), Some(new)) => {
-            if new >= old {
-                Cell::new((new - old).to_string()).set_alignment(CellAlignment::Center)
-            } else {
-                Cell::new("0").set_alignment(CellAlignment::Center)
-            }
-        }
-        (None, Some(new)) => Cell::new(new.to_string()).set_alignment(CellAlignment::Center),
-        (_, None) => Cell::new("0").set_alignment(CellAlignment::Center),
-    });
+        table.add_row(Vec::from([
+            Cell::new(marker).set_alignment(CellAlignment::Center),
+            Cell::new(statement),
+        ]));
+    }
 
-    s.push(match (summary.old_version, summary.new_version) {
-        (Some(old), Some(new)) => {
-            if new <= old {
-                Cell::new((old - new).to_string()).set_alignment(CellAlignment::Center)
-            } else {
-                Cell::new("0").set_alignment(CellAlignment::Center)
-            }
-        }
-        (Some(old), None) => Cell::new(old.to_string()).set_alignment(CellAlignment::Center),
-        (None, _) => Cell::new("0").set_alignment(CellAlignment::Center),
-    });
+    eprintln!("{table}");
 
-    table.add_row(s);
+    tracing::error!(
+        "schema drift detected, objects marked with `-` are missing or altered, \
+        objects marked with `+` are not created by migrations"
+    );
+    Err(Error::Cli("schema drift detected".into()))
+}
 
-    eprintln!("{table}");
+async fn rehearse<Db>(migrator: Migrator<Db>) -> Result<CliOutcome, Error>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    rehearsal_outcome(migrator.rehearse().await)
 }
 
-async fn setup_migrator<Db>(migrate: &Migrate, migrations: Vec<Migration<Db>>) -> Migrator<Db>
+async fn rehearse_pending<Db>(
+    migrator: Migrator<Db>,
+    name: Option<&str>,
+    version: Option<u64>,
+) -> Result<CliOutcome, Error>
 where
     Db: Database,
     Db::Connection: db::Migrations,
     for<'a> &'a mut Db::Connection: Executor<'a>,
 {
-    let db_url = match &migrate.database_url {
-        Some(s) => s.clone(),
+    let version = match (version, name) {
+        (Some(version), _) => version,
+        (None, Some(name)) => {
+            if let Some(version) = migrator.migration_version(name) {
+                version
+            } else {
+                tracing::error!(name = name, "migration not found");
+                return Err(Error::MigrationNotFound {
+                    name: name.to_string(),
+                });
+            }
+        }
+        (None, None) => migrator.local_migrations().len() as u64,
+    };
+
+    rehearsal_outcome(migrator.rehearse_pending(version).await)
+}
+
+fn rehearsal_outcome(res: Result<Rehearsal, Error>) -> Result<CliOutcome, Error> {
+    let rehearsal = match res {
+        Ok(rehearsal) => rehearsal,
+        Err(error) => {
+            tracing::error!(error = %error, "error rehearsing migrations");
+            return Err(error);
+        }
+    };
+
+    eprintln!("{}", report::rehearsal_table(&rehearsal));
+
+    if let Some(error) = rehearsal.error {
+        tracing::error!(error = %error, "migration failed in the rehearsal");
+        return Err(error);
+    }
+
+    tracing::info!(
+        migrations = rehearsal.applied.len(),
+        "all migrations were rehearsed successfully"
+    );
+    Ok(CliOutcome::Done)
+}
+
+async fn diff<Db>(migrator: Migrator<Db>, other_url: &str) -> Result<CliOutcome, Error>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    let options =
+        match other_url.parse::<<<Db as Database>::Connection as sqlx::Connection>::Options>() {
+            Ok(opts) => opts.disable_statement_logging(),
+            Err(err) => {
+                tracing::error!(error = %err, "invalid other database URL");
+                return Err(Error::Database(err));
+            }
+        };
+
+    let other = match <Db::Connection as sqlx::Connection>::connect_with(&options).await {
+        Ok(conn) => conn,
+        Err(err) => {
+            tracing::error!(error = %err, "failed to create other database connection");
+            return Err(Error::Database(err));
+        }
+    };
+
+    let diff = match migrator.diff(other).await {
+        Ok(diff) => diff,
+        Err(error) => {
+            tracing::error!(error = %error, "error comparing migration histories");
+            return Err(error);
+        }
+    };
+
+    if diff.is_empty() {
+        tracing::info!("the migration histories are identical");
+        return Ok(CliOutcome::Done);
+    }
+
+    let mut table = Table::new();
+
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(Vec::from([
+            Cell::new("Version").set_alignment(CellAlignment::Center),
+            Cell::new("Difference").set_alignment(CellAlignment::Center),
+            Cell::new("This Database").set_alignment(CellAlignment::Center),
+            Cell::new("Other Database").set_alignment(CellAlignment::Center),
+        ]));
+
+    let short_checksum = |checksum: &[u8]| encode_hex(checksum.get(..8).unwrap_or(checksum));
+
+    for d in &diff {
+        let (difference, this, other) = match d {
+            HistoryDiff::OnlyThis(mig) => ("applied", mig.name.to_string(), String::new()),
+            HistoryDiff::OnlyOther(mig) => ("applied", String::new(), mig.name.to_string()),
+            HistoryDiff::NameMismatch {
+                name, other_name, ..
+            } => ("name", name.to_string(), other_name.to_string()),
+            HistoryDiff::ChecksumMismatch {
+                name,
+                checksum,
+                other_checksum,
+                ..
+            } => (
+                "checksum",
+                format!("{name} ({})", short_checksum(checksum)),
+                format!("{name} ({})", short_checksum(other_checksum)),
+            ),
+        };
+
+        table.add_row(Vec::from([
+            Cell::new(d.version().to_string().as_str()).set_alignment(CellAlignment::Center),
+            Cell::new(difference).set_alignment(CellAlignment::Center),
+            Cell::new(this).set_alignment(CellAlignment::Center),
+            Cell::new(other).set_alignment(CellAlignment::Center),
+        ]));
+    }
+
+    eprintln!("{table}");
+
+    tracing::error!("the migration histories differ");
+    Err(Error::Cli("the migration histories differ".into()))
+}
+
+async fn release<Db>(
+    migrator: Migrator<Db>,
+    operation: &ReleaseOperation,
+) -> Result<CliOutcome, Error>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    match operation {
+        ReleaseOperation::Tag { name, version } => {
+            let res = match version {
+                Some(version) => migrator.tag_release_at(name, *version).await,
+                None => migrator.tag_release(name).await,
+            };
+
+            if let Err(error) = res {
+                tracing::error!(error = %error, "error tagging release");
+                return Err(error);
+            }
+        }
+        ReleaseOperation::List {} => {
+            let releases = match migrator.releases().await {
+                Ok(releases) => releases,
+                Err(error) => {
+                    tracing::error!(error = %error, "error listing releases");
+                    return Err(error);
+                }
+            };
+
+            if releases.is_empty() {
+                tracing::info!("no releases are tagged");
+                return Ok(CliOutcome::Done);
+            }
+
+            let mut table = Table::new();
+
+            table
+                .set_content_arrangement(ContentArrangement::Dynamic)
+                .set_header(Vec::from([
+                    Cell::new("Release").set_alignment(CellAlignment::Center),
+                    Cell::new("Version").set_alignment(CellAlignment::Center),
+                    Cell::new("Tagged At").set_alignment(CellAlignment::Center),
+                ]));
+
+            for release in &releases {
+                table.add_row(Vec::from([
+                    Cell::new(&release.name).set_alignment(CellAlignment::Center),
+                    Cell::new(release.version.to_string()).set_alignment(CellAlignment::Center),
+                    Cell::new(release.tagged_on.map_or_else(String::new, |tagged_on| {
+                        humantime::format_rfc3339_seconds(tagged_on).to_string()
+                    }))
+                    .set_alignment(CellAlignment::Center),
+                ]));
+            }
+
+            println!("{table}");
+        }
+    }
+
+    Ok(CliOutcome::Done)
+}
+
+async fn report<Db>(
+    migrate: &Migrate,
+    migrator: Migrator<Db>,
+    operation: &ReportOperation,
+) -> Result<CliOutcome, Error>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    match operation {
+        ReportOperation::Timings { other } => {
+            let mut databases = Vec::with_capacity(other.len() + 1);
+
+            match migrator.runs().await {
+                Ok(runs) => databases.push((String::from("this"), runs)),
+                Err(error) => {
+                    tracing::error!(error = %error, "error listing migration runs");
+                    return Err(error);
+                }
+            }
+
+            for other_url in other {
+                let options = match other_url
+                    .parse::<<<Db as Database>::Connection as sqlx::Connection>::Options>(
+                ) {
+                    Ok(opts) => opts.disable_statement_logging(),
+                    Err(err) => {
+                        tracing::error!(error = %err, "invalid other database URL");
+                        return Err(Error::Database(err));
+                    }
+                };
+
+                let conn = match <Db::Connection as sqlx::Connection>::connect_with(&options).await
+                {
+                    Ok(conn) => conn,
+                    Err(err) => {
+                        tracing::error!(error = %err, "failed to create other database connection");
+                        return Err(Error::Database(err));
+                    }
+                };
+
+                let mut other_migrator = Migrator::<Db>::new(conn);
+
+                if !migrate.migrations_table.is_empty() {
+                    other_migrator.set_migrations_table(&migrate.migrations_table);
+                }
+
+                match other_migrator.runs().await {
+                    Ok(runs) => databases.push((database_label(other_url), runs)),
+                    Err(error) => {
+                        tracing::error!(error = %error, "error listing migration runs");
+                        return Err(error);
+                    }
+                }
+            }
+
+            if databases.iter().all(|(_, runs)| runs.is_empty()) {
+                tracing::warn!("no migration runs are recorded, see `--record-runs`");
+                return Ok(CliOutcome::Done);
+            }
+
+            println!("{}", report::timings_table(&databases));
+
+            Ok(CliOutcome::Done)
+        }
+    }
+}
+
+// The database URL without credentials and parameters.
+fn database_label(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(mut url) => {
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            url.set_query(None);
+            url.to_string()
+        }
+        Err(_) => url.to_string(),
+    }
+}
+
+#[cfg(debug_assertions)]
+async fn squash<Db>(
+    migrate: &Migrate,
+    migrations_dirs: &[PathBuf],
+    migrator: Migrator<Db>,
+    through: u64,
+    name: &str,
+) -> Result<CliOutcome, Error>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    if !migrate.force {
+        tracing::error!("the `--force` flag is required for this operation");
+        return Err(Error::Cli(
+            "the `--force` flag is required for this operation".into(),
+        ));
+    }
+
+    let re = Regex::new("^[A-Za-z_][A-Za-z_0-9]*$").unwrap();
+
+    if !re.is_match(name) {
+        tracing::error!(name, "invalid migration name");
+        return Err(Error::Cli("invalid migration name".into()));
+    }
+
+    if through == 0 {
+        tracing::error!(through, "at least one migration must be squashed");
+        return Err(Error::InvalidVersion {
+            version: through,
+            min_version: 1,
+            max_version: migrator.local_migrations().len() as _,
+        });
+    }
+
+    let files = match migration_files(migrations_dirs) {
+        Ok(files) => files,
+        Err(error) => {
+            tracing::error!(error = %error, paths = ?migrations_dirs, "failed to read migrations");
+            return Err(Error::Cli(format!("failed to read migrations: {error}")));
+        }
+    };
+
+    if files.len() < through as usize {
+        tracing::error!(
+            through,
+            found = files.len(),
+            "not enough migration files to squash"
+        );
+        return Err(Error::Cli("not enough migration files to squash".into()));
+    }
+
+    let (squashed, rest) = files.split_at(through as usize);
+
+    if rest.iter().any(|files| files.name == name) {
+        tracing::error!(name, "a migration with the given name already exists");
+        return Err(Error::Cli(
+            "a migration with the given name already exists".into(),
+        ));
+    }
+
+    if squashed
+        .iter()
+        .zip(migrator.local_migrations())
+        .any(|(files, mig)| files.name != mig.name())
+    {
+        tracing::error!("migration files do not match the local migrations, rebuild and try again");
+        return Err(Error::Cli(
+            "migration files do not match the local migrations, rebuild and try again".into(),
+        ));
+    }
+
+    // The baseline takes the place of the last squashed migration.
+    let last = &squashed[squashed.len() - 1];
+    let date = &last.date;
+    let baseline_path = last.dir.join(format!("{date}_{name}.migrate.sql"));
+
+    let existed = baseline_path.exists();
+
+    // The baseline is written before the bookkeeping changes are committed,
+    // so that the database never refers to a baseline that does not exist.
+    let res = migrator
+        .squash_with(through, name.to_string(), |summary| {
+            fs::write(&baseline_path, &summary.sql).map_err(|error| {
+                tracing::error!(error = %error, path = ?baseline_path, "failed to write file");
+                Error::Cli(format!(
+                    "failed to write file {}: {error}",
+                    baseline_path.display()
+                ))
+            })
+        })
+        .await;
+
+    let summary = match res {
+        Ok(s) => s,
+        Err(error) => {
+            if !existed && baseline_path.exists() {
+                if let Err(err) = fs::remove_file(&baseline_path) {
+                    tracing::warn!(error = %err, path = ?baseline_path, "failed to remove the baseline file");
+                }
+            }
+
+            tracing::error!(error = %error, "error squashing migrations");
+            return Err(error);
+        }
+    };
+
+    // The squashed files are only removed once the changes are committed.
+    for path in squashed.iter().flat_map(|files| &files.paths) {
+        if *path == baseline_path {
+            continue;
+        }
+
+        if let Err(error) = fs::remove_file(path) {
+            tracing::error!(error = %error, path = ?path, "failed to remove file");
+            return Err(Error::Cli(format!(
+                "failed to remove file {}: {error}",
+                path.display()
+            )));
+        }
+    }
+
+    for dir in migrations_dirs {
+        touch_migrations_dir(dir);
+    }
+
+    tracing::info!(
+        squashed = summary.squashed,
+        name = %summary.name,
+        path = ?baseline_path,
+        "squashed migrations, other databases can be reconciled with `set`"
+    );
+
+    Ok(CliOutcome::Done)
+}
+
+async fn do_migrate<Db>(
+    _migrate: &Migrate,
+    migrations_dirs: &[PathBuf],
+    migrator: Migrator<Db>,
+    name: Option<&str>,
+    version: Option<u64>,
+    phase: Option<MigrationPhase>,
+    skip: &[u64],
+) -> Result<CliOutcome, Error>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    if let Some(phase) = phase {
+        let res = migrator.migrate_phase(phase).await;
+        return migrate_outcome(migrations_dirs, res);
+    }
+
+    let version = match version {
+        Some(v) => Some(v),
+        None => match name {
+            Some(name) => {
+                if let Some(version) = migrator.migration_version(name) {
+                    Some(version)
+                } else {
+                    tracing::error!(name = name, "migration not found");
+                    return Err(Error::MigrationNotFound {
+                        name: name.to_string(),
+                    });
+                }
+            }
+            None => None,
+        },
+    };
+
+    if !skip.is_empty() {
+        let version = version.unwrap_or(migrator.local_migrations().len() as u64);
+
+        let res = migrator
+            .migrate_skipping(version, skip.iter().copied())
+            .await;
+        return migrate_outcome(migrations_dirs, res);
+    }
+
+    let res = match version {
+        Some(version) => migrator.migrate(version).await,
+        None => migrator.migrate_all().await,
+    };
+
+    migrate_outcome(migrations_dirs, res)
+}
+
+fn migrate_outcome(
+    migrations_dirs: &[PathBuf],
+    res: Result<MigrationSummary, Error>,
+) -> Result<CliOutcome, Error> {
+    match res {
+        Ok(s) => {
+            print_summary(&s);
+            Ok(CliOutcome::Summary(s))
+        }
+        Err(error) => {
+            log_migration_error(migrations_dirs, &error, "error applying migrations");
+            Err(error)
+        }
+    }
+}
+
+async fn revert<Db>(
+    migrate: &Migrate,
+    migrations_dirs: &[PathBuf],
+    migrator: Migrator<Db>,
+    name: Option<&str>,
+    version: Option<u64>,
+    to_release: Option<&str>,
+) -> Result<CliOutcome, Error>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    if !migrate.force {
+        tracing::error!("the `--force` flag is required for this operation");
+        return Err(Error::Cli(
+            "the `--force` flag is required for this operation".into(),
+        ));
+    }
+
+    let version = match version {
+        Some(v) => Some(v),
+        None => match name {
+            Some(name) => {
+                if let Some(version) = migrator.migration_version(name) {
+                    Some(version)
+                } else {
+                    tracing::error!(name = name, "migration not found");
+                    return Err(Error::MigrationNotFound {
+                        name: name.to_string(),
+                    });
+                }
+            }
+            None => None,
+        },
+    };
+
+    let res = match (to_release, version) {
+        (Some(release), _) => migrator.revert_to_release(release).await,
+        (None, Some(version)) => migrator.revert(version).await,
+        (None, None) => migrator.revert_all().await,
+    };
+
+    match res {
+        Ok(s) => {
+            print_summary(&s);
+            Ok(CliOutcome::Summary(s))
+        }
+        Err(error) => {
+            log_migration_error(migrations_dirs, &error, "error reverting migrations");
+
+            match error {
+                Error::Irreversible { .. } => tracing::info!(
+                    fix =
+                        "pass `--allow-irreversible` to only remove them from the migrations table",
+                    "nothing was reverted"
+                ),
+                Error::IrreversibleBoundary { .. } => tracing::info!(
+                    fix = "pass `--cross-boundary` if the lost data is not needed",
+                    "nothing was reverted"
+                ),
+                _ => {}
+            }
+
+            Err(error)
+        }
+    }
+}
+
+async fn force<Db>(
+    migrate: &Migrate,
+    migrator: Migrator<Db>,
+    name: Option<&str>,
+    version: Option<u64>,
+) -> Result<CliOutcome, Error>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    if !migrate.force {
+        tracing::error!("the `--do-as-i-say` or `--force` flag is required for this operation");
+        return Err(Error::Cli(
+            "the `--do-as-i-say` or `--force` flag is required for this operation".into(),
+        ));
+    }
+
+    let version = match version {
+        Some(v) => v,
+        None => {
+            if let Some(version) = migrator.migration_version(name.unwrap()) {
+                version
+            } else {
+                tracing::error!(name = name.unwrap(), "migration not found");
+                return Err(Error::MigrationNotFound {
+                    name: name.unwrap().to_string(),
+                });
+            }
+        }
+    };
+
+    match migrator.force_version(version).await {
+        Ok(s) => {
+            print_summary(&s);
+            Ok(CliOutcome::Summary(s))
+        }
+        Err(error) => {
+            tracing::error!(error = %error, "error updating migrations");
+            Err(error)
+        }
+    }
+}
+
+async fn mark<Db>(
+    migrate: &Migrate,
+    migrator: Migrator<Db>,
+    version: u64,
+    applied: bool,
+) -> Result<CliOutcome, Error>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    if !migrate.force {
+        tracing::error!("the `--do-as-i-say` or `--force` flag is required for this operation");
+        return Err(Error::Cli(
+            "the `--do-as-i-say` or `--force` flag is required for this operation".into(),
+        ));
+    }
+
+    let res = if applied {
+        migrator.mark_applied(version).await
+    } else {
+        migrator.mark_unapplied(version).await
+    };
+
+    if let Err(error) = res {
+        tracing::error!(error = %error, "error updating migrations");
+        return Err(error);
+    }
+
+    Ok(CliOutcome::Done)
+}
+
+async fn log_status<Db>(
+    _migrate: &Migrate,
+    migrator: Migrator<Db>,
+    wide: bool,
+) -> Result<CliOutcome, Error>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    let local = migrator.local_status();
+
+    let status = match migrator.status().await {
+        Ok(s) => s,
+        Err(error @ Error::MigrationsTableInaccessible { .. }) => {
+            tracing::warn!(
+                error = %error,
+                "bookkeeping table inaccessible, only the local migrations are listed"
+            );
+            println!("{}", report::status_table(&local, wide));
+            return Ok(CliOutcome::Status(local));
+        }
+        Err(error) => {
+            tracing::error!(error = %error, "error retrieving migration status");
+            return Err(error);
+        }
+    };
+
+    let all_valid = status.iter().all(report::status_ok);
+
+    println!("{}", report::status_table(&status, wide));
+
+    if !all_valid {
+        return Err(Error::Cli("some migrations are invalid".into()));
+    }
+
+    Ok(CliOutcome::Status(status))
+}
+
+fn print_summary(summary: &MigrationSummary) {
+    eprintln!("{}", report::summary_table(summary));
+
+    if let Some(table) = report::irreversible_table(summary) {
+        eprintln!("{table}");
+    }
+}
+
+/// Parse a `--param` argument.
+fn parse_param(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got `{s}`")),
+    }
+}
+
+async fn setup_migrator<Db>(
+    migrate: &Migrate,
+    migrations: Vec<Migration<Db>>,
+    setup: impl FnOnce(&mut Migrator<Db>),
+    connect: Option<ConnectFn<Db>>,
+) -> Result<Migrator<Db>, Error>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    let statement_logging = migrate.log_statements.then(StatementLogging::default);
+
+    let migrations_table_ddl = match &migrate.migrations_table_ddl {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(ddl) => Some(ddl),
+            Err(error) => {
+                tracing::error!(error = %error, path = ?path, "failed to read migrations table DDL");
+                return Err(Error::Cli(format!(
+                    "failed to read migrations table DDL {}: {error}",
+                    path.display()
+                )));
+            }
+        },
+        None => None,
+    };
+
+    let mut mig = match connect {
+        Some(connect) => match connect(migrate).await {
+            Ok(mig) => mig,
+            Err(error) => {
+                tracing::error!(error = %error, "failed to create database connection");
+                return Err(error);
+            }
+        },
+        None => connect_url(migrate, statement_logging).await?,
+    };
+
+    let (allow_unsafe, analyze_threshold) = match &migrate.operation {
+        Operation::Migrate {
+            unsafe_allow,
+            analyze_threshold,
+            ..
+        } => (unsafe_allow.clone(), *analyze_threshold),
+        _ => (Vec::new(), None),
+    };
+
+    let allow_irreversible = matches!(
+        migrate.operation,
+        Operation::Revert {
+            allow_irreversible: true,
+            ..
+        }
+    );
+
+    let cross_boundaries = matches!(
+        migrate.operation,
+        Operation::Revert {
+            cross_boundary: true,
+            ..
+        }
+    );
+
+    let explain = matches!(migrate.operation, Operation::Locks { explain: true });
+
+    let behind_threshold = match migrate.operation {
+        Operation::Status {
+            behind_threshold, ..
+        } => behind_threshold,
+        _ => None,
+    };
+
+    mig.set_options(MigratorOptions {
+        verify_checksums: !migrate.no_verify_checksums,
+        checksum_sampling: migrate.checksum_sampling,
+        verify_names: !migrate.no_verify_names,
+        refuse_newer_database: migrate.refuse_newer_database,
+        unsafe_linter: Some(Linter::new()),
+        allow_unsafe,
+        allow_irreversible,
+        cross_boundaries,
+        migrations_table_ddl,
+        extensions: migrate.extensions.clone(),
+        create_schemas: migrate.create_schemas,
+        lock_heartbeat: migrate.lock_heartbeat,
+        reconnect_attempts: migrate.reconnect_attempts,
+        statement_log: migrate.statement_log.clone(),
+        statement_logging,
+        analyze_threshold,
+        explain,
+        record_runs: migrate.record_runs,
+        behind_threshold,
+        #[cfg(feature = "notify")]
+        webhook: migrate
+            .webhook_url
+            .as_ref()
+            .map(crate::notify::Webhook::new),
+        ..Default::default()
+    });
+
+    if !migrate.migrations_table.is_empty() {
+        mig.set_migrations_table(&migrate.migrations_table);
+    }
+
+    if !migrate.params.is_empty() {
+        mig.set(migrate.params.iter().cloned().collect::<Params>());
+    }
+
+    setup(&mut mig);
+
+    if let Err(error) = mig.try_add_migrations(migrations) {
+        tracing::error!(error = %error, "invalid migrations");
+        return Err(error);
+    }
+
+    Ok(mig)
+}
+
+async fn connect_url<Db>(
+    migrate: &Migrate,
+    statement_logging: Option<StatementLogging>,
+) -> Result<Migrator<Db>, Error>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    let replica_url = if migrate.operation.is_read_only() {
+        migrate
+            .replica_url
+            .clone()
+            .or_else(|| std::env::var("DATABASE_REPLICA_URL").ok())
+    } else {
+        None
+    };
+
+    let db_url = match replica_url.or_else(|| migrate.database_url.clone()) {
+        Some(s) => s,
         None => {
             if let Ok(url) = std::env::var("DATABASE_URL") {
                 url
@@ -632,48 +2489,639 @@ where
                 tracing::error!(
                     "`DATABASE_URL` environment variable or `--database-url` argument is required"
                 );
-                process::exit(1);
+                return Err(Error::Cli(
+                    "`DATABASE_URL` environment variable or `--database-url` argument is required"
+                        .into(),
+                ));
             }
         }
     };
 
-    let mut options =
-        match db_url.parse::<<<Db as Database>::Connection as sqlx::Connection>::Options>() {
-            Ok(opts) => opts,
-            Err(err) => {
-                tracing::error!(error = %err, "invalid database URL");
-                process::exit(1);
+    if let Err(err) = db_url.parse::<<<Db as Database>::Connection as sqlx::Connection>::Options>()
+    {
+        tracing::error!(error = %err, "invalid database URL");
+        return Err(Error::Database(err));
+    }
+
+    // The migrator keeps the URL for reconnecting.
+    let options = MigratorOptions {
+        statement_logging,
+        ..Default::default()
+    };
+
+    match Migrator::connect_with_options(&db_url, options).await {
+        Ok(mig) => Ok(mig),
+        Err(err) => {
+            tracing::error!(error = %err, "failed to create database connection");
+            Err(Error::Database(err))
+        }
+    }
+}
+
+/// Load the lint configuration files of the migrations directories.
+fn load_lint_config(migrations_dirs: &[PathBuf]) -> Result<Linter, Error> {
+    let mut linter = Linter::new();
+
+    for migrations_path in migrations_dirs {
+        let path = migrations_path.join(lint::CONFIG_FILE);
+
+        if !path.is_file() {
+            continue;
+        }
+
+        let config = fs::read_to_string(&path).map_err(|err| {
+            tracing::error!(error = %err, path = ?path, "failed to read lint configuration");
+            Error::Cli(format!("failed to read {}: {err}", path.display()))
+        })?;
+
+        linter = linter.configure(&config).map_err(|err| {
+            tracing::error!(error = %err, path = ?path, "invalid lint configuration");
+            Error::Cli(format!(
+                "invalid lint configuration in {}: {err}",
+                path.display()
+            ))
+        })?;
+    }
+
+    Ok(linter)
+}
+
+async fn lint<Db>(migrator: Migrator<Db>, linter: &Linter) -> Result<CliOutcome, Error>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    let results = match migrator.lint(linter).await {
+        Ok(results) => results,
+        Err(error) => {
+            tracing::error!(error = %error, "error linting migrations");
+            return Err(error);
+        }
+    };
+
+    if results.is_empty() {
+        tracing::info!("no issues found");
+        return Ok(CliOutcome::Done);
+    }
+
+    let mut table = Table::new();
+
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(Vec::from([
+            Cell::new("Version").set_alignment(CellAlignment::Center),
+            Cell::new("Name").set_alignment(CellAlignment::Center),
+            Cell::new("Severity").set_alignment(CellAlignment::Center),
+            Cell::new("Rule").set_alignment(CellAlignment::Center),
+            Cell::new("Statement").set_alignment(CellAlignment::Center),
+        ]));
+
+    let mut errors = 0;
+    let mut warnings = 0;
+
+    for mig in &results {
+        for lint in &mig.lints {
+            match lint.severity {
+                Severity::Warning => warnings += 1,
+                Severity::Error => errors += 1,
             }
-        };
 
-    if migrate.log_statements {
-        options = options
-            .log_statements("INFO".parse().unwrap())
-            .log_slow_statements("WARN".parse().unwrap(), Duration::from_secs(1));
+            table.add_row(Vec::from([
+                Cell::new(mig.version.to_string()).set_alignment(CellAlignment::Center),
+                Cell::new(&mig.name).set_alignment(CellAlignment::Center),
+                Cell::new(lint.severity.to_string()).set_alignment(CellAlignment::Center),
+                Cell::new(lint.rule.name()).set_alignment(CellAlignment::Center),
+                Cell::new(&lint.statement),
+            ]));
+        }
+    }
+
+    eprintln!("{table}");
+
+    if errors > 0 {
+        tracing::error!(errors, warnings, "dangerous migrations found");
+        return Err(Error::Cli("dangerous migrations found".into()));
+    }
+
+    tracing::warn!(warnings, "potentially dangerous migrations found");
+
+    Ok(CliOutcome::Done)
+}
+
+async fn locks<Db>(migrator: Migrator<Db>) -> Result<CliOutcome, Error>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    let results = match migrator.lock_impact().await {
+        Ok(results) => results,
+        Err(error) => {
+            tracing::error!(error = %error, "error analyzing migrations");
+            return Err(error);
+        }
+    };
+
+    if results.iter().all(|mig| mig.locks.is_empty()) {
+        tracing::info!("no exclusive locks are taken by pending migrations");
     } else {
-        options = options.disable_statement_logging();
+        let mut table = Table::new();
+
+        table
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(Vec::from([
+                Cell::new("Version").set_alignment(CellAlignment::Center),
+                Cell::new("Name").set_alignment(CellAlignment::Center),
+                Cell::new("Table").set_alignment(CellAlignment::Center),
+                Cell::new("Rows").set_alignment(CellAlignment::Center),
+                Cell::new("Size").set_alignment(CellAlignment::Center),
+                Cell::new("Rewrite").set_alignment(CellAlignment::Center),
+                Cell::new("Statement").set_alignment(CellAlignment::Center),
+            ]));
+
+        for mig in &results {
+            for (lock, stats) in &mig.locks {
+                let (rows, size) = match stats {
+                    Some(stats) => (format!("~{}", stats.rows), format_bytes(stats.bytes)),
+                    None => ("-".to_string(), "-".to_string()),
+                };
+
+                table.add_row(Vec::from([
+                    Cell::new(mig.version.to_string()).set_alignment(CellAlignment::Center),
+                    Cell::new(&mig.name).set_alignment(CellAlignment::Center),
+                    Cell::new(&lock.table).set_alignment(CellAlignment::Center),
+                    Cell::new(rows).set_alignment(CellAlignment::Center),
+                    Cell::new(size).set_alignment(CellAlignment::Center),
+                    Cell::new(if lock.rewrite { "x" } else { "" })
+                        .set_alignment(CellAlignment::Center),
+                    Cell::new(&lock.statement),
+                ]));
+            }
+        }
+
+        eprintln!("{table}");
     }
 
-    match Migrator::connect_with(&options).await {
-        Ok(mut mig) => {
-            mig.set_options(MigratorOptions {
-                verify_checksums: !migrate.no_verify_checksums,
-                verify_names: !migrate.no_verify_names,
-            });
+    if results.iter().any(|mig| !mig.plans.is_empty()) {
+        let mut table = Table::new();
+
+        table
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(Vec::from([
+                Cell::new("Version").set_alignment(CellAlignment::Center),
+                Cell::new("Name").set_alignment(CellAlignment::Center),
+                Cell::new("Statement").set_alignment(CellAlignment::Center),
+                Cell::new("Plan").set_alignment(CellAlignment::Center),
+            ]));
+
+        for mig in &results {
+            for plan in &mig.plans {
+                let explained = match (&plan.plan, &plan.error) {
+                    (Some(plan), _) => plan.clone(),
+                    (None, Some(error)) => format!("cannot be explained: {error}"),
+                    (None, None) => String::new(),
+                };
+
+                table.add_row(Vec::from([
+                    Cell::new(mig.version.to_string()).set_alignment(CellAlignment::Center),
+                    Cell::new(&mig.name).set_alignment(CellAlignment::Center),
+                    Cell::new(&plan.statement),
+                    Cell::new(explained),
+                ]));
+            }
+        }
+
+        eprintln!("{table}");
+    }
+
+    Ok(CliOutcome::Done)
+}
 
-            if !migrate.migrations_table.is_empty() {
-                mig.set_migrations_table(&migrate.migrations_table);
+#[allow(clippy::cast_precision_loss)]
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+async fn history<Db>(
+    migrate: &Migrate,
+    migrator: Migrator<Db>,
+    operation: &HistoryOperation,
+) -> Result<CliOutcome, Error>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    match operation {
+        HistoryOperation::Export { file } => {
+            let history = match migrator.export_history().await {
+                Ok(history) => history,
+                Err(error) => {
+                    tracing::error!(error = %error, "error exporting migration history");
+                    return Err(error);
+                }
+            };
+
+            let json = serde_json::to_string_pretty(&history).unwrap();
+
+            match file {
+                Some(path) => {
+                    if let Err(err) = fs::write(path, json) {
+                        tracing::error!(error = %err, path = ?path, "failed to write migration history");
+                        return Err(Error::Cli(format!(
+                            "failed to write migration history {}: {err}",
+                            path.display()
+                        )));
+                    }
+                    tracing::info!(
+                        path = ?path,
+                        migrations = history.migrations.len(),
+                        "migration history exported"
+                    );
+                }
+                None => println!("{json}"),
             }
+        }
+        HistoryOperation::Import { file } => {
+            let history: MigrationHistory = match fs::read_to_string(file)
+                .map_err(|err| err.to_string())
+                .and_then(|json| serde_json::from_str(&json).map_err(|err| err.to_string()))
+            {
+                Ok(history) => history,
+                Err(error) => {
+                    tracing::error!(error = %error, path = ?file, "failed to read migration history");
+                    return Err(Error::Cli(format!(
+                        "failed to read migration history {}: {error}",
+                        file.display()
+                    )));
+                }
+            };
 
-            mig.add_migrations(migrations);
+            let count = history.migrations.len();
+
+            if let Err(error) = migrator.import_history(&history, migrate.force).await {
+                if let crate::Error::HistoryExists { .. } = error {
+                    tracing::error!(
+                        error = %error,
+                        "use the `--force` flag to replace the existing records"
+                    );
+                } else {
+                    tracing::error!(error = %error, "error importing migration history");
+                }
+                return Err(error);
+            }
 
-            mig
+            tracing::info!(migrations = count, "migration history imported");
         }
+    }
+
+    Ok(CliOutcome::Done)
+}
+
+fn docs<Db>(
+    migrations_dirs: &[PathBuf],
+    migrations: &[Migration<Db>],
+    output: Option<&Path>,
+) -> Result<CliOutcome, Error>
+where
+    Db: Database,
+{
+    let files = match migration_files(migrations_dirs) {
+        Ok(files) => files,
         Err(err) => {
-            tracing::error!(error = %err, "failed to create database connection");
-            process::exit(1);
+            tracing::error!(error = %err, "failed to read migrations directory");
+            return Err(Error::Cli(format!(
+                "failed to read migrations directory: {err}"
+            )));
+        }
+    };
+
+    let mut md = String::from("# Migrations\n");
+
+    for (idx, mig) in migrations.iter().enumerate() {
+        let version = idx + 1;
+
+        writeln!(md, "\n## {version}. {}\n", mig.name()).unwrap();
+
+        let mig_files = files.iter().find(|f| f.name == mig.name());
+
+        if let Some(mig_files) = mig_files {
+            writeln!(md, "- Date: {}", migration_date(&mig_files.date)).unwrap();
+        }
+
+        writeln!(
+            md,
+            "- Reversible: {}",
+            if mig.is_reversible() { "yes" } else { "no" }
+        )
+        .unwrap();
+
+        let paths = mig_files.map(|f| f.paths.as_slice()).unwrap_or_default();
+
+        let mut sections = Vec::new();
+        let mut description = String::new();
+
+        for (title, suffix) in [("Migrate", ".migrate."), ("Revert", ".revert.")] {
+            let Some(path) = paths.iter().find(|p| {
+                p.file_name()
+                    .is_some_and(|f| f.to_string_lossy().to_ascii_lowercase().contains(suffix))
+            }) else {
+                continue;
+            };
+
+            let source = match fs::read_to_string(path) {
+                Ok(source) => source,
+                Err(err) => {
+                    tracing::error!(error = %err, path = ?path, "failed to read migration file");
+                    return Err(Error::Cli(format!(
+                        "failed to read migration file {}: {err}",
+                        path.display()
+                    )));
+                }
+            };
+
+            let lang = if path.extension().is_some_and(|ext| ext == "sql") {
+                "sql"
+            } else {
+                "rust"
+            };
+
+            if description.is_empty() && title == "Migrate" {
+                description = migration_description(&source, lang == "sql");
+            }
+
+            sections.push(format!(
+                "### {title}\n\n```{lang}\n{}\n```\n",
+                source.trim()
+            ));
+        }
+
+        if !description.is_empty() {
+            writeln!(md, "\n{description}").unwrap();
+        }
+
+        for section in sections {
+            write!(md, "\n{section}").unwrap();
+        }
+    }
+
+    match output {
+        Some(path) => {
+            if let Err(err) = fs::write(path, md) {
+                tracing::error!(error = %err, path = ?path, "failed to write documentation");
+                return Err(Error::Cli(format!(
+                    "failed to write documentation {}: {err}",
+                    path.display()
+                )));
+            }
+            tracing::info!(path = ?path, "documentation written");
+        }
+        None => print!("{md}"),
+    }
+
+    Ok(CliOutcome::Done)
+}
+
+/// Format the date prefix of a migration file name, e.g. `20211215161742`.
+fn migration_date(date: &str) -> String {
+    if date.len() != 14 || !date.bytes().all(|b| b.is_ascii_digit()) {
+        return date.to_string();
+    }
+
+    format!(
+        "{}-{}-{} {}:{}:{}",
+        &date[0..4],
+        &date[4..6],
+        &date[6..8],
+        &date[8..10],
+        &date[10..12],
+        &date[12..14]
+    )
+}
+
+/// The leading comment lines of a SQL migration,
+/// or the documentation comments of a Rust migration.
+fn migration_description(source: &str, sql: bool) -> String {
+    let lines: Vec<&str> = if sql {
+        source
+            .lines()
+            .map(str::trim)
+            .skip_while(|line| line.is_empty())
+            .take_while(|line| line.starts_with("--"))
+            .map(|line| line.trim_start_matches('-').trim())
+            .collect()
+    } else {
+        source
+            .lines()
+            .map(str::trim)
+            .skip_while(|line| !line.starts_with("///"))
+            .take_while(|line| line.starts_with("///"))
+            .map(|line| line.trim_start_matches('/').trim())
+            .collect()
+    };
+
+    lines.join("\n").trim().to_string()
+}
+
+/// Format the given time in UTC as `YYYYMMDDhhmmss` for migration file names.
+fn file_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since the epoch,
+    // see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}{month:02}{day:02}{:02}{:02}{:02}",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Log an error along with its sources, the file of the failed migration,
+/// and the backtrace if it was captured.
+fn log_migration_error(migrations_dirs: &[PathBuf], error: &Error, message: &str) {
+    // The message of the migration error is already part of the error.
+    let (name, mut source) = match error {
+        Error::Migration { name, error, .. }
+        | Error::Revert { name, error, .. }
+        | Error::Deferred { name, error, .. } => (Some(name), error.source()),
+        _ => (None, std::error::Error::source(error)),
+    };
+
+    let suffix = if matches!(error, Error::Revert { .. }) {
+        ".revert."
+    } else {
+        ".migrate."
+    };
+
+    let path = name.and_then(|name| {
+        migration_files(migrations_dirs)
+            .ok()?
+            .into_iter()
+            .find(|f| f.name == *name)?
+            .paths
+            .into_iter()
+            .find(|p| {
+                p.file_name()
+                    .is_some_and(|f| f.to_string_lossy().to_ascii_lowercase().contains(suffix))
+            })
+    });
+
+    if let Some(path) = path {
+        tracing::error!(error = %error, path = ?path, "{message}");
+    } else {
+        tracing::error!(error = %error, "{message}");
+    }
+
+    while let Some(error) = source {
+        tracing::error!("caused by: {error}");
+        source = error.source();
+    }
+
+    if let Some(backtrace) = error.backtrace() {
+        tracing::error!("backtrace:\n{backtrace}");
+    }
+}
+
+/// The files of a single migration in a migrations directory.
+struct MigrationFiles {
+    date: String,
+    name: String,
+    /// The migrations directory the files are in.
+    dir: PathBuf,
+    paths: Vec<PathBuf>,
+}
+
+/// Collect the migration files in the given directories, ordered by date.
+///
+/// Files ignored by the `.migrateignore` file of their directory are skipped,
+/// migrations with the same name or date in different directories are rejected.
+fn migration_files(migrations_dirs: &[PathBuf]) -> io::Result<Vec<MigrationFiles>> {
+    let mut migrations: Vec<MigrationFiles> = Vec::new();
+
+    for migrations_path in migrations_dirs {
+        for files in dir_migration_files(migrations_path)? {
+            if let Some(other) = migrations
+                .iter()
+                .find(|mig| mig.name == files.name || mig.date == files.date)
+            {
+                return Err(io::Error::other(format!(
+                    "migration {} in {} collides with {} in {}",
+                    files.name,
+                    migrations_path.display(),
+                    other.name,
+                    other.dir.display()
+                )));
+            }
+
+            migrations.push(files);
+        }
+    }
+
+    migrations.sort_by(|a, b| a.date.cmp(&b.date));
+
+    Ok(migrations)
+}
+
+/// Update the modification time of the migrations directory,
+/// so that `build.rs` picks up new and removed files.
+#[cfg(debug_assertions)]
+fn touch_migrations_dir(migrations_path: &Path) {
+    if let Err(err) = filetime::set_file_mtime(migrations_path, FileTime::now()) {
+        tracing::debug!(error = %err, "error updating the migrations directory");
+    }
+}
+
+/// Collect the migration files in a single directory, ordered by date.
+fn dir_migration_files(migrations_path: &Path) -> io::Result<Vec<MigrationFiles>> {
+    let ignore = MigrateIgnore::load(migrations_path).map_err(io::Error::other)?;
+
+    let mut entries = fs::read_dir(migrations_path)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(fs::DirEntry::file_name);
+
+    let mut migrations: Vec<MigrationFiles> = Vec::new();
+
+    for entry in entries {
+        let path = entry.path();
+
+        if path.is_dir() || ignore.is_ignored(&path) {
+            continue;
+        }
+
+        let file_name = entry.file_name().to_string_lossy().to_string();
+
+        let Some((date, rest)) = file_name.split_once('_') else {
+            continue;
+        };
+
+        let rest_lower = rest.to_ascii_lowercase();
+
+        let Some((stem, extension)) = rest_lower.rsplit_once('.') else {
+            continue;
+        };
+
+        if !["rs", "sql", "jinja"].contains(&extension) {
+            continue;
+        }
+
+        // SQL can be specific to a database, e.g. `name.migrate.pg.sql`.
+        let stem = match stem.rsplit_once('.') {
+            Some((rest, tag))
+                if extension != "rs" && DatabaseType::from_dialect_tag(tag).is_some() =>
+            {
+                rest
+            }
+            _ => stem,
+        };
+
+        let Some(name) = stem
+            .strip_suffix(".migrate")
+            .or_else(|| stem.strip_suffix(".revert"))
+            .map(|name| &rest[..name.len()])
+        else {
+            continue;
+        };
+
+        match migrations.iter_mut().find(|mig| mig.name == name) {
+            Some(mig) => mig.paths.push(path),
+            None => migrations.push(MigrationFiles {
+                date: date.to_string(),
+                name: name.to_string(),
+                dir: migrations_path.to_path_buf(),
+                paths: vec![path],
+            }),
         }
     }
+
+    Ok(migrations)
 }
 
 fn setup_logging(migrate: &Migrate) {
@@ -1,8 +1,38 @@
-use sha2::{Digest, Sha256};
+use sha2::Sha256;
 use state::TypeMap;
-use std::{any::Any, borrow::BorrowMut, sync::Arc};
+use std::{
+    any::Any,
+    borrow::Cow,
+    collections::HashMap,
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 
-use sqlx::{Database, Executor};
+use sqlx::Database;
+
+use crate::db::Migrations;
+
+#[cfg(any(feature = "postgres", feature = "sqlite", feature = "any"))]
+use crate::MigrationError;
+#[cfg(any(feature = "postgres", feature = "sqlite", feature = "any"))]
+use futures_core::future::LocalBoxFuture;
+#[cfg(any(feature = "postgres", feature = "sqlite", feature = "any"))]
+use sha2::Digest;
+#[cfg(any(feature = "postgres", feature = "sqlite", feature = "any"))]
+use sqlx::Executor;
+
+// Savepoints share a name, nested savepoints are
+// released and rolled back in reverse order.
+#[cfg(any(feature = "postgres", feature = "sqlite", feature = "any"))]
+const SAVEPOINT: &str = "SAVEPOINT sqlx_migrate_savepoint";
+#[cfg(any(feature = "postgres", feature = "sqlite", feature = "any"))]
+const RELEASE_SAVEPOINT: &str = "RELEASE SAVEPOINT sqlx_migrate_savepoint";
+#[cfg(any(feature = "postgres", feature = "sqlite", feature = "any"))]
+const ROLLBACK_TO_SAVEPOINT: &str = "ROLLBACK TO SAVEPOINT sqlx_migrate_savepoint";
 
 pub struct MigrationContext<Db>
 where
@@ -10,6 +40,30 @@ where
 {
     pub(crate) hash_only: bool,
     pub(crate) hasher: Sha256,
+    /// Values that are replaced by placeholders in the checksum,
+    /// see [`MigrationContext::hash_placeholder`].
+    pub(crate) hash_placeholders: Vec<(String, String)>,
+    /// The executed SQL, only collected if set.
+    pub(crate) sql: Option<Vec<String>>,
+    /// Whether batches are committed individually, see [`MigrationContext::batch`].
+    pub(crate) batch_commits: bool,
+    /// Rate limit for batches, see [`MigrationContext::batch`].
+    pub(crate) throttle: Option<Throttle>,
+    /// Variables for [`MigrationContext::substitute`].
+    pub(crate) variables: HashMap<String, String>,
+    /// Statements queued with [`MigrationContext::after_commit`].
+    pub(crate) post_commit: Vec<String>,
+    /// Verification of the migration lock, see [`MigrationContext::heartbeat`].
+    pub(crate) heartbeat: Option<Heartbeat>,
+    /// Rows affected by executed statements, see [`MigrationContext::rows_affected`].
+    pub(crate) rows_affected: Arc<AtomicU64>,
+    /// Rows affected per table, only tracked if set,
+    /// see [`MigratorOptions::analyze_threshold`](crate::MigratorOptions::analyze_threshold).
+    pub(crate) table_rows: Option<TableRows>,
+    /// The progress reported with [`MigrationContext::step`].
+    pub(crate) progress: Progress,
+    /// Receives the reported progress, see [`MigratorOptions::progress`](crate::MigratorOptions::progress).
+    pub(crate) progress_handler: Option<ProgressHandler>,
     pub(crate) conn: Db::Connection,
     pub(crate) ext: Arc<TypeMap![Send + Sync]>,
 }
@@ -22,6 +76,14 @@ where
         f.debug_struct("MigrationContext")
             .field("hash_only", &self.hash_only)
             .field("hasher", &self.hasher)
+            .field("batch_commits", &self.batch_commits)
+            .field("throttle", &self.throttle)
+            .field("variables", &self.variables)
+            .field("post_commit", &self.post_commit)
+            .field("heartbeat", &self.heartbeat)
+            .field("rows_affected", &self.rows_affected)
+            .field("table_rows", &self.table_rows)
+            .field("progress", &self.progress)
             .field("ext", &self.ext)
             .finish_non_exhaustive()
     }
@@ -38,13 +100,632 @@ where
         self
     }
 
+    /// Set the rate limit for [`MigrationContext::batch`].
+    ///
+    /// The default is [`MigratorOptions::batch_throttle`](crate::MigratorOptions::batch_throttle),
+    /// `None` disables throttling.
+    pub fn set_throttle(&mut self, throttle: Option<Throttle>) {
+        self.throttle = throttle;
+    }
+
+    /// Replace `${name}` placeholders in SQL with the values from
+    /// [`MigratorOptions::variables`](crate::MigratorOptions::variables),
+    /// see [`substitute_variables`](crate::sql::substitute_variables).
+    ///
+    /// SQL migrations are substituted automatically.
+    #[must_use]
+    pub fn substitute<'a>(&self, sql: &'a str) -> Cow<'a, str> {
+        crate::sql::substitute_variables(sql, &self.variables)
+    }
+
+    /// Queue a statement to be executed after the transaction of the
+    /// migration is committed, e.g. `ANALYZE` or `VACUUM` that cannot
+    /// run in a transaction.
+    ///
+    /// The statements are executed in order after all migrations of the
+    /// operation are committed, and they are part of the checksum.
+    /// If a statement fails, the migrations remain applied.
+    #[cfg(any(feature = "postgres", feature = "sqlite", feature = "any"))]
+    pub fn after_commit(&mut self, sql: impl Into<String>) {
+        let sql = sql.into();
+
+        self.record(&sql);
+
+        if !self.hash_only {
+            self.post_commit.push(sql);
+        }
+    }
+
+    /// The total number of rows affected by the statements executed
+    /// with [`Executor::execute`](sqlx::Executor::execute) so far,
+    /// including batches.
+    ///
+    /// It is recorded for every applied migration, rows returned
+    /// by queries are not counted.
+    #[must_use]
+    pub fn rows_affected(&self) -> u64 {
+        self.rows_affected.load(Ordering::Relaxed)
+    }
+
+    /// Exclude the value of the given [`Params`] parameter from the checksum
+    /// of the migration, and return the value.
+    ///
+    /// Occurrences of the value in the SQL executed afterwards are hashed as
+    /// `{key}`, so that migrations that are parameterized per tenant (e.g. with
+    /// schema names) have the same checksum for every tenant and still verify.
+    ///
+    /// Returns `None` and changes nothing if the parameter is not set.
+    pub fn hash_placeholder(&mut self, key: &str) -> Option<String> {
+        let value = self.get::<Params>()?.get(key)?.to_string();
+        self.hash_placeholder_value(key, value.clone());
+        Some(value)
+    }
+
+    /// Same as [`MigrationContext::hash_placeholder`], but for values
+    /// that are not parameters, e.g. from other extensions.
+    ///
+    /// Occurrences of `value` are hashed as `{name}`, empty values are ignored.
+    /// Longer values are replaced first if they overlap.
+    pub fn hash_placeholder_value(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let value = value.into();
+
+        if value.is_empty() {
+            return;
+        }
+
+        self.hash_placeholders.push((name.into(), value));
+        self.hash_placeholders
+            .sort_by_key(|(_, value)| std::cmp::Reverse(value.len()));
+    }
+
+    /// Set the total number of steps reported with [`MigrationContext::step`].
+    pub fn set_total_steps(&mut self, total_steps: u64) {
+        self.progress.total_steps = Some(total_steps);
+    }
+
+    /// Report that a step of a long-running migration is completed,
+    /// e.g. a table of a data migration is processed.
+    ///
+    /// The progress is logged and passed to the
+    /// [`MigratorOptions::progress`](crate::MigratorOptions::progress) handler,
+    /// nothing is reported while the checksum of the migration is calculated.
+    pub fn step(&mut self, message: impl Into<String>) {
+        if self.hash_only {
+            return;
+        }
+
+        self.progress.step += 1;
+        self.progress.message = message.into();
+
+        tracing::info!(
+            name = %self.progress.name,
+            step = self.progress.step,
+            total_steps = self.progress.total_steps,
+            "{}",
+            self.progress.message
+        );
+
+        if let Some(handler) = &self.progress_handler {
+            handler.report(&self.progress);
+        }
+    }
+
     /// Get an extension.
     #[must_use]
     pub fn get<T: Any>(&self) -> Option<&T> {
         self.ext.try_get()
     }
+
+    /// Wait before the next batch if required by the throttle.
+    #[cfg(any(feature = "postgres", feature = "sqlite", feature = "any"))]
+    async fn throttle(&self, affected: u64, elapsed: Duration) {
+        let Some(throttle) = self.throttle else {
+            return;
+        };
+
+        let delay = throttle.min_duration(affected).saturating_sub(elapsed);
+
+        if !delay.is_zero() {
+            tracing::trace!(?delay, "throttling batch");
+            futures_timer::Delay::new(delay).await;
+        }
+    }
+
+    #[cfg(any(feature = "postgres", feature = "sqlite", feature = "any"))]
+    fn record(&mut self, sql: &str) {
+        if self.hash_placeholders.is_empty() {
+            self.hasher.update(sql);
+        } else {
+            let mut normalized = sql.to_string();
+
+            for (name, value) in &self.hash_placeholders {
+                normalized = normalized.replace(value.as_str(), &format!("{{{name}}}"));
+            }
+
+            self.hasher.update(normalized);
+        }
+
+        if let Some(executed) = &mut self.sql {
+            executed.push(sql.to_string());
+        }
+    }
+}
+
+impl<Db> MigrationContext<Db>
+where
+    Db: Database,
+    Db::Connection: Migrations,
+{
+    // A context for running a migration on the connection,
+    // callers set the other fields where they differ.
+    pub(crate) fn new(
+        conn: Db::Connection,
+        ext: Arc<TypeMap![Send + Sync]>,
+        options: &crate::MigratorOptions,
+        hash_only: bool,
+    ) -> Self {
+        Self {
+            hash_only,
+            hasher: Sha256::default(),
+            hash_placeholders: Vec::new(),
+            sql: None,
+            batch_commits: false,
+            throttle: options.batch_throttle,
+            variables: options.variables.clone(),
+            post_commit: Vec::new(),
+            heartbeat: None,
+            rows_affected: Arc::default(),
+            table_rows: None,
+            progress: Progress::default(),
+            progress_handler: None,
+            conn,
+            ext,
+        }
+    }
+
+    /// Verify that the migration lock is still held if the interval of
+    /// [`MigratorOptions::lock_heartbeat`](crate::MigratorOptions::lock_heartbeat)
+    /// elapsed since the last verification.
+    ///
+    /// Batches are verified automatically, long-running migrations
+    /// should call this regularly otherwise.
+    /// Nothing is done if the heartbeat is not enabled.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the lock was lost, the migration should
+    /// fail with it so that its changes are rolled back.
+    pub async fn heartbeat(&mut self) -> Result<(), sqlx::Error> {
+        let Some(heartbeat) = &mut self.heartbeat else {
+            return Ok(());
+        };
+
+        if self.hash_only || heartbeat.last_check.elapsed() < heartbeat.interval {
+            return Ok(());
+        }
+
+        if !self.conn.lock_held().await? {
+            heartbeat.lost = true;
+
+            return Err(sqlx::Error::Io(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "the migration lock was lost",
+            )));
+        }
+
+        heartbeat.last_check = Instant::now();
+
+        Ok(())
+    }
+
+    pub(crate) fn lock_lost(&self) -> bool {
+        self.heartbeat.is_some_and(|heartbeat| heartbeat.lost)
+    }
+}
+
+/// The table that [`MigrationContext::snapshot_table`] copies
+/// the given table to, in the same schema.
+#[cfg(any(feature = "postgres", feature = "sqlite", feature = "any"))]
+fn snapshot_name(table: &str) -> String {
+    let (schema, name) = match table.rsplit_once('.') {
+        Some((schema, name)) => (Some(schema), name),
+        None => (None, table),
+    };
+
+    let snapshot = format!(r#""_sqlx_snapshot_{}""#, name.trim_matches('"'));
+
+    match schema {
+        Some(schema) => format!("{schema}.{snapshot}"),
+        None => snapshot,
+    }
+}
+
+/// Periodic verification of the migration lock while migrations are run.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Heartbeat {
+    interval: Duration,
+    last_check: Instant,
+    lost: bool,
+}
+
+impl Heartbeat {
+    /// A heartbeat for a lock that was just acquired.
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_check: Instant::now(),
+            lost: false,
+        }
+    }
+}
+
+/// Rows affected per table by the statements of a migration.
+///
+/// The rows affected by a query are counted for every table
+/// modified by its `INSERT`, `UPDATE`, `DELETE` and `MERGE` statements.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TableRows(Arc<Mutex<HashMap<String, u64>>>);
+
+impl TableRows {
+    #[cfg(any(feature = "postgres", feature = "sqlite", feature = "any"))]
+    fn add(&self, tables: &[String], rows: u64) {
+        let mut table_rows = self.0.lock().unwrap();
+
+        for table in tables {
+            *table_rows.entry(table.clone()).or_default() += rows;
+        }
+    }
+
+    /// The tables with more affected rows than the threshold, ordered by name.
+    pub(crate) fn exceeding(&self, threshold: u64) -> Vec<String> {
+        let mut tables: Vec<String> = self
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, rows)| **rows > threshold)
+            .map(|(table, _)| table.clone())
+            .collect();
+
+        tables.sort();
+        tables
+    }
+}
+
+/// The progress of a migration, see [`MigrationContext::step`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// The name of the migration.
+    pub name: Cow<'static, str>,
+    /// The number of completed steps.
+    pub step: u64,
+    /// The total number of steps, if set with [`MigrationContext::set_total_steps`].
+    pub total_steps: Option<u64>,
+    /// The message of the last completed step.
+    pub message: String,
+}
+
+impl Progress {
+    /// The progress of a migration that has not completed any steps.
+    pub(crate) fn new(name: Cow<'static, str>) -> Self {
+        Self {
+            name,
+            ..Self::default()
+        }
+    }
+}
+
+/// A handler for the progress reported by migrations,
+/// see [`MigratorOptions::progress`](crate::MigratorOptions::progress).
+#[derive(Clone)]
+pub struct ProgressHandler(Arc<dyn Fn(&Progress) + Send + Sync>);
+
+impl ProgressHandler {
+    /// A handler that calls the given function with every reported step.
+    pub fn new(f: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    fn report(&self, progress: &Progress) {
+        (self.0)(progress);
+    }
+}
+
+impl std::fmt::Debug for ProgressHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProgressHandler").finish_non_exhaustive()
+    }
+}
+
+/// A rate limit for [`MigrationContext::batch`], so that backfills
+/// on busy tables leave room for other queries.
+///
+/// The helper sleeps between batches whenever they complete faster
+/// than the limit allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Throttle {
+    /// Execute at most this many batch statements per second.
+    StatementsPerSecond(u32),
+    /// Process at most this many rows per second.
+    RowsPerSecond(u64),
+}
+
+impl Throttle {
+    /// The minimum time a batch that affected the given
+    /// amount of rows should take.
+    #[must_use]
+    pub fn min_duration(self, affected: u64) -> Duration {
+        match self {
+            Throttle::StatementsPerSecond(limit) => Duration::from_secs(1) / limit.max(1),
+            Throttle::RowsPerSecond(limit) => {
+                let nanos = u128::from(affected) * 1_000_000_000 / u128::from(limit.max(1));
+                Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+            }
+        }
+    }
+}
+
+/// Key-value parameters of a run, e.g. from `--param key=value` of the CLI,
+/// so that schema names, feature toggles or batch sizes can be given
+/// without recompiling.
+///
+/// They are added to the migrator with [`Migrator::set`](crate::Migrator::set),
+/// and are available to migrations with `ctx.get::<Params>()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Params(HashMap<String, String>);
+
+impl Params {
+    /// Empty parameters.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set a parameter, replacing the previous value.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.0.insert(key.into(), value.into());
+    }
+
+    /// The value of a parameter.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    /// Parse the value of a parameter, e.g. a batch size.
+    ///
+    /// # Errors
+    ///
+    /// Returns the parse error if the value is invalid.
+    pub fn parse<T: std::str::FromStr>(&self, key: &str) -> Result<Option<T>, T::Err> {
+        self.get(key).map(str::parse).transpose()
+    }
+
+    /// All parameters in an arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for Params {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self(
+            iter.into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        )
+    }
 }
 
+impl<K: Into<String>, V: Into<String>> Extend<(K, V)> for Params {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        self.0
+            .extend(iter.into_iter().map(|(k, v)| (k.into(), v.into())));
+    }
+}
+
+// The helpers that need an executor are the same for all backends,
+// see the `Executor` implementations below.
+#[cfg(any(feature = "postgres", feature = "sqlite", feature = "any"))]
+macro_rules! impl_executor_helpers {
+    ($db:ty) => {
+        impl MigrationContext<$db> {
+            /// Repeatedly execute a query that processes at most `batch_size` rows,
+            /// until it affects fewer rows than that.
+            ///
+            /// The batch size is bound as the first parameter (`$1`), e.g.:
+            ///
+            /// ```sql
+            /// UPDATE users SET email = lower(email)
+            /// WHERE id IN (SELECT id FROM users WHERE email <> lower(email) LIMIT $1)
+            /// ```
+            ///
+            /// Deferred migrations commit after every batch, so the query must not
+            /// process the same rows twice. Other migrations run all batches in the
+            /// migration's transaction.
+            ///
+            /// Batches are rate limited if a [`Throttle`] is set,
+            /// see [`MigrationContext::set_throttle`].
+            ///
+            /// The total number of affected rows is returned.
+            ///
+            /// # Errors
+            ///
+            /// Database errors are returned.
+            pub async fn batch(&mut self, sql: &str, batch_size: u64) -> Result<u64, sqlx::Error> {
+                let mut total = 0;
+
+                loop {
+                    let started = Instant::now();
+                    let affected = sqlx::query(sql)
+                        .bind(batch_size as i64)
+                        .execute(&mut *self)
+                        .await?
+                        .rows_affected();
+
+                    total += affected;
+
+                    if self.hash_only || affected < batch_size {
+                        break;
+                    }
+
+                    if self.batch_commits {
+                        self.conn.execute("COMMIT").await?;
+                        self.conn.execute("BEGIN").await?;
+                    }
+
+                    tracing::debug!(affected, total, "batch processed");
+
+                    self.heartbeat().await?;
+                    self.throttle(affected, started.elapsed()).await;
+                }
+
+                Ok(total)
+            }
+
+            /// Execute multiple statements one after the other.
+            ///
+            /// The statements are split with [`split_statements`](crate::sql::split_statements),
+            /// so semicolons in comments, strings, `$$` bodies and trigger bodies are handled.
+            ///
+            /// The total number of affected rows is returned.
+            ///
+            /// # Errors
+            ///
+            /// Database errors are returned, the remaining statements are not executed.
+            pub async fn execute_batch(&mut self, sql: &str) -> Result<u64, sqlx::Error> {
+                let mut total = 0;
+
+                for statement in crate::sql::split_statements(sql) {
+                    total += (&mut *self).execute(statement).await?.rows_affected();
+                }
+
+                Ok(total)
+            }
+
+            /// Execute the statements of a SQL file, e.g.:
+            ///
+            /// ```ignore
+            /// ctx.execute_file(include_str!("backfill.sql")).await?;
+            /// ```
+            ///
+            /// This is the same as [`MigrationContext::execute_batch`],
+            /// except that a leading byte order mark is ignored.
+            ///
+            /// # Errors
+            ///
+            /// Database errors are returned, the remaining statements are not executed.
+            pub async fn execute_file(&mut self, contents: &str) -> Result<u64, sqlx::Error> {
+                self.execute_batch(contents.trim_start_matches('\u{feff}'))
+                    .await
+            }
+
+            /// Run the given function in a savepoint.
+            ///
+            /// If the function fails, the changes it made are rolled back and the
+            /// error is returned, the migration can either continue or fail with it:
+            ///
+            /// ```ignore
+            /// let optimized = ctx
+            ///     .savepoint(|ctx| {
+            ///         Box::pin(async move {
+            ///             ctx.tx().execute("CREATE INDEX ...").await?;
+            ///             Ok(())
+            ///         })
+            ///     })
+            ///     .await;
+            ///
+            /// if let Err(error) = optimized {
+            ///     tracing::warn!(%error, "skipping optional index");
+            /// }
+            /// ```
+            ///
+            /// Savepoints can be nested, but batches must not be committed
+            /// within them, see [`MigrationContext::batch`].
+            ///
+            /// # Errors
+            ///
+            /// The error of the function or the database is returned.
+            pub async fn savepoint<T>(
+                &mut self,
+                f: impl for<'c> FnOnce(&'c mut Self) -> LocalBoxFuture<'c, Result<T, MigrationError>>,
+            ) -> Result<T, MigrationError> {
+                if self.hash_only {
+                    return f(self).await;
+                }
+
+                self.conn.execute(SAVEPOINT).await?;
+
+                match f(self).await {
+                    Ok(value) => {
+                        self.conn.execute(RELEASE_SAVEPOINT).await?;
+                        Ok(value)
+                    }
+                    Err(error) => {
+                        self.conn.execute(ROLLBACK_TO_SAVEPOINT).await?;
+                        self.conn.execute(RELEASE_SAVEPOINT).await?;
+                        Err(error)
+                    }
+                }
+            }
+
+            /// Copy the rows of a table to a snapshot table before risky changes,
+            /// e.g. destructive updates in a data migration.
+            ///
+            /// The snapshot is kept until it is restored with [`MigrationContext::restore_snapshot`],
+            /// or dropped with the returned name, e.g. by a later migration.
+            ///
+            /// # Errors
+            ///
+            /// Database errors are returned, e.g. if the table already has a snapshot.
+            pub async fn snapshot_table(&mut self, table: &str) -> Result<String, sqlx::Error> {
+                let snapshot = snapshot_name(table);
+
+                (&mut *self)
+                    .execute(&*format!(
+                        "CREATE TABLE {snapshot} AS SELECT * FROM {table}"
+                    ))
+                    .await?;
+
+                Ok(snapshot)
+            }
+
+            /// Replace the rows of a table with the rows of its snapshot taken with
+            /// [`MigrationContext::snapshot_table`] and drop the snapshot,
+            /// e.g. in the down migration.
+            ///
+            /// The columns of the table must match the columns of the snapshot.
+            ///
+            /// The number of restored rows is returned.
+            ///
+            /// # Errors
+            ///
+            /// Database errors are returned, e.g. if the table has no snapshot.
+            pub async fn restore_snapshot(&mut self, table: &str) -> Result<u64, sqlx::Error> {
+                let snapshot = snapshot_name(table);
+
+                (&mut *self)
+                    .execute(&*format!("DELETE FROM {table}"))
+                    .await?;
+
+                let restored = (&mut *self)
+                    .execute(&*format!("INSERT INTO {table} SELECT * FROM {snapshot}"))
+                    .await?
+                    .rows_affected();
+
+                (&mut *self)
+                    .execute(&*format!("DROP TABLE {snapshot}"))
+                    .await?;
+
+                Ok(restored)
+            }
+        }
+    };
+}
+
+#[cfg(feature = "postgres")]
+impl_executor_helpers!(sqlx::Postgres);
+
 // Implementing this in a generic way confuses the hell out of rustc,
 // so instead this is copy/pasted for all supported backends.
 #[cfg(feature = "postgres")]
@@ -68,13 +749,13 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Postgres> {
         'c: 'e,
         E: sqlx::Execute<'q, Self::Database>,
     {
-        self.hasher.update(query.sql());
+        self.record(query.sql());
 
         if self.hash_only {
-            return self.conn.borrow_mut().fetch_many("");
+            return Box::pin(futures_util::stream::empty());
         }
 
-        self.conn.borrow_mut().fetch_many(query)
+        self.conn.fetch_many(query)
     }
 
     fn fetch_optional<'e, 'q: 'e, E: 'q>(
@@ -88,13 +769,13 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Postgres> {
         'c: 'e,
         E: sqlx::Execute<'q, Self::Database>,
     {
-        self.hasher.update(query.sql());
+        self.record(query.sql());
 
         if self.hash_only {
             return Box::pin(async move { Ok(None) });
         }
 
-        self.conn.borrow_mut().fetch_optional(query)
+        self.conn.fetch_optional(query)
     }
 
     fn prepare_with<'e, 'q: 'e>(
@@ -103,13 +784,13 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Postgres> {
         parameters: &'e [<Self::Database as Database>::TypeInfo],
     ) -> futures_core::future::BoxFuture<
         'e,
-        Result<<Self::Database as sqlx::database::HasStatement<'q>>::Statement, sqlx::Error>,
+        Result<<Self::Database as Database>::Statement<'q>, sqlx::Error>,
     >
     where
         'c: 'e,
     {
-        self.hasher.update(sql);
-        self.conn.borrow_mut().prepare_with(sql, parameters)
+        self.record(sql);
+        self.conn.prepare_with(sql, parameters)
     }
 
     fn describe<'e, 'q: 'e>(
@@ -119,8 +800,8 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Postgres> {
     where
         'c: 'e,
     {
-        self.hasher.update(sql);
-        self.conn.borrow_mut().describe(sql)
+        self.record(sql);
+        self.conn.describe(sql)
     }
 
     fn execute<'e, 'q: 'e, E: 'q>(
@@ -134,13 +815,29 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Postgres> {
         'c: 'e,
         E: sqlx::Execute<'q, Self::Database>,
     {
-        self.hasher.update(query.sql());
+        self.record(query.sql());
 
         if self.hash_only {
-            return self.conn.borrow_mut().execute("");
+            return Box::pin(async { Ok(<Self::Database as Database>::QueryResult::default()) });
         }
 
-        self.conn.borrow_mut().execute(query)
+        let rows_affected = Arc::clone(&self.rows_affected);
+        let table_rows = self
+            .table_rows
+            .clone()
+            .map(|table_rows| (table_rows, crate::lint::dml_tables(query.sql())));
+        let execute = self.conn.execute(query);
+
+        Box::pin(async move {
+            let result = execute.await?;
+            rows_affected.fetch_add(result.rows_affected(), Ordering::Relaxed);
+
+            if let Some((table_rows, tables)) = table_rows {
+                table_rows.add(&tables, result.rows_affected());
+            }
+
+            Ok(result)
+        })
     }
 
     fn execute_many<'e, 'q: 'e, E: 'q>(
@@ -154,13 +851,13 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Postgres> {
         'c: 'e,
         E: sqlx::Execute<'q, Self::Database>,
     {
-        self.hasher.update(query.sql());
+        self.record(query.sql());
 
         if self.hash_only {
-            return self.conn.borrow_mut().execute_many("");
+            return Box::pin(futures_util::stream::empty());
         }
 
-        self.conn.borrow_mut().execute_many(query)
+        self.conn.execute_many(query)
     }
 
     fn fetch<'e, 'q: 'e, E: 'q>(
@@ -171,13 +868,13 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Postgres> {
         'c: 'e,
         E: sqlx::Execute<'q, Self::Database>,
     {
-        self.hasher.update(query.sql());
+        self.record(query.sql());
 
         if self.hash_only {
-            return self.conn.borrow_mut().fetch("");
+            return Box::pin(futures_util::stream::empty());
         }
 
-        self.conn.borrow_mut().fetch(query)
+        self.conn.fetch(query)
     }
 
     fn fetch_all<'e, 'q: 'e, E: 'q>(
@@ -191,13 +888,13 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Postgres> {
         'c: 'e,
         E: sqlx::Execute<'q, Self::Database>,
     {
-        self.hasher.update(query.sql());
+        self.record(query.sql());
 
         if self.hash_only {
-            return self.conn.borrow_mut().fetch_all("");
+            return Box::pin(async { Ok(Vec::new()) });
         }
 
-        self.conn.borrow_mut().fetch_all(query)
+        self.conn.fetch_all(query)
     }
 
     fn fetch_one<'e, 'q: 'e, E: 'q>(
@@ -208,13 +905,13 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Postgres> {
         'c: 'e,
         E: sqlx::Execute<'q, Self::Database>,
     {
-        self.hasher.update(query.sql());
+        self.record(query.sql());
 
         if self.hash_only {
-            return self.conn.borrow_mut().fetch_one("");
+            return Box::pin(async { Err(sqlx::Error::RowNotFound) });
         }
 
-        self.conn.borrow_mut().fetch_one(query)
+        self.conn.fetch_one(query)
     }
 
     fn prepare<'e, 'q: 'e>(
@@ -222,16 +919,19 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Postgres> {
         query: &'q str,
     ) -> futures_core::future::BoxFuture<
         'e,
-        Result<<Self::Database as sqlx::database::HasStatement<'q>>::Statement, sqlx::Error>,
+        Result<<Self::Database as Database>::Statement<'q>, sqlx::Error>,
     >
     where
         'c: 'e,
     {
-        self.hasher.update(query);
-        self.conn.borrow_mut().prepare(query)
+        self.record(query);
+        self.conn.prepare(query)
     }
 }
 
+#[cfg(feature = "sqlite")]
+impl_executor_helpers!(sqlx::Sqlite);
+
 // Implementing this in a generic way confuses the hell out of rustc,
 // so instead this is copy/pasted for all supported backends.
 #[cfg(feature = "sqlite")]
@@ -255,13 +955,13 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Sqlite> {
         'c: 'e,
         E: sqlx::Execute<'q, Self::Database>,
     {
-        self.hasher.update(query.sql());
+        self.record(query.sql());
 
         if self.hash_only {
-            return self.conn.borrow_mut().fetch_many("");
+            return Box::pin(futures_util::stream::empty());
         }
 
-        self.conn.borrow_mut().fetch_many(query)
+        self.conn.fetch_many(query)
     }
 
     fn fetch_optional<'e, 'q: 'e, E: 'q>(
@@ -275,13 +975,13 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Sqlite> {
         'c: 'e,
         E: sqlx::Execute<'q, Self::Database>,
     {
-        self.hasher.update(query.sql());
+        self.record(query.sql());
 
         if self.hash_only {
             return Box::pin(async move { Ok(None) });
         }
 
-        self.conn.borrow_mut().fetch_optional(query)
+        self.conn.fetch_optional(query)
     }
 
     fn prepare_with<'e, 'q: 'e>(
@@ -290,13 +990,13 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Sqlite> {
         parameters: &'e [<Self::Database as Database>::TypeInfo],
     ) -> futures_core::future::BoxFuture<
         'e,
-        Result<<Self::Database as sqlx::database::HasStatement<'q>>::Statement, sqlx::Error>,
+        Result<<Self::Database as Database>::Statement<'q>, sqlx::Error>,
     >
     where
         'c: 'e,
     {
-        self.hasher.update(sql);
-        self.conn.borrow_mut().prepare_with(sql, parameters)
+        self.record(sql);
+        self.conn.prepare_with(sql, parameters)
     }
 
     fn describe<'e, 'q: 'e>(
@@ -306,8 +1006,8 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Sqlite> {
     where
         'c: 'e,
     {
-        self.hasher.update(sql);
-        self.conn.borrow_mut().describe(sql)
+        self.record(sql);
+        self.conn.describe(sql)
     }
 
     fn execute<'e, 'q: 'e, E: 'q>(
@@ -321,13 +1021,29 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Sqlite> {
         'c: 'e,
         E: sqlx::Execute<'q, Self::Database>,
     {
-        self.hasher.update(query.sql());
+        self.record(query.sql());
 
         if self.hash_only {
-            return self.conn.borrow_mut().execute("");
+            return Box::pin(async { Ok(<Self::Database as Database>::QueryResult::default()) });
         }
 
-        self.conn.borrow_mut().execute(query)
+        let rows_affected = Arc::clone(&self.rows_affected);
+        let table_rows = self
+            .table_rows
+            .clone()
+            .map(|table_rows| (table_rows, crate::lint::dml_tables(query.sql())));
+        let execute = self.conn.execute(query);
+
+        Box::pin(async move {
+            let result = execute.await?;
+            rows_affected.fetch_add(result.rows_affected(), Ordering::Relaxed);
+
+            if let Some((table_rows, tables)) = table_rows {
+                table_rows.add(&tables, result.rows_affected());
+            }
+
+            Ok(result)
+        })
     }
 
     fn execute_many<'e, 'q: 'e, E: 'q>(
@@ -341,13 +1057,13 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Sqlite> {
         'c: 'e,
         E: sqlx::Execute<'q, Self::Database>,
     {
-        self.hasher.update(query.sql());
+        self.record(query.sql());
 
         if self.hash_only {
-            return self.conn.borrow_mut().execute_many("");
+            return Box::pin(futures_util::stream::empty());
         }
 
-        self.conn.borrow_mut().execute_many(query)
+        self.conn.execute_many(query)
     }
 
     fn fetch<'e, 'q: 'e, E: 'q>(
@@ -358,13 +1074,13 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Sqlite> {
         'c: 'e,
         E: sqlx::Execute<'q, Self::Database>,
     {
-        self.hasher.update(query.sql());
+        self.record(query.sql());
 
         if self.hash_only {
-            return self.conn.borrow_mut().fetch("");
+            return Box::pin(futures_util::stream::empty());
         }
 
-        self.conn.borrow_mut().fetch(query)
+        self.conn.fetch(query)
     }
 
     fn fetch_all<'e, 'q: 'e, E: 'q>(
@@ -378,13 +1094,13 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Sqlite> {
         'c: 'e,
         E: sqlx::Execute<'q, Self::Database>,
     {
-        self.hasher.update(query.sql());
+        self.record(query.sql());
 
         if self.hash_only {
-            return self.conn.borrow_mut().fetch_all("");
+            return Box::pin(async { Ok(Vec::new()) });
         }
 
-        self.conn.borrow_mut().fetch_all(query)
+        self.conn.fetch_all(query)
     }
 
     fn fetch_one<'e, 'q: 'e, E: 'q>(
@@ -395,13 +1111,13 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Sqlite> {
         'c: 'e,
         E: sqlx::Execute<'q, Self::Database>,
     {
-        self.hasher.update(query.sql());
+        self.record(query.sql());
 
         if self.hash_only {
-            return self.conn.borrow_mut().fetch_one("");
+            return Box::pin(async { Err(sqlx::Error::RowNotFound) });
         }
 
-        self.conn.borrow_mut().fetch_one(query)
+        self.conn.fetch_one(query)
     }
 
     fn prepare<'e, 'q: 'e>(
@@ -409,12 +1125,344 @@ impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Sqlite> {
         query: &'q str,
     ) -> futures_core::future::BoxFuture<
         'e,
-        Result<<Self::Database as sqlx::database::HasStatement<'q>>::Statement, sqlx::Error>,
+        Result<<Self::Database as Database>::Statement<'q>, sqlx::Error>,
     >
     where
         'c: 'e,
     {
-        self.hasher.update(query);
-        self.conn.borrow_mut().prepare(query)
+        self.record(query);
+        self.conn.prepare(query)
+    }
+}
+
+#[cfg(feature = "any")]
+impl MigrationContext<sqlx::Any> {
+    /// The name of the database the migration is applied to, e.g. `PostgreSQL` or `SQLite`,
+    /// see [`AnyConnection::backend_name`](sqlx::AnyConnection::backend_name).
+    #[must_use]
+    pub fn backend_name(&self) -> &str {
+        self.conn.backend_name()
+    }
+}
+
+#[cfg(feature = "any")]
+impl_executor_helpers!(sqlx::Any);
+
+// Implementing this in a generic way confuses the hell out of rustc,
+// so instead this is copy/pasted for all supported backends.
+#[cfg(feature = "any")]
+impl<'c> Executor<'c> for &'c mut MigrationContext<sqlx::Any> {
+    type Database = sqlx::Any;
+
+    fn fetch_many<'e, 'q: 'e, E: 'q>(
+        self,
+        query: E,
+    ) -> futures_core::stream::BoxStream<
+        'e,
+        Result<
+            itertools::Either<
+                <Self::Database as Database>::QueryResult,
+                <Self::Database as Database>::Row,
+            >,
+            sqlx::Error,
+        >,
+    >
+    where
+        'c: 'e,
+        E: sqlx::Execute<'q, Self::Database>,
+    {
+        self.record(query.sql());
+
+        if self.hash_only {
+            return Box::pin(futures_util::stream::empty());
+        }
+
+        self.conn.fetch_many(query)
+    }
+
+    fn fetch_optional<'e, 'q: 'e, E: 'q>(
+        self,
+        query: E,
+    ) -> futures_core::future::BoxFuture<
+        'e,
+        Result<Option<<Self::Database as Database>::Row>, sqlx::Error>,
+    >
+    where
+        'c: 'e,
+        E: sqlx::Execute<'q, Self::Database>,
+    {
+        self.record(query.sql());
+
+        if self.hash_only {
+            return Box::pin(async move { Ok(None) });
+        }
+
+        self.conn.fetch_optional(query)
+    }
+
+    fn prepare_with<'e, 'q: 'e>(
+        self,
+        sql: &'q str,
+        parameters: &'e [<Self::Database as Database>::TypeInfo],
+    ) -> futures_core::future::BoxFuture<
+        'e,
+        Result<<Self::Database as Database>::Statement<'q>, sqlx::Error>,
+    >
+    where
+        'c: 'e,
+    {
+        self.record(sql);
+        self.conn.prepare_with(sql, parameters)
+    }
+
+    fn describe<'e, 'q: 'e>(
+        self,
+        sql: &'q str,
+    ) -> futures_core::future::BoxFuture<'e, Result<sqlx::Describe<Self::Database>, sqlx::Error>>
+    where
+        'c: 'e,
+    {
+        self.record(sql);
+        self.conn.describe(sql)
+    }
+
+    fn execute<'e, 'q: 'e, E: 'q>(
+        self,
+        query: E,
+    ) -> futures_core::future::BoxFuture<
+        'e,
+        Result<<Self::Database as Database>::QueryResult, sqlx::Error>,
+    >
+    where
+        'c: 'e,
+        E: sqlx::Execute<'q, Self::Database>,
+    {
+        self.record(query.sql());
+
+        if self.hash_only {
+            return Box::pin(async { Ok(<Self::Database as Database>::QueryResult::default()) });
+        }
+
+        let rows_affected = Arc::clone(&self.rows_affected);
+        let table_rows = self
+            .table_rows
+            .clone()
+            .map(|table_rows| (table_rows, crate::lint::dml_tables(query.sql())));
+        let execute = self.conn.execute(query);
+
+        Box::pin(async move {
+            let result = execute.await?;
+            rows_affected.fetch_add(result.rows_affected(), Ordering::Relaxed);
+
+            if let Some((table_rows, tables)) = table_rows {
+                table_rows.add(&tables, result.rows_affected());
+            }
+
+            Ok(result)
+        })
+    }
+
+    fn execute_many<'e, 'q: 'e, E: 'q>(
+        self,
+        query: E,
+    ) -> futures_core::stream::BoxStream<
+        'e,
+        Result<<Self::Database as Database>::QueryResult, sqlx::Error>,
+    >
+    where
+        'c: 'e,
+        E: sqlx::Execute<'q, Self::Database>,
+    {
+        self.record(query.sql());
+
+        if self.hash_only {
+            return Box::pin(futures_util::stream::empty());
+        }
+
+        self.conn.execute_many(query)
+    }
+
+    fn fetch<'e, 'q: 'e, E: 'q>(
+        self,
+        query: E,
+    ) -> futures_core::stream::BoxStream<'e, Result<<Self::Database as Database>::Row, sqlx::Error>>
+    where
+        'c: 'e,
+        E: sqlx::Execute<'q, Self::Database>,
+    {
+        self.record(query.sql());
+
+        if self.hash_only {
+            return Box::pin(futures_util::stream::empty());
+        }
+
+        self.conn.fetch(query)
+    }
+
+    fn fetch_all<'e, 'q: 'e, E: 'q>(
+        self,
+        query: E,
+    ) -> futures_core::future::BoxFuture<
+        'e,
+        Result<Vec<<Self::Database as Database>::Row>, sqlx::Error>,
+    >
+    where
+        'c: 'e,
+        E: sqlx::Execute<'q, Self::Database>,
+    {
+        self.record(query.sql());
+
+        if self.hash_only {
+            return Box::pin(async { Ok(Vec::new()) });
+        }
+
+        self.conn.fetch_all(query)
+    }
+
+    fn fetch_one<'e, 'q: 'e, E: 'q>(
+        self,
+        query: E,
+    ) -> futures_core::future::BoxFuture<'e, Result<<Self::Database as Database>::Row, sqlx::Error>>
+    where
+        'c: 'e,
+        E: sqlx::Execute<'q, Self::Database>,
+    {
+        self.record(query.sql());
+
+        if self.hash_only {
+            return Box::pin(async { Err(sqlx::Error::RowNotFound) });
+        }
+
+        self.conn.fetch_one(query)
+    }
+
+    fn prepare<'e, 'q: 'e>(
+        self,
+        query: &'q str,
+    ) -> futures_core::future::BoxFuture<
+        'e,
+        Result<<Self::Database as Database>::Statement<'q>, sqlx::Error>,
+    >
+    where
+        'c: 'e,
+    {
+        self.record(query);
+        self.conn.prepare(query)
+    }
+}
+
+#[cfg(all(feature = "barrel", feature = "postgres"))]
+impl MigrationContext<sqlx::Postgres> {
+    /// Build a schema with [`barrel`] and execute it
+    /// with the PostgreSQL backend, e.g.:
+    ///
+    /// ```ignore
+    /// ctx.apply_schema(|m| {
+    ///     m.create_table("users", |t| {
+    ///         t.add_column("id", barrel::types::primary());
+    ///     });
+    /// })
+    /// .await?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Database errors are returned.
+    pub async fn apply_schema(
+        &mut self,
+        schema: impl FnOnce(&mut barrel::Migration),
+    ) -> Result<(), sqlx::Error> {
+        let mut m = barrel::Migration::new();
+        schema(&mut m);
+        self.execute(m.make::<barrel::backend::Pg>().as_str())
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "barrel", feature = "sqlite"))]
+impl MigrationContext<sqlx::Sqlite> {
+    /// Build a schema with [`barrel`] and execute it
+    /// with the `SQLite` backend, e.g.:
+    ///
+    /// ```ignore
+    /// ctx.apply_schema(|m| {
+    ///     m.create_table("users", |t| {
+    ///         t.add_column("id", barrel::types::primary());
+    ///     });
+    /// })
+    /// .await?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Database errors are returned.
+    pub async fn apply_schema(
+        &mut self,
+        schema: impl FnOnce(&mut barrel::Migration),
+    ) -> Result<(), sqlx::Error> {
+        let mut m = barrel::Migration::new();
+        schema(&mut m);
+        self.execute(m.make::<barrel::backend::Sqlite>().as_str())
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "sea-query", feature = "postgres"))]
+impl MigrationContext<sqlx::Postgres> {
+    /// Execute a [`sea_query`] schema statement built
+    /// for PostgreSQL, e.g.:
+    ///
+    /// ```ignore
+    /// ctx.execute_stmt(
+    ///     Table::create()
+    ///         .table(Alias::new("users"))
+    ///         .col(ColumnDef::new(Alias::new("id")).integer().primary_key()),
+    /// )
+    /// .await?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Database errors are returned.
+    pub async fn execute_stmt(
+        &mut self,
+        statement: &impl sea_query::SchemaStatementBuilder,
+    ) -> Result<(), sqlx::Error> {
+        self.execute(
+            statement
+                .to_string(sea_query::PostgresQueryBuilder)
+                .as_str(),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "sea-query", feature = "sqlite"))]
+impl MigrationContext<sqlx::Sqlite> {
+    /// Execute a [`sea_query`] schema statement built
+    /// for `SQLite`, e.g.:
+    ///
+    /// ```ignore
+    /// ctx.execute_stmt(
+    ///     Table::create()
+    ///         .table(Alias::new("users"))
+    ///         .col(ColumnDef::new(Alias::new("id")).integer().primary_key()),
+    /// )
+    /// .await?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Database errors are returned.
+    pub async fn execute_stmt(
+        &mut self,
+        statement: &impl sea_query::SchemaStatementBuilder,
+    ) -> Result<(), sqlx::Error> {
+        self.execute(statement.to_string(sea_query::SqliteQueryBuilder).as_str())
+            .await?;
+        Ok(())
     }
 }
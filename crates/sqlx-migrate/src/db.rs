@@ -6,9 +6,76 @@ mod postgres;
 #[cfg(feature = "sqlite")]
 mod sqlite;
 
+#[cfg(feature = "any")]
+mod any;
+
+#[cfg(any(feature = "postgres", feature = "sqlite", feature = "any"))]
+mod dialect;
+
 use async_trait::async_trait;
 use sqlx::Connection;
-use std::{borrow::Cow, time::Duration};
+use std::{
+    borrow::Cow,
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use crate::{Error, MigrationPhase};
+
+/// The columns of the migrations table that are used by migrators.
+pub const MIGRATIONS_TABLE_COLUMNS: [&str; 8] = [
+    "version",
+    "name",
+    "applied_on",
+    "checksum",
+    "execution_time",
+    "phase",
+    "skipped",
+    "rows_affected",
+];
+
+/// The columns that migrations tables had since the first version,
+/// existing tables without them belong to other tools.
+pub(crate) const ORIGINAL_COLUMNS: [&str; 5] = [
+    "version",
+    "name",
+    "applied_on",
+    "checksum",
+    "execution_time",
+];
+
+/// Guess the tool that created a migrations table with the given columns.
+pub(crate) fn foreign_tool(columns: &[String]) -> Option<&'static str> {
+    let has = |column: &str| columns.iter().any(|c| c.eq_ignore_ascii_case(column));
+
+    if has("description") && has("success") && has("installed_on") {
+        Some("sqlx-cli")
+    } else if has("installed_rank") && has("script") {
+        Some("Flyway")
+    } else if has("run_on") {
+        Some("Diesel")
+    } else if has("version") && has("name") && has("applied_on") && !has("execution_time") {
+        Some("Refinery")
+    } else {
+        None
+    }
+}
+
+/// Whether the error is caused by missing privileges,
+/// e.g. a read-only user or database.
+pub(crate) fn is_permission_denied(error: &sqlx::Error) -> bool {
+    let Some(code) = error
+        .as_database_error()
+        .and_then(sqlx::error::DatabaseError::code)
+    else {
+        return false;
+    };
+
+    // `insufficient_privilege` in PostgreSQL, and `SQLITE_READONLY`
+    // with its extended codes in SQLite.
+    code == "42501" || (code.len() < 5 && code.parse::<u32>().is_ok_and(|code| code & 0xff == 8))
+}
 
 #[derive(Debug, Clone)]
 pub struct AppliedMigration<'m> {
@@ -16,6 +83,137 @@ pub struct AppliedMigration<'m> {
     pub name: Cow<'m, str>,
     pub checksum: Cow<'m, [u8]>,
     pub execution_time: Duration,
+    /// The phase the migration was applied in,
+    /// not known for migrations applied by older versions.
+    pub phase: Option<MigrationPhase>,
+    /// Whether the migration was recorded as applied without being executed.
+    pub skipped: bool,
+    /// The number of rows affected by the statements of the migration,
+    /// see [`MigrationContext::rows_affected`](crate::MigrationContext::rows_affected).
+    ///
+    /// Not known for migrations applied by older versions.
+    pub rows_affected: Option<u64>,
+    /// The time the migration was recorded in the migrations table.
+    ///
+    /// It is set by the database, and it is `None` for migrations that are not yet recorded.
+    pub applied_on: Option<SystemTime>,
+}
+
+impl AppliedMigration<'_> {
+    /// The time the migration was recorded as a [`time::OffsetDateTime`].
+    #[cfg(feature = "time")]
+    #[must_use]
+    pub fn applied_on_time(&self) -> Option<time::OffsetDateTime> {
+        self.applied_on.map(Into::into)
+    }
+
+    /// The time the migration was recorded as a [`chrono::DateTime`].
+    #[cfg(feature = "chrono")]
+    #[must_use]
+    pub fn applied_on_chrono(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.applied_on.map(Into::into)
+    }
+}
+
+/// The state of a deferred migration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeferredState {
+    /// Waiting to be run.
+    Scheduled,
+    /// Currently being run.
+    Running,
+    /// Successfully completed.
+    Completed,
+    /// The last run failed, it will be retried.
+    Failed,
+}
+
+impl DeferredState {
+    /// The name of the state stored in the database.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeferredState::Scheduled => "scheduled",
+            DeferredState::Running => "running",
+            DeferredState::Completed => "completed",
+            DeferredState::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for DeferredState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scheduled" => Ok(Self::Scheduled),
+            "running" => Ok(Self::Running),
+            "completed" => Ok(Self::Completed),
+            "failed" => Ok(Self::Failed),
+            state => Err(format!("invalid deferred migration state `{state}`")),
+        }
+    }
+}
+
+/// A deferred migration recorded in the database.
+#[derive(Debug, Clone)]
+pub struct DeferredMigration {
+    pub name: String,
+    pub state: DeferredState,
+    /// The error of the last failed run.
+    pub error: Option<String>,
+    /// The execution time of the last completed run.
+    pub execution_time: Option<Duration>,
+}
+
+/// A run of a migration recorded in the database,
+/// see [`MigratorOptions::record_runs`](crate::MigratorOptions::record_runs).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationRun {
+    /// The version of the migration, `None` for deferred migrations.
+    pub version: Option<u64>,
+    pub name: String,
+    pub execution_time: Duration,
+    /// The time the run was recorded, set by the database.
+    pub ran_on: Option<SystemTime>,
+}
+
+/// A version tagged as a release, see [`Migrator::tag_release`](crate::Migrator::tag_release).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Release {
+    /// The name of the release, e.g. `v2.3.0`.
+    pub name: String,
+    /// The version of the last migration of the release.
+    pub version: u64,
+    /// The time the release was tagged, set by the database.
+    pub tagged_on: Option<SystemTime>,
+}
+
+/// Estimated size of a table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableStats {
+    /// Estimated number of rows.
+    pub rows: u64,
+    /// Total size on disk including indexes.
+    pub bytes: u64,
+}
+
+/// A privilege that the connected user lacks, see [`Migrator::preflight`](crate::Migrator::preflight).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingPrivilege {
+    /// The privilege, e.g. `CREATE` or `INSERT`.
+    pub privilege: String,
+    /// The object of the privilege, e.g. `SCHEMA "public"`.
+    pub object: String,
+    /// The statement that grants the privilege, `None` if the
+    /// database has no privileges that could be granted.
+    pub grant: Option<String>,
+}
+
+impl fmt::Display for MissingPrivilege {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} on {}", self.privilege, self.object)
+    }
 }
 
 #[async_trait(?Send)]
@@ -29,11 +227,21 @@ pub trait Migrations: Connection {
     #[must_use]
     async fn lock(&mut self) -> Result<(), sqlx::Error>;
 
+    // Should try to acquire the same lock as [`Migrations::lock`] without
+    // waiting for it, and return whether it was acquired.
+    #[must_use]
+    async fn try_lock(&mut self) -> Result<bool, sqlx::Error>;
+
     // Should release the lock. [`Migrate`] will call this function after all
     // migrations have been run.
     #[must_use]
     async fn unlock(&mut self) -> Result<(), sqlx::Error>;
 
+    // Return whether the lock acquired with [`Migrations::lock`] is still held
+    // by this connection, used for the lock heartbeat.
+    #[must_use]
+    async fn lock_held(&mut self) -> Result<bool, sqlx::Error>;
+
     // Return the ordered list of applied migrations
     #[must_use]
     async fn list_migrations(
@@ -52,6 +260,218 @@ pub trait Migrations: Connection {
     async fn remove_migration(&mut self, table_name: &str, version: u64)
         -> Result<(), sqlx::Error>;
 
+    #[must_use]
+    async fn rename_migration(
+        &mut self,
+        table_name: &str,
+        version: u64,
+        name: &str,
+    ) -> Result<(), sqlx::Error>;
+
     #[must_use]
     async fn clear_migrations(&mut self, table_name: &str) -> Result<(), sqlx::Error>;
+
+    // Return the SQL statements that recreate the current schema of the database,
+    // excluding the given tables, e.g. the migrations table.
+    //
+    // The output is used as a baseline for squashed migrations and for detecting
+    // schema drift, so it should be deterministic and every statement should
+    // describe a single database object.
+    #[must_use]
+    async fn dump_schema(&mut self, excluded_tables: &[String])
+        -> Result<Vec<String>, sqlx::Error>;
+
+    // Return the estimated size of the given table, `None` if the table
+    // does not exist or estimates are not available.
+    //
+    // The estimates should be cheap to retrieve, tables must not be scanned.
+    #[must_use]
+    async fn table_stats(&mut self, table: &str) -> Result<Option<TableStats>, sqlx::Error>;
+
+    // Return the query plan of the given statement without executing it,
+    // `None` if query plans are not supported.
+    #[must_use]
+    async fn explain(&mut self, statement: &str) -> Result<Option<String>, sqlx::Error>;
+
+    // Prepare an empty database for rehearsing migrations while in a transaction.
+    // Either this connection is switched to a new empty schema with the given name
+    // that is dropped with the transaction and `None` is returned, or a connection
+    // to a separate empty database is returned, also in a transaction.
+    #[must_use]
+    async fn rehearsal_database(&mut self, name: &str) -> Result<Option<Self>, sqlx::Error>
+    where
+        Self: Sized;
+
+    // Return the column names of the given table, empty if the table does not exist.
+    #[must_use]
+    async fn table_columns(&mut self, table: &str) -> Result<Vec<String>, sqlx::Error>;
+
+    // Create the given extensions if they do not exist, creating
+    // extensions that already exist must not require any privileges.
+    #[must_use]
+    async fn ensure_extensions(&mut self, extensions: &[String]) -> Result<(), sqlx::Error>;
+
+    // Create the schema of the given table and the schemas in the search path
+    // if they do not exist, creating schemas that already exist must not
+    // require any privileges. Databases without schemas do nothing.
+    #[must_use]
+    async fn ensure_schemas(&mut self, table: &str) -> Result<(), sqlx::Error>;
+
+    // Return the privileges that the connected user lacks for creating
+    // the schema of the given migrations table and for keeping its records.
+    //
+    // Nothing must be persisted, privileges on objects that do not exist yet
+    // are not checked.
+    #[must_use]
+    async fn missing_privileges(
+        &mut self,
+        table_name: &str,
+    ) -> Result<Vec<MissingPrivilege>, sqlx::Error>;
+
+    #[must_use]
+    async fn ensure_deferred_table(&mut self, table_name: &str) -> Result<(), sqlx::Error>;
+
+    #[must_use]
+    async fn ensure_runs_table(&mut self, table_name: &str) -> Result<(), sqlx::Error>;
+
+    // Record a run of a migration, the time of the run is set by the database.
+    #[must_use]
+    async fn add_run(&mut self, table_name: &str, run: &MigrationRun) -> Result<(), sqlx::Error>;
+
+    // Return the recorded runs in the order they were recorded.
+    #[must_use]
+    async fn list_runs(&mut self, table_name: &str) -> Result<Vec<MigrationRun>, sqlx::Error>;
+
+    #[must_use]
+    async fn ensure_releases_table(&mut self, table_name: &str) -> Result<(), sqlx::Error>;
+
+    // Tag the version as the release, moving the release if it already exists,
+    // and return the tagged release.
+    #[must_use]
+    async fn tag_release(
+        &mut self,
+        table_name: &str,
+        name: &str,
+        version: u64,
+    ) -> Result<Release, sqlx::Error>;
+
+    // Return the releases ordered by their versions.
+    #[must_use]
+    async fn list_releases(&mut self, table_name: &str) -> Result<Vec<Release>, sqlx::Error>;
+
+    // Record a deferred migration as scheduled, unless it is already recorded.
+    #[must_use]
+    async fn schedule_deferred(&mut self, table_name: &str, name: &str) -> Result<(), sqlx::Error>;
+
+    #[must_use]
+    async fn list_deferred(
+        &mut self,
+        table_name: &str,
+    ) -> Result<Vec<DeferredMigration>, sqlx::Error>;
+
+    // Atomically mark a scheduled or failed deferred migration as running,
+    // returns whether it was claimed by this call.
+    #[must_use]
+    async fn claim_deferred(&mut self, table_name: &str, name: &str) -> Result<bool, sqlx::Error>;
+
+    // Mark a running deferred migration as completed, or as failed if an error is given.
+    #[must_use]
+    async fn finish_deferred(
+        &mut self,
+        table_name: &str,
+        name: &str,
+        execution_time: Duration,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::Error>;
+}
+
+/// The bookkeeping of a migrations table, for tools that inspect or reconcile
+/// the applied migrations with the queries used by migrators.
+///
+/// A store for the migrations table of a migrator is returned by [`Migrator::store`](crate::Migrator::store).
+///
+/// The store does not acquire the migration lock, changes are not coordinated
+/// with running migrators unless the lock is acquired on the [connection](MigrationStore::connection).
+pub struct MigrationStore<'c, C: Migrations> {
+    conn: &'c mut C,
+    table: &'c str,
+}
+
+impl<'c, C: Migrations> MigrationStore<'c, C> {
+    /// A store for the migrations table with the given name,
+    /// see [`Migrations::ensure_migrations_table`] for creating it.
+    pub fn new(conn: &'c mut C, table: &'c str) -> Self {
+        Self { conn, table }
+    }
+
+    /// The name of the migrations table.
+    #[must_use]
+    pub fn table(&self) -> &str {
+        self.table
+    }
+
+    /// The connection of the store, e.g. for transactions or the migration lock.
+    pub fn connection(&mut self) -> &mut C {
+        self.conn
+    }
+
+    /// The applied migrations ordered by version.
+    ///
+    /// # Errors
+    ///
+    /// Connection and database errors are returned.
+    pub async fn list(&mut self) -> Result<Vec<AppliedMigration<'static>>, Error> {
+        Ok(self.conn.list_migrations(self.table).await?)
+    }
+
+    /// Record an applied migration.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::ConcurrentMigration`] is returned if the version is already recorded,
+    /// connection and database errors are returned.
+    pub async fn add(&mut self, migration: AppliedMigration<'static>) -> Result<(), Error> {
+        let version = migration.version;
+
+        match self.conn.add_migration(self.table, migration).await {
+            Err(err)
+                if err
+                    .as_database_error()
+                    .is_some_and(sqlx::error::DatabaseError::is_unique_violation) =>
+            {
+                Err(Error::ConcurrentMigration { version })
+            }
+            res => Ok(res?),
+        }
+    }
+
+    /// Remove the migration with the given version.
+    ///
+    /// # Errors
+    ///
+    /// Connection and database errors are returned.
+    pub async fn remove(&mut self, version: u64) -> Result<(), Error> {
+        Ok(self.conn.remove_migration(self.table, version).await?)
+    }
+
+    /// Rename the migration with the given version.
+    ///
+    /// # Errors
+    ///
+    /// Connection and database errors are returned.
+    pub async fn rename(&mut self, version: u64, name: &str) -> Result<(), Error> {
+        Ok(self
+            .conn
+            .rename_migration(self.table, version, name)
+            .await?)
+    }
+
+    /// Remove all migrations.
+    ///
+    /// # Errors
+    ///
+    /// Connection and database errors are returned.
+    pub async fn clear(&mut self) -> Result<(), Error> {
+        Ok(self.conn.clear_migrations(self.table).await?)
+    }
 }
@@ -0,0 +1,643 @@
+//! Migrations with [`AnyConnection`](sqlx::AnyConnection), the bookkeeping
+//! SQL of the actual database is selected at runtime.
+//!
+//! Only the databases with enabled features are supported,
+//! the drivers must be installed with [`sqlx::any::install_default_drivers`].
+
+use async_trait::async_trait;
+use sqlx::{query, query_as, query_scalar, AnyConnection, Connection};
+use std::time::Duration;
+
+use super::{
+    dialect::{
+        applied_migration, ensure_unique_versions, migration_run, release, Dialect, MigrationRow,
+        ReleaseRow, RunRow, ADDED_COLUMNS,
+    },
+    AppliedMigration, DeferredMigration, DeferredState, MigrationRun, MissingPrivilege, Release,
+    TableStats,
+};
+use crate::MigrationPhase;
+
+/// The dialect of the actual database behind the connection.
+fn dialect(conn: &AnyConnection) -> Result<Dialect, sqlx::Error> {
+    match conn.backend_name() {
+        #[cfg(feature = "postgres")]
+        "PostgreSQL" => Ok(Dialect::Postgres),
+        #[cfg(feature = "sqlite")]
+        "SQLite" => Ok(Dialect::Sqlite),
+        name => Err(sqlx::Error::Configuration(
+            format!("unsupported database for migrations: {name}").into(),
+        )),
+    }
+}
+
+#[async_trait(?Send)]
+impl super::Migrations for AnyConnection {
+    async fn ensure_migrations_table(&mut self, table_name: &str) -> Result<(), sqlx::Error> {
+        let dialect = dialect(self)?;
+
+        let ddl = match dialect {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => super::postgres::migrations_table(table_name),
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => super::sqlite::migrations_table(table_name),
+        };
+
+        // DDL is rejected on PostgreSQL read replicas even if the table exists.
+        let exists = match dialect {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => {
+                let tables: i64 =
+                    query_scalar("SELECT COUNT(*) FROM pg_class WHERE oid = to_regclass($1)")
+                        .bind(table_name)
+                        .fetch_one(&mut *self)
+                        .await?;
+                tables > 0
+            }
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => false,
+        };
+
+        if !exists {
+            query(&ddl).execute(&mut *self).await?;
+        }
+
+        // Tables created by older versions lack some of the columns.
+        let column_count = dialect.column_count();
+
+        for (column, definition) in ADDED_COLUMNS {
+            // Booleans are not decoded consistently by all drivers.
+            let columns: i64 = query_scalar(&column_count)
+                .bind(table_name)
+                .bind(column)
+                .fetch_one(&mut *self)
+                .await?;
+
+            if columns == 0 {
+                query(&format!(
+                    r#"ALTER TABLE {table_name} ADD COLUMN {definition}"#
+                ))
+                .execute(&mut *self)
+                .await?;
+            }
+        }
+
+        ensure_unique_versions::<sqlx::Any>(self, dialect, table_name).await
+    }
+
+    async fn lock(&mut self) -> Result<(), sqlx::Error> {
+        match dialect(self)? {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => {
+                let lock_id = lock_id(self).await?;
+
+                // The advisory lock functions return `void`, which cannot be decoded.
+                query("SELECT 1 FROM pg_advisory_lock($1)")
+                    .bind(lock_id)
+                    .execute(self)
+                    .await?;
+            }
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => {}
+        }
+
+        Ok(())
+    }
+
+    async fn try_lock(&mut self) -> Result<bool, sqlx::Error> {
+        match dialect(self)? {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => {
+                let lock_id = lock_id(self).await?;
+
+                query_scalar("SELECT pg_try_advisory_lock($1)")
+                    .bind(lock_id)
+                    .fetch_one(self)
+                    .await
+            }
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => Ok(true),
+        }
+    }
+
+    async fn unlock(&mut self) -> Result<(), sqlx::Error> {
+        match dialect(self)? {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => {
+                let lock_id = lock_id(self).await?;
+
+                query("SELECT 1 FROM pg_advisory_unlock($1)")
+                    .bind(lock_id)
+                    .execute(self)
+                    .await?;
+            }
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => {}
+        }
+
+        Ok(())
+    }
+
+    async fn lock_held(&mut self) -> Result<bool, sqlx::Error> {
+        match dialect(self)? {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => {
+                let lock_id = lock_id(self).await?;
+
+                query_scalar(super::postgres::LOCK_HELD)
+                    .bind(lock_id)
+                    .fetch_one(self)
+                    .await
+            }
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => Ok(true),
+        }
+    }
+
+    async fn list_migrations(
+        &mut self,
+        table_name: &str,
+    ) -> Result<Vec<AppliedMigration<'static>>, sqlx::Error> {
+        let rows: Vec<MigrationRow> = query_as(&dialect(self)?.list_migrations(table_name))
+            .fetch_all(self)
+            .await?;
+
+        Ok(rows.into_iter().map(applied_migration).collect())
+    }
+
+    async fn add_migration(
+        &mut self,
+        table_name: &str,
+        migration: AppliedMigration<'static>,
+    ) -> Result<(), sqlx::Error> {
+        query(&dialect(self)?.insert_migration(table_name))
+            .bind(migration.version as i64)
+            .bind(migration.name.into_owned())
+            .bind(migration.checksum.into_owned())
+            .bind(migration.execution_time.as_nanos() as i64)
+            .bind(migration.phase.map(MigrationPhase::as_str))
+            .bind(migration.skipped)
+            .bind(migration.rows_affected.map(|rows| rows as i64))
+            .execute(self)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn remove_migration(
+        &mut self,
+        table_name: &str,
+        version: u64,
+    ) -> Result<(), sqlx::Error> {
+        query(&dialect(self)?.remove_migration(table_name))
+            .bind(version as i64)
+            .execute(self)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn rename_migration(
+        &mut self,
+        table_name: &str,
+        version: u64,
+        name: &str,
+    ) -> Result<(), sqlx::Error> {
+        query(&dialect(self)?.rename_migration(table_name))
+            .bind(name)
+            .bind(version as i64)
+            .execute(self)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn clear_migrations(&mut self, table_name: &str) -> Result<(), sqlx::Error> {
+        query(&dialect(self)?.clear_migrations(table_name))
+            .execute(self)
+            .await?;
+        Ok(())
+    }
+
+    async fn ensure_deferred_table(&mut self, table_name: &str) -> Result<(), sqlx::Error> {
+        let ddl = match dialect(self)? {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => super::postgres::deferred_table(table_name),
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => super::sqlite::deferred_table(table_name),
+        };
+
+        query(&ddl).execute(self).await?;
+
+        Ok(())
+    }
+
+    async fn ensure_runs_table(&mut self, table_name: &str) -> Result<(), sqlx::Error> {
+        let ddl = match dialect(self)? {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => super::postgres::runs_table(table_name),
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => super::sqlite::runs_table(table_name),
+        };
+
+        query(&ddl).execute(self).await?;
+
+        Ok(())
+    }
+
+    async fn add_run(&mut self, table_name: &str, run: &MigrationRun) -> Result<(), sqlx::Error> {
+        query(&dialect(self)?.insert_run(table_name))
+            .bind(run.version.map(|version| version as i64))
+            .bind(&run.name)
+            .bind(run.execution_time.as_nanos() as i64)
+            .execute(self)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list_runs(&mut self, table_name: &str) -> Result<Vec<MigrationRun>, sqlx::Error> {
+        let rows: Vec<RunRow> = query_as(&dialect(self)?.list_runs(table_name))
+            .fetch_all(self)
+            .await?;
+
+        Ok(rows.into_iter().map(migration_run).collect())
+    }
+
+    async fn ensure_releases_table(&mut self, table_name: &str) -> Result<(), sqlx::Error> {
+        let ddl = match dialect(self)? {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => super::postgres::releases_table(table_name),
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => super::sqlite::releases_table(table_name),
+        };
+
+        query(&ddl).execute(self).await?;
+
+        Ok(())
+    }
+
+    async fn tag_release(
+        &mut self,
+        table_name: &str,
+        name: &str,
+        version: u64,
+    ) -> Result<Release, sqlx::Error> {
+        let row: ReleaseRow = query_as(&dialect(self)?.tag_release(table_name))
+            .bind(name)
+            .bind(version as i64)
+            .fetch_one(self)
+            .await?;
+
+        Ok(release(row))
+    }
+
+    async fn list_releases(&mut self, table_name: &str) -> Result<Vec<Release>, sqlx::Error> {
+        let rows: Vec<ReleaseRow> = query_as(&dialect(self)?.list_releases(table_name))
+            .fetch_all(self)
+            .await?;
+
+        Ok(rows.into_iter().map(release).collect())
+    }
+
+    async fn schedule_deferred(&mut self, table_name: &str, name: &str) -> Result<(), sqlx::Error> {
+        query(&dialect(self)?.schedule_deferred(table_name))
+            .bind(name)
+            .execute(self)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list_deferred(
+        &mut self,
+        table_name: &str,
+    ) -> Result<Vec<DeferredMigration>, sqlx::Error> {
+        let rows: Vec<(String, String, Option<String>, Option<i64>)> = query_as(&format!(
+            r#"
+            SELECT
+                name,
+                state,
+                error,
+                execution_time
+            FROM
+                {table_name}
+            ORDER BY scheduled_on, name
+            "#
+        ))
+        .fetch_all(self)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| DeferredMigration {
+                name: row.0,
+                state: row.1.parse().unwrap_or(DeferredState::Failed),
+                error: row.2,
+                execution_time: row.3.map(|t| Duration::from_nanos(t as _)),
+            })
+            .collect())
+    }
+
+    async fn claim_deferred(&mut self, table_name: &str, name: &str) -> Result<bool, sqlx::Error> {
+        let res = query(&dialect(self)?.claim_deferred(table_name))
+            .bind(name)
+            .execute(self)
+            .await?;
+
+        Ok(res.rows_affected() == 1)
+    }
+
+    async fn finish_deferred(
+        &mut self,
+        table_name: &str,
+        name: &str,
+        execution_time: Duration,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        query(&dialect(self)?.finish_deferred(table_name))
+            .bind(name)
+            .bind(if error.is_some() {
+                DeferredState::Failed.as_str()
+            } else {
+                DeferredState::Completed.as_str()
+            })
+            .bind(error)
+            .bind(execution_time.as_nanos() as i64)
+            .execute(self)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn table_stats(&mut self, table: &str) -> Result<Option<TableStats>, sqlx::Error> {
+        match dialect(self)? {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => {
+                let stats: Option<(i64, i64)> = query_as(super::postgres::TABLE_STATS)
+                    .bind(table)
+                    .fetch_optional(self)
+                    .await?;
+
+                Ok(stats.map(|(rows, bytes)| TableStats {
+                    rows: rows as u64,
+                    bytes: bytes as u64,
+                }))
+            }
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => Ok(None),
+        }
+    }
+
+    async fn explain(&mut self, statement: &str) -> Result<Option<String>, sqlx::Error> {
+        match dialect(self)? {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => {
+                let plan: Vec<String> = query_scalar(&super::postgres::explain(statement))
+                    .fetch_all(self)
+                    .await?;
+                Ok(Some(plan.join("\n")))
+            }
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => Ok(None),
+        }
+    }
+
+    async fn rehearsal_database(&mut self, name: &str) -> Result<Option<Self>, sqlx::Error> {
+        match dialect(self)? {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => {
+                query(&super::postgres::create_schema(name))
+                    .execute(&mut *self)
+                    .await?;
+                query(super::postgres::REHEARSAL_SEARCH_PATH)
+                    .bind(name)
+                    .execute(self)
+                    .await?;
+                Ok(None)
+            }
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => {
+                let mut conn = AnyConnection::connect(super::sqlite::REHEARSAL_DATABASE).await?;
+                query("BEGIN").execute(&mut conn).await?;
+                Ok(Some(conn))
+            }
+        }
+    }
+
+    async fn table_columns(&mut self, table: &str) -> Result<Vec<String>, sqlx::Error> {
+        let sql = match dialect(self)? {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => super::postgres::TABLE_COLUMNS,
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => super::sqlite::TABLE_COLUMNS,
+        };
+
+        query_scalar(sql).bind(table).fetch_all(self).await
+    }
+
+    async fn ensure_extensions(&mut self, extensions: &[String]) -> Result<(), sqlx::Error> {
+        match dialect(self)? {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => {
+                for extension in extensions {
+                    let exists: i64 = query_scalar(super::postgres::EXTENSION_EXISTS)
+                        .bind(extension)
+                        .fetch_one(&mut *self)
+                        .await?;
+
+                    if exists == 0 {
+                        query(&super::postgres::create_extension(extension))
+                            .execute(&mut *self)
+                            .await?;
+                    }
+                }
+
+                Ok(())
+            }
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => match extensions.first() {
+                Some(extension) => Err(sqlx::Error::Configuration(
+                    format!("SQLite does not support extensions: {extension}").into(),
+                )),
+                None => Ok(()),
+            },
+        }
+    }
+
+    async fn ensure_schemas(&mut self, table: &str) -> Result<(), sqlx::Error> {
+        match dialect(self)? {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => {
+                let search_path: String = query_scalar(super::postgres::SEARCH_PATH)
+                    .fetch_one(&mut *self)
+                    .await?;
+
+                for schema in super::postgres::bootstrap_schemas(table, &search_path) {
+                    let exists: i64 = query_scalar(super::postgres::SCHEMA_EXISTS)
+                        .bind(&schema)
+                        .fetch_one(&mut *self)
+                        .await?;
+
+                    if exists == 0 {
+                        query(&super::postgres::create_schema(&schema))
+                            .execute(&mut *self)
+                            .await?;
+                    }
+                }
+
+                Ok(())
+            }
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => Ok(()),
+        }
+    }
+
+    async fn missing_privileges(
+        &mut self,
+        table_name: &str,
+    ) -> Result<Vec<MissingPrivilege>, sqlx::Error> {
+        match dialect(self)? {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => {
+                use super::postgres::{missing_privilege, quote_ident};
+
+                let (user, database, current_schema): (String, String, Option<String>) =
+                    query_as(super::postgres::PRIVILEGE_CONTEXT)
+                        .fetch_one(&mut *self)
+                        .await?;
+
+                let schema = super::postgres::bootstrap_schemas(table_name, "")
+                    .into_iter()
+                    .next()
+                    .or(current_schema);
+
+                let mut missing = Vec::new();
+                let mut schema_exists = false;
+
+                if let Some(schema) = &schema {
+                    let exists: i64 = query_scalar(super::postgres::SCHEMA_EXISTS)
+                        .bind(schema)
+                        .fetch_one(&mut *self)
+                        .await?;
+
+                    schema_exists = exists > 0;
+
+                    if schema_exists {
+                        let granted: bool = query_scalar(super::postgres::SCHEMA_PRIVILEGE)
+                            .bind(schema)
+                            .fetch_one(&mut *self)
+                            .await?;
+
+                        if !granted {
+                            missing.push(missing_privilege(
+                                "CREATE",
+                                format!("SCHEMA {}", quote_ident(schema)),
+                                &user,
+                            ));
+                        }
+                    }
+                }
+
+                if !schema_exists {
+                    let granted: bool = query_scalar(super::postgres::DATABASE_PRIVILEGE)
+                        .fetch_one(&mut *self)
+                        .await?;
+
+                    if !granted {
+                        missing.push(missing_privilege(
+                            "CREATE",
+                            format!("DATABASE {}", quote_ident(&database)),
+                            &user,
+                        ));
+                    }
+                }
+
+                let exists: bool = query_scalar(super::postgres::TABLE_EXISTS)
+                    .bind(table_name)
+                    .fetch_one(&mut *self)
+                    .await?;
+
+                if exists {
+                    for privilege in super::postgres::TABLE_PRIVILEGES {
+                        let granted: bool = query_scalar(super::postgres::TABLE_PRIVILEGE)
+                            .bind(table_name)
+                            .bind(privilege)
+                            .fetch_one(&mut *self)
+                            .await?;
+
+                        if !granted {
+                            missing.push(missing_privilege(
+                                privilege,
+                                format!("TABLE {table_name}"),
+                                &user,
+                            ));
+                        }
+                    }
+                }
+
+                Ok(missing)
+            }
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => {
+                let mut tx = self.begin().await?;
+                let res = query(super::sqlite::WRITE_CHECK).execute(&mut *tx).await;
+                tx.rollback().await?;
+
+                match res {
+                    Ok(_) => Ok(Vec::new()),
+                    Err(error) if super::is_permission_denied(&error) => {
+                        Ok(vec![super::sqlite::read_only()])
+                    }
+                    Err(error) => Err(error),
+                }
+            }
+        }
+    }
+
+    async fn dump_schema(
+        &mut self,
+        excluded_tables: &[String],
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let excluded_tables = excluded_tables.join("\n");
+
+        match dialect(self)? {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => {
+                let mut statements: Vec<String> = Vec::new();
+
+                for (sql, excludes_tables) in super::postgres::DUMP_QUERIES {
+                    let mut query = query_scalar(sql);
+
+                    if excludes_tables {
+                        query = query.bind(&excluded_tables);
+                    }
+
+                    statements.extend(query.fetch_all(&mut *self).await?);
+                }
+
+                if !statements.is_empty() {
+                    statements.insert(0, super::postgres::DUMP_PREAMBLE.into());
+                }
+
+                Ok(statements)
+            }
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => {
+                query_scalar(super::sqlite::DUMP_SCHEMA)
+                    .bind(excluded_tables)
+                    .fetch_all(self)
+                    .await
+            }
+        }
+    }
+}
+
+#[cfg(feature = "postgres")]
+async fn lock_id(conn: &mut AnyConnection) -> Result<i64, sqlx::Error> {
+    let database_name: String = query_scalar("SELECT current_database()::TEXT")
+        .fetch_one(conn)
+        .await?;
+
+    Ok(super::postgres::generate_lock_id(&database_name))
+}
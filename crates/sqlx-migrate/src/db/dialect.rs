@@ -0,0 +1,387 @@
+//! The bookkeeping SQL of the supported databases.
+//!
+//! The statements only differ in placeholders, the current time and
+//! statements that are not supported everywhere (e.g. `TRUNCATE` in SQLite),
+//! so the backends share the SQL generated here.
+
+use std::{
+    borrow::Cow,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use sqlx::{query, query_scalar, ColumnIndex, Database, Decode, Encode, Executor, Type};
+
+use super::{AppliedMigration, MigrationRun, Release};
+
+/// The SQL dialect of a database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Dialect {
+    #[cfg(feature = "postgres")]
+    Postgres,
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+/// The columns missing from tables created by older versions, as `(name, definition)`.
+pub(super) const ADDED_COLUMNS: [(&str, &str); 3] = [
+    ("phase", "phase TEXT"),
+    ("skipped", "skipped BOOLEAN NOT NULL DEFAULT FALSE"),
+    ("rows_affected", "rows_affected BIGINT"),
+];
+
+/// A row of [`Dialect::list_releases`].
+pub(super) type ReleaseRow = (String, i64, i64);
+
+/// A row of [`Dialect::list_runs`].
+pub(super) type RunRow = (Option<i64>, String, i64, i64);
+
+/// A row of [`Dialect::list_migrations`].
+pub(super) type MigrationRow = (
+    i64,
+    String,
+    Vec<u8>,
+    i64,
+    Option<String>,
+    i64,
+    i64,
+    Option<i64>,
+);
+
+impl Dialect {
+    /// The placeholder of the positional parameter with the given index, starting from 1.
+    fn param(self, idx: usize) -> String {
+        match self {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => format!("${idx}"),
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => format!("?{idx}"),
+        }
+    }
+
+    /// The current time in the type of the `applied_on` and `scheduled_on` columns.
+    fn now(self) -> &'static str {
+        match self {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => "now()",
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => "CAST(strftime('%s', 'now') AS INTEGER)",
+        }
+    }
+
+    /// Count the columns of the table (`1`) with the given name (`2`).
+    pub(super) fn column_count(self) -> String {
+        let (table, column) = (self.param(1), self.param(2));
+
+        match self {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => format!(
+                r#"
+                SELECT COUNT(*) FROM pg_attribute
+                WHERE attrelid = to_regclass({table}) AND attname = {column} AND NOT attisdropped
+                "#
+            ),
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => {
+                format!(r#"SELECT COUNT(*) FROM pragma_table_info({table}) WHERE name = {column}"#)
+            }
+        }
+    }
+
+    /// Count the unique indexes (including primary keys) of the table (`1`)
+    /// on the `version` column alone.
+    pub(super) fn unique_version_count(self) -> String {
+        let table = self.param(1);
+
+        match self {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => format!(
+                r#"
+                SELECT COUNT(*) FROM pg_index i
+                JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = i.indkey[0]
+                WHERE i.indrelid = to_regclass({table}) AND i.indisunique
+                    AND i.indnatts = 1 AND a.attname = 'version'
+                "#
+            ),
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => format!(
+                r#"
+                SELECT COUNT(*) FROM pragma_index_list({table}) l
+                WHERE l."unique"
+                    AND (SELECT COUNT(*) FROM pragma_index_info(l.name)) = 1
+                    AND EXISTS (SELECT 1 FROM pragma_index_info(l.name) WHERE name = 'version')
+                "#
+            ),
+        }
+    }
+
+    /// The applied migrations ordered by version, see [`applied_migration`].
+    pub(super) fn list_migrations(self, table_name: &str) -> String {
+        // Booleans are not decoded consistently by all drivers,
+        // the time the migrations were applied is in microseconds.
+        let (skipped, applied_on) = match self {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => (
+                "(CASE WHEN skipped THEN 1 ELSE 0 END)::BIGINT",
+                "(EXTRACT(EPOCH FROM applied_on) * 1000000)::BIGINT",
+            ),
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => (
+                "CASE WHEN skipped THEN 1 ELSE 0 END",
+                "applied_on * 1000000",
+            ),
+        };
+
+        format!(
+            r#"
+            SELECT
+                version,
+                name,
+                checksum,
+                execution_time,
+                phase,
+                {skipped},
+                {applied_on},
+                rows_affected
+            FROM
+                {table_name}
+            ORDER BY version
+            "#
+        )
+    }
+
+    /// Record a migration with the version (`1`), name (`2`), checksum (`3`),
+    /// execution time (`4`), phase (`5`), skipped (`6`) and rows affected (`7`).
+    pub(super) fn insert_migration(self, table_name: &str) -> String {
+        let params = (1..=7).map(|idx| self.param(idx)).collect::<Vec<_>>();
+        let now = self.now();
+
+        format!(
+            r#"
+                INSERT INTO {table_name} ( version, name, checksum, execution_time, phase, skipped, rows_affected, applied_on )
+                VALUES ( {}, {now} )
+            "#,
+            params.join(", ")
+        )
+    }
+
+    /// Remove the migration with the given version (`1`).
+    pub(super) fn remove_migration(self, table_name: &str) -> String {
+        format!(
+            r#"DELETE FROM {table_name} WHERE version = {}"#,
+            self.param(1)
+        )
+    }
+
+    /// Set the name (`1`) of the migration with the given version (`2`).
+    pub(super) fn rename_migration(self, table_name: &str) -> String {
+        format!(
+            r#"UPDATE {table_name} SET name = {} WHERE version = {}"#,
+            self.param(1),
+            self.param(2)
+        )
+    }
+
+    /// Remove all migrations, SQLite has no `TRUNCATE`.
+    pub(super) fn clear_migrations(self, table_name: &str) -> String {
+        match self {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => format!("TRUNCATE {table_name}"),
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => format!("DELETE FROM {table_name}"),
+        }
+    }
+
+    /// Record a run with the version (`1`), name (`2`) and execution time (`3`).
+    pub(super) fn insert_run(self, table_name: &str) -> String {
+        format!(
+            r#"
+                INSERT INTO {table_name} ( version, name, execution_time, ran_on )
+                VALUES ( {}, {}, {}, {} )
+            "#,
+            self.param(1),
+            self.param(2),
+            self.param(3),
+            self.now()
+        )
+    }
+
+    /// The timestamp in the given column as microseconds since the Unix epoch.
+    fn epoch_micros(self, column: &str) -> String {
+        match self {
+            #[cfg(feature = "postgres")]
+            Dialect::Postgres => format!("(EXTRACT(EPOCH FROM {column}) * 1000000)::BIGINT"),
+            #[cfg(feature = "sqlite")]
+            Dialect::Sqlite => format!("{column} * 1000000"),
+        }
+    }
+
+    /// The recorded runs in the order they were recorded, see [`migration_run`].
+    pub(super) fn list_runs(self, table_name: &str) -> String {
+        let ran_on = self.epoch_micros("ran_on");
+
+        format!(
+            r#"
+            SELECT
+                version,
+                name,
+                execution_time,
+                {ran_on}
+            FROM
+                {table_name}
+            ORDER BY id
+            "#
+        )
+    }
+
+    /// Tag the version (`2`) as the release with the given name (`1`),
+    /// a release with the same name is moved to the version.
+    ///
+    /// Returns the tagged release, see [`release`].
+    pub(super) fn tag_release(self, table_name: &str) -> String {
+        format!(
+            r#"
+                INSERT INTO {table_name} ( name, version, tagged_on )
+                VALUES ( {}, {}, {} )
+                ON CONFLICT ( name ) DO UPDATE
+                SET version = excluded.version, tagged_on = excluded.tagged_on
+                RETURNING name, version, {}
+            "#,
+            self.param(1),
+            self.param(2),
+            self.now(),
+            self.epoch_micros("tagged_on")
+        )
+    }
+
+    /// The releases ordered by their versions, see [`release`].
+    pub(super) fn list_releases(self, table_name: &str) -> String {
+        let tagged_on = self.epoch_micros("tagged_on");
+
+        format!(
+            r#"
+            SELECT
+                name,
+                version,
+                {tagged_on}
+            FROM
+                {table_name}
+            ORDER BY version, tagged_on
+            "#
+        )
+    }
+
+    /// Schedule the deferred migration with the given name (`1`).
+    pub(super) fn schedule_deferred(self, table_name: &str) -> String {
+        format!(
+            r#"
+                INSERT INTO {table_name} ( name, state, scheduled_on )
+                VALUES ( {}, 'scheduled', {} )
+                ON CONFLICT ( name ) DO NOTHING
+            "#,
+            self.param(1),
+            self.now()
+        )
+    }
+
+    /// Claim the deferred migration with the given name (`1`).
+    pub(super) fn claim_deferred(self, table_name: &str) -> String {
+        format!(
+            r#"
+                UPDATE {table_name} SET state = 'running'
+                WHERE name = {} AND state IN ('scheduled', 'failed')
+            "#,
+            self.param(1)
+        )
+    }
+
+    /// Set the state (`2`), error (`3`) and execution time (`4`)
+    /// of the deferred migration with the given name (`1`).
+    pub(super) fn finish_deferred(self, table_name: &str) -> String {
+        format!(
+            r#"
+                UPDATE {table_name} SET state = {}, error = {}, execution_time = {}
+                WHERE name = {}
+            "#,
+            self.param(2),
+            self.param(3),
+            self.param(4),
+            self.param(1)
+        )
+    }
+}
+
+/// An applied migration from a row of [`Dialect::list_migrations`].
+pub(super) fn applied_migration(row: MigrationRow) -> AppliedMigration<'static> {
+    AppliedMigration {
+        version: row.0 as u64,
+        name: Cow::Owned(row.1),
+        checksum: Cow::Owned(row.2),
+        execution_time: Duration::from_nanos(row.3 as _),
+        phase: row.4.and_then(|phase| phase.parse().ok()),
+        skipped: row.5 != 0,
+        applied_on: Some(UNIX_EPOCH + Duration::from_micros(row.6 as u64)),
+        rows_affected: row.7.map(|rows| rows as u64),
+    }
+}
+
+/// A recorded run from a row of [`Dialect::list_runs`].
+pub(super) fn migration_run(row: RunRow) -> MigrationRun {
+    MigrationRun {
+        version: row.0.map(|version| version as u64),
+        name: row.1,
+        execution_time: Duration::from_nanos(row.2 as _),
+        ran_on: Some(UNIX_EPOCH + Duration::from_micros(row.3 as u64)),
+    }
+}
+
+/// A release from a row of [`Dialect::list_releases`].
+pub(super) fn release(row: ReleaseRow) -> Release {
+    Release {
+        name: row.0,
+        version: row.1 as u64,
+        tagged_on: Some(UNIX_EPOCH + Duration::from_micros(row.2 as u64)),
+    }
+}
+
+/// Make the versions of the migrations table unique, for tables
+/// created by custom DDL without a primary key.
+pub(super) fn unique_version(table_name: &str) -> String {
+    // Indexes are created in the schema of the table.
+    let index = table_name
+        .rsplit('.')
+        .next()
+        .unwrap_or(table_name)
+        .trim_matches('"');
+
+    format!(r#"CREATE UNIQUE INDEX IF NOT EXISTS "{index}_version_key" ON {table_name} (version)"#)
+}
+
+/// Create the unique version index of the table unless the version column is
+/// already unique, so that concurrent migrators cannot record the same
+/// version twice.
+pub(super) async fn ensure_unique_versions<DB>(
+    conn: &mut DB::Connection,
+    dialect: Dialect,
+    table_name: &str,
+) -> Result<(), sqlx::Error>
+where
+    DB: Database,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> <DB as Database>::Arguments<'q>: sqlx::IntoArguments<'q, DB>,
+    for<'q> &'q str: Encode<'q, DB> + Type<DB>,
+    i64: for<'r> Decode<'r, DB> + Type<DB>,
+    usize: ColumnIndex<DB::Row>,
+{
+    let unique_versions: i64 = query_scalar(&dialect.unique_version_count())
+        .bind(table_name)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    if unique_versions == 0 {
+        query(&unique_version(table_name))
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    Ok(())
+}
@@ -1,29 +1,54 @@
-use std::{borrow::Cow, time::Duration};
+use std::time::Duration;
 
 use async_trait::async_trait;
 use sqlx::{query, query_as, query_scalar, PgConnection};
 
-use super::AppliedMigration;
+use super::{
+    dialect::{
+        applied_migration, ensure_unique_versions, migration_run, release, Dialect, MigrationRow,
+        ReleaseRow, RunRow, ADDED_COLUMNS,
+    },
+    DeferredMigration, DeferredState, MigrationRun, MissingPrivilege, Release, TableStats,
+};
+use crate::MigrationPhase;
+
+const DIALECT: Dialect = Dialect::Postgres;
 
 #[async_trait(?Send)]
 impl super::Migrations for sqlx::PgConnection {
     async fn ensure_migrations_table(&mut self, table_name: &str) -> Result<(), sqlx::Error> {
-        query(&format!(
-            r#"
-                CREATE TABLE IF NOT EXISTS {} (
-                    version BIGINT PRIMARY KEY,
-                    name TEXT NOT NULL,
-                    applied_on TIMESTAMPTZ NOT NULL DEFAULT now(),
-                    checksum BYTEA NOT NULL,
-                    execution_time BIGINT NOT NULL
-                );
-                "#,
-            table_name
-        ))
-        .execute(self)
-        .await?;
+        // DDL is rejected on read replicas even if the table exists.
+        let exists: bool = query_scalar(TABLE_EXISTS)
+            .bind(table_name)
+            .fetch_one(&mut *self)
+            .await?;
 
-        Ok(())
+        if !exists {
+            query(&migrations_table(table_name))
+                .execute(&mut *self)
+                .await?;
+        }
+
+        // Tables created by older versions lack some of the columns.
+        let column_count = DIALECT.column_count();
+
+        for (column, definition) in ADDED_COLUMNS {
+            let columns: i64 = query_scalar(&column_count)
+                .bind(table_name)
+                .bind(column)
+                .fetch_one(&mut *self)
+                .await?;
+
+            if columns == 0 {
+                query(&format!(
+                    r#"ALTER TABLE {table_name} ADD COLUMN {definition}"#
+                ))
+                .execute(&mut *self)
+                .await?;
+            }
+        }
+
+        ensure_unique_versions::<sqlx::Postgres>(self, DIALECT, table_name).await
     }
 
     async fn lock(&mut self) -> Result<(), sqlx::Error> {
@@ -45,6 +70,17 @@ impl super::Migrations for sqlx::PgConnection {
         Ok(())
     }
 
+    async fn try_lock(&mut self) -> Result<bool, sqlx::Error> {
+        let database_name = current_database(self).await?;
+        let lock_id = generate_lock_id(&database_name);
+
+        // language=SQL
+        query_scalar("SELECT pg_try_advisory_lock($1)")
+            .bind(lock_id)
+            .fetch_one(self)
+            .await
+    }
+
     async fn unlock(&mut self) -> Result<(), sqlx::Error> {
         let database_name = current_database(self).await?;
         let lock_id = generate_lock_id(&database_name);
@@ -58,80 +94,818 @@ impl super::Migrations for sqlx::PgConnection {
         Ok(())
     }
 
+    async fn lock_held(&mut self) -> Result<bool, sqlx::Error> {
+        let database_name = current_database(self).await?;
+        let lock_id = generate_lock_id(&database_name);
+
+        query_scalar(LOCK_HELD).bind(lock_id).fetch_one(self).await
+    }
+
     async fn list_migrations(
         &mut self,
         table_name: &str,
     ) -> Result<Vec<super::AppliedMigration<'static>>, sqlx::Error> {
-        let rows: Vec<(i64, String, Vec<u8>, i64)> = query_as(&format!(
+        let rows: Vec<MigrationRow> = query_as(&DIALECT.list_migrations(table_name))
+            .fetch_all(self)
+            .await?;
+
+        Ok(rows.into_iter().map(applied_migration).collect())
+    }
+
+    async fn add_migration(
+        &mut self,
+        table_name: &str,
+        migration: super::AppliedMigration<'static>,
+    ) -> Result<(), sqlx::Error> {
+        query(&DIALECT.insert_migration(table_name))
+            .bind(migration.version as i64)
+            .bind(&*migration.name.clone())
+            .bind(&*migration.checksum.clone())
+            .bind(migration.execution_time.as_nanos() as i64)
+            .bind(migration.phase.map(MigrationPhase::as_str))
+            .bind(migration.skipped)
+            .bind(migration.rows_affected.map(|rows| rows as i64))
+            .execute(self)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn remove_migration(
+        &mut self,
+        table_name: &str,
+        version: u64,
+    ) -> Result<(), sqlx::Error> {
+        query(&DIALECT.remove_migration(table_name))
+            .bind(version as i64)
+            .execute(self)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn rename_migration(
+        &mut self,
+        table_name: &str,
+        version: u64,
+        name: &str,
+    ) -> Result<(), sqlx::Error> {
+        query(&DIALECT.rename_migration(table_name))
+            .bind(name)
+            .bind(version as i64)
+            .execute(self)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn clear_migrations(&mut self, table_name: &str) -> Result<(), sqlx::Error> {
+        query(&DIALECT.clear_migrations(table_name))
+            .execute(self)
+            .await?;
+        Ok(())
+    }
+
+    async fn ensure_deferred_table(&mut self, table_name: &str) -> Result<(), sqlx::Error> {
+        query(&deferred_table(table_name)).execute(self).await?;
+
+        Ok(())
+    }
+
+    async fn ensure_runs_table(&mut self, table_name: &str) -> Result<(), sqlx::Error> {
+        query(&runs_table(table_name)).execute(self).await?;
+
+        Ok(())
+    }
+
+    async fn add_run(&mut self, table_name: &str, run: &MigrationRun) -> Result<(), sqlx::Error> {
+        query(&DIALECT.insert_run(table_name))
+            .bind(run.version.map(|version| version as i64))
+            .bind(&run.name)
+            .bind(run.execution_time.as_nanos() as i64)
+            .execute(self)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list_runs(&mut self, table_name: &str) -> Result<Vec<MigrationRun>, sqlx::Error> {
+        let rows: Vec<RunRow> = query_as(&DIALECT.list_runs(table_name))
+            .fetch_all(self)
+            .await?;
+
+        Ok(rows.into_iter().map(migration_run).collect())
+    }
+
+    async fn ensure_releases_table(&mut self, table_name: &str) -> Result<(), sqlx::Error> {
+        query(&releases_table(table_name)).execute(self).await?;
+
+        Ok(())
+    }
+
+    async fn tag_release(
+        &mut self,
+        table_name: &str,
+        name: &str,
+        version: u64,
+    ) -> Result<Release, sqlx::Error> {
+        let row: ReleaseRow = query_as(&DIALECT.tag_release(table_name))
+            .bind(name)
+            .bind(version as i64)
+            .fetch_one(self)
+            .await?;
+
+        Ok(release(row))
+    }
+
+    async fn list_releases(&mut self, table_name: &str) -> Result<Vec<Release>, sqlx::Error> {
+        let rows: Vec<ReleaseRow> = query_as(&DIALECT.list_releases(table_name))
+            .fetch_all(self)
+            .await?;
+
+        Ok(rows.into_iter().map(release).collect())
+    }
+
+    async fn schedule_deferred(&mut self, table_name: &str, name: &str) -> Result<(), sqlx::Error> {
+        query(&DIALECT.schedule_deferred(table_name))
+            .bind(name)
+            .execute(self)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list_deferred(
+        &mut self,
+        table_name: &str,
+    ) -> Result<Vec<DeferredMigration>, sqlx::Error> {
+        let rows: Vec<(String, String, Option<String>, Option<i64>)> = query_as(&format!(
             r#"
             SELECT
-                version,
                 name,
-                checksum,
+                state,
+                error,
                 execution_time
             FROM
-                {}
-            ORDER BY version
-            "#,
-            table_name
+                {table_name}
+            ORDER BY scheduled_on, name
+            "#
         ))
         .fetch_all(self)
         .await?;
 
         Ok(rows
             .into_iter()
-            .map(|row| AppliedMigration {
-                version: row.0 as u64,
-                name: Cow::Owned(row.1),
-                checksum: Cow::Owned(row.2),
-                execution_time: Duration::from_nanos(row.3 as _),
+            .map(|row| DeferredMigration {
+                name: row.0,
+                state: row.1.parse().unwrap_or(DeferredState::Failed),
+                error: row.2,
+                execution_time: row.3.map(|t| Duration::from_nanos(t as _)),
             })
             .collect())
     }
 
-    async fn add_migration(
-        &mut self,
-        table_name: &str,
-        migration: super::AppliedMigration<'static>,
-    ) -> Result<(), sqlx::Error> {
-        query(&format!(
-            r#"
-                INSERT INTO {} ( version, name, checksum, execution_time )
-                VALUES ( $1, $2, $3, $4 )
-            "#,
-            table_name
-        ))
-        .bind(migration.version as i64)
-        .bind(&*migration.name.clone())
-        .bind(&*migration.checksum.clone())
-        .bind(migration.execution_time.as_nanos() as i64)
-        .execute(self)
-        .await?;
+    async fn claim_deferred(&mut self, table_name: &str, name: &str) -> Result<bool, sqlx::Error> {
+        let res = query(&DIALECT.claim_deferred(table_name))
+            .bind(name)
+            .execute(self)
+            .await?;
 
-        Ok(())
+        Ok(res.rows_affected() == 1)
     }
 
-    async fn remove_migration(
+    async fn finish_deferred(
         &mut self,
         table_name: &str,
-        version: u64,
+        name: &str,
+        execution_time: Duration,
+        error: Option<&str>,
     ) -> Result<(), sqlx::Error> {
-        query(&format!(r#"DELETE FROM {} WHERE version = $1"#, table_name))
-            .bind(version as i64)
+        query(&DIALECT.finish_deferred(table_name))
+            .bind(name)
+            .bind(if error.is_some() {
+                DeferredState::Failed.as_str()
+            } else {
+                DeferredState::Completed.as_str()
+            })
+            .bind(error)
+            .bind(execution_time.as_nanos() as i64)
             .execute(self)
             .await?;
 
         Ok(())
     }
 
-    async fn clear_migrations(&mut self, table_name: &str) -> Result<(), sqlx::Error> {
-        query(&format!("TRUNCATE {}", table_name))
+    async fn table_stats(&mut self, table: &str) -> Result<Option<TableStats>, sqlx::Error> {
+        let stats: Option<(i64, i64)> = query_as(TABLE_STATS)
+            .bind(table)
+            .fetch_optional(self)
+            .await?;
+
+        Ok(stats.map(|(rows, bytes)| TableStats {
+            rows: rows as u64,
+            bytes: bytes as u64,
+        }))
+    }
+
+    async fn explain(&mut self, statement: &str) -> Result<Option<String>, sqlx::Error> {
+        let plan: Vec<String> = query_scalar(&explain(statement)).fetch_all(self).await?;
+        Ok(Some(plan.join("\n")))
+    }
+
+    async fn rehearsal_database(&mut self, name: &str) -> Result<Option<Self>, sqlx::Error> {
+        query(&create_schema(name)).execute(&mut *self).await?;
+        query(REHEARSAL_SEARCH_PATH)
+            .bind(name)
             .execute(self)
             .await?;
+        Ok(None)
+    }
+
+    async fn table_columns(&mut self, table: &str) -> Result<Vec<String>, sqlx::Error> {
+        query_scalar(TABLE_COLUMNS)
+            .bind(table)
+            .fetch_all(self)
+            .await
+    }
+
+    async fn ensure_extensions(&mut self, extensions: &[String]) -> Result<(), sqlx::Error> {
+        for extension in extensions {
+            let exists: i64 = query_scalar(EXTENSION_EXISTS)
+                .bind(extension)
+                .fetch_one(&mut *self)
+                .await?;
+
+            if exists == 0 {
+                query(&create_extension(extension))
+                    .execute(&mut *self)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn ensure_schemas(&mut self, table: &str) -> Result<(), sqlx::Error> {
+        let search_path: String = query_scalar(SEARCH_PATH).fetch_one(&mut *self).await?;
+
+        for schema in bootstrap_schemas(table, &search_path) {
+            let exists: i64 = query_scalar(SCHEMA_EXISTS)
+                .bind(&schema)
+                .fetch_one(&mut *self)
+                .await?;
+
+            if exists == 0 {
+                query(&create_schema(&schema)).execute(&mut *self).await?;
+            }
+        }
+
         Ok(())
     }
+
+    async fn missing_privileges(
+        &mut self,
+        table_name: &str,
+    ) -> Result<Vec<MissingPrivilege>, sqlx::Error> {
+        let (user, database, current_schema): (String, String, Option<String>) =
+            query_as(PRIVILEGE_CONTEXT).fetch_one(&mut *self).await?;
+
+        let schema = bootstrap_schemas(table_name, "")
+            .into_iter()
+            .next()
+            .or(current_schema);
+
+        let mut missing = Vec::new();
+        let mut schema_exists = false;
+
+        if let Some(schema) = &schema {
+            let exists: i64 = query_scalar(SCHEMA_EXISTS)
+                .bind(schema)
+                .fetch_one(&mut *self)
+                .await?;
+
+            schema_exists = exists > 0;
+
+            if schema_exists {
+                let granted: bool = query_scalar(SCHEMA_PRIVILEGE)
+                    .bind(schema)
+                    .fetch_one(&mut *self)
+                    .await?;
+
+                if !granted {
+                    missing.push(missing_privilege(
+                        "CREATE",
+                        format!("SCHEMA {}", quote_ident(schema)),
+                        &user,
+                    ));
+                }
+            }
+        }
+
+        // The schema has to be created first.
+        if !schema_exists {
+            let granted: bool = query_scalar(DATABASE_PRIVILEGE)
+                .fetch_one(&mut *self)
+                .await?;
+
+            if !granted {
+                missing.push(missing_privilege(
+                    "CREATE",
+                    format!("DATABASE {}", quote_ident(&database)),
+                    &user,
+                ));
+            }
+        }
+
+        let exists: bool = query_scalar(TABLE_EXISTS)
+            .bind(table_name)
+            .fetch_one(&mut *self)
+            .await?;
+
+        if exists {
+            for privilege in TABLE_PRIVILEGES {
+                let granted: bool = query_scalar(TABLE_PRIVILEGE)
+                    .bind(table_name)
+                    .bind(privilege)
+                    .fetch_one(&mut *self)
+                    .await?;
+
+                if !granted {
+                    missing.push(missing_privilege(
+                        privilege,
+                        format!("TABLE {table_name}"),
+                        &user,
+                    ));
+                }
+            }
+        }
+
+        Ok(missing)
+    }
+
+    async fn dump_schema(
+        &mut self,
+        excluded_tables: &[String],
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let excluded_tables = excluded_tables.join("\n");
+        let mut statements: Vec<String> = Vec::new();
+
+        for (sql, excludes_tables) in DUMP_QUERIES {
+            let mut query = query_scalar(sql);
+
+            if excludes_tables {
+                query = query.bind(&excluded_tables);
+            }
+
+            statements.extend(query.fetch_all(&mut *self).await?);
+        }
+
+        if !statements.is_empty() {
+            statements.insert(0, DUMP_PREAMBLE.into());
+        }
+
+        Ok(statements)
+    }
+}
+
+pub(super) fn migrations_table(table_name: &str) -> String {
+    format!(
+        r#"
+            CREATE TABLE IF NOT EXISTS {table_name} (
+                version BIGINT PRIMARY KEY,
+                name TEXT NOT NULL,
+                applied_on TIMESTAMPTZ NOT NULL DEFAULT now(),
+                checksum BYTEA NOT NULL,
+                execution_time BIGINT NOT NULL,
+                phase TEXT,
+                skipped BOOLEAN NOT NULL DEFAULT FALSE,
+                rows_affected BIGINT
+            );
+            "#
+    )
 }
 
+pub(super) fn deferred_table(table_name: &str) -> String {
+    format!(
+        r#"
+            CREATE TABLE IF NOT EXISTS {table_name} (
+                name TEXT PRIMARY KEY,
+                state TEXT NOT NULL,
+                scheduled_on TIMESTAMPTZ NOT NULL DEFAULT now(),
+                error TEXT,
+                execution_time BIGINT
+            );
+            "#
+    )
+}
+
+pub(super) fn runs_table(table_name: &str) -> String {
+    format!(
+        r#"
+            CREATE TABLE IF NOT EXISTS {table_name} (
+                id BIGSERIAL PRIMARY KEY,
+                version BIGINT,
+                name TEXT NOT NULL,
+                execution_time BIGINT NOT NULL,
+                ran_on TIMESTAMPTZ NOT NULL DEFAULT now()
+            );
+            "#
+    )
+}
+
+pub(super) fn releases_table(table_name: &str) -> String {
+    format!(
+        r#"
+            CREATE TABLE IF NOT EXISTS {table_name} (
+                name TEXT PRIMARY KEY,
+                version BIGINT NOT NULL,
+                tagged_on TIMESTAMPTZ NOT NULL DEFAULT now()
+            );
+            "#
+    )
+}
+
+pub(super) const EXTENSION_EXISTS: &str = "SELECT COUNT(*) FROM pg_extension WHERE extname = $1";
+
+pub(super) fn create_extension(name: &str) -> String {
+    format!(
+        r#"CREATE EXTENSION IF NOT EXISTS "{}""#,
+        name.replace('"', r#""""#)
+    )
+}
+
+pub(super) const SEARCH_PATH: &str = "SELECT current_setting('search_path')";
+
+pub(super) const SCHEMA_EXISTS: &str = "SELECT COUNT(*) FROM pg_namespace WHERE nspname = $1";
+
+pub(super) fn create_schema(name: &str) -> String {
+    format!("CREATE SCHEMA IF NOT EXISTS {}", quote_ident(name))
+}
+
+pub(super) fn quote_ident(name: &str) -> String {
+    format!(r#""{}""#, name.replace('"', r#""""#))
+}
+
+// Objects are created in the scratch schema (`$1`), existing tables are not
+// visible, only the schemas of installed extensions remain on the path.
+pub(super) const REHEARSAL_SEARCH_PATH: &str = r#"
+    SELECT set_config(
+        'search_path',
+        array_to_string(
+            array_prepend(
+                quote_ident($1),
+                ARRAY(
+                    SELECT DISTINCT quote_ident(n.nspname)
+                    FROM pg_extension e
+                    JOIN pg_namespace n ON n.oid = e.extnamespace
+                    WHERE n.nspname NOT IN ('pg_catalog', $1)
+                    ORDER BY 1
+                )
+            ),
+            ', '
+        ),
+        true
+    )
+"#;
+
+pub(super) const PRIVILEGE_CONTEXT: &str =
+    "SELECT current_user::TEXT, current_database()::TEXT, current_schema()::TEXT";
+
+pub(super) const SCHEMA_PRIVILEGE: &str = "SELECT has_schema_privilege($1, 'CREATE')";
+
+pub(super) const DATABASE_PRIVILEGE: &str =
+    "SELECT has_database_privilege(current_database(), 'CREATE')";
+
+pub(super) const TABLE_PRIVILEGE: &str = "SELECT has_table_privilege($1, $2)";
+
+// The privileges on the migrations table used by the migrator.
+pub(super) const TABLE_PRIVILEGES: [&str; 4] = ["SELECT", "INSERT", "UPDATE", "DELETE"];
+
+pub(super) fn missing_privilege(privilege: &str, object: String, user: &str) -> MissingPrivilege {
+    MissingPrivilege {
+        privilege: privilege.into(),
+        grant: Some(format!(
+            "GRANT {privilege} ON {object} TO {}",
+            quote_ident(user)
+        )),
+        object,
+    }
+}
+
+// The schema of the table followed by the schemas in the search path,
+// `$user` and the system schemas are skipped.
+pub(super) fn bootstrap_schemas(table: &str, search_path: &str) -> Vec<String> {
+    let table_schema = table.rsplit_once('.').map(|(schema, _)| schema);
+
+    let mut schemas: Vec<String> = Vec::new();
+
+    for schema in table_schema.into_iter().chain(search_path.split(',')) {
+        let schema = schema.trim();
+
+        let schema = match schema
+            .strip_prefix('"')
+            .and_then(|schema| schema.strip_suffix('"'))
+        {
+            Some(quoted) => quoted.replace(r#""""#, "\""),
+            None => schema.to_lowercase(),
+        };
+
+        if schema.is_empty()
+            || schema == "$user"
+            || schema.starts_with("pg_")
+            || schemas.contains(&schema)
+        {
+            continue;
+        }
+
+        schemas.push(schema);
+    }
+
+    schemas
+}
+
+pub(super) fn explain(statement: &str) -> String {
+    format!("EXPLAIN {statement}")
+}
+
+pub(super) const TABLE_EXISTS: &str = "SELECT to_regclass($1) IS NOT NULL";
+
+pub(super) const TABLE_COLUMNS: &str = r#"
+    SELECT attname::TEXT
+    FROM pg_attribute
+    WHERE attrelid = to_regclass($1) AND attnum > 0 AND NOT attisdropped
+    ORDER BY attnum
+"#;
+
+// `reltuples` is -1 for tables that were never analyzed.
+pub(super) const TABLE_STATS: &str = r#"
+    SELECT
+        greatest(c.reltuples, 0)::BIGINT,
+        pg_total_relation_size(c.oid)
+    FROM
+        pg_class c
+    WHERE
+        c.oid = to_regclass($1)
+"#;
+
+// The queries for dumping the schema in order, and whether they exclude
+// the tables given as the first parameter, see `not_excluded`.
+pub(super) const DUMP_QUERIES: [(&str, bool); 11] = [
+    (DUMP_SCHEMAS, false),
+    (DUMP_EXTENSIONS, false),
+    (DUMP_ENUMS, false),
+    (DUMP_SEQUENCES, true),
+    (DUMP_FUNCTIONS, false),
+    (DUMP_TABLES, true),
+    (DUMP_SEQUENCE_OWNERS, true),
+    (DUMP_CONSTRAINTS, true),
+    (DUMP_INDEXES, true),
+    (DUMP_VIEWS, false),
+    (DUMP_TRIGGERS, false),
+];
+
+pub(super) const DUMP_PREAMBLE: &str = "SET LOCAL check_function_bodies = false;";
+
+// Filters objects created by users, the object must have the `oid` column
+// and the namespace must be joined as `n`.
+macro_rules! user_object {
+    ($oid:literal) => {
+        concat!(
+            "n.nspname NOT IN ('pg_catalog', 'information_schema') ",
+            "AND n.nspname NOT LIKE 'pg\\_%' ",
+            "AND NOT EXISTS (SELECT 1 FROM pg_depend dep WHERE dep.objid = ",
+            $oid,
+            " AND dep.deptype = 'e')"
+        )
+    };
+}
+
+// Filters objects of tables that are not excluded, the names of the excluded
+// tables separated by newlines are the first parameter.
+macro_rules! not_excluded {
+    ($oid:literal) => {
+        concat!(
+            "NOT EXISTS (SELECT 1 FROM unnest(string_to_array($1, E'\\n')) t ",
+            "WHERE to_regclass(t) = ",
+            $oid,
+            ")"
+        )
+    };
+}
+
+const DUMP_SCHEMAS: &str = concat!(
+    r#"
+    SELECT format('CREATE SCHEMA IF NOT EXISTS %I;', n.nspname)
+    FROM pg_namespace n
+    WHERE n.nspname <> 'public' AND "#,
+    user_object!("n.oid"),
+    r#"
+    ORDER BY n.nspname
+    "#
+);
+
+const DUMP_EXTENSIONS: &str = r#"
+    SELECT format('CREATE EXTENSION IF NOT EXISTS %I WITH SCHEMA %I;', e.extname, n.nspname)
+    FROM pg_extension e
+    JOIN pg_namespace n ON n.oid = e.extnamespace
+    WHERE e.extname <> 'plpgsql'
+    ORDER BY e.extname
+"#;
+
+const DUMP_ENUMS: &str = concat!(
+    r#"
+    SELECT format(
+        'CREATE TYPE %I.%I AS ENUM (%s);',
+        n.nspname,
+        t.typname,
+        (
+            SELECT string_agg(quote_literal(e.enumlabel), ', ' ORDER BY e.enumsortorder)
+            FROM pg_enum e
+            WHERE e.enumtypid = t.oid
+        )
+    )
+    FROM pg_type t
+    JOIN pg_namespace n ON n.oid = t.typnamespace
+    WHERE t.typtype = 'e' AND "#,
+    user_object!("t.oid"),
+    r#"
+    ORDER BY n.nspname, t.typname
+    "#
+);
+
+// Identity sequences are created along with their tables,
+// sequences owned by excluded tables are excluded with them.
+const DUMP_SEQUENCES: &str = concat!(
+    r#"
+    SELECT format(
+        'CREATE SEQUENCE %I.%I AS %s INCREMENT BY %s MINVALUE %s MAXVALUE %s START WITH %s%s;',
+        n.nspname,
+        c.relname,
+        format_type(s.seqtypid, NULL),
+        s.seqincrement,
+        s.seqmin,
+        s.seqmax,
+        s.seqstart,
+        CASE WHEN s.seqcycle THEN ' CYCLE' ELSE '' END
+    )
+    FROM pg_sequence s
+    JOIN pg_class c ON c.oid = s.seqrelid
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    WHERE NOT EXISTS (
+        SELECT 1 FROM pg_depend d WHERE d.objid = c.oid AND d.deptype = 'i'
+    ) AND NOT EXISTS (
+        SELECT 1 FROM pg_depend d
+        WHERE d.objid = c.oid AND d.deptype = 'a' AND NOT "#,
+    not_excluded!("d.refobjid"),
+    r#"
+    ) AND "#,
+    user_object!("c.oid"),
+    r#"
+    ORDER BY n.nspname, c.relname
+    "#
+);
+
+const DUMP_FUNCTIONS: &str = concat!(
+    r#"
+    SELECT pg_get_functiondef(p.oid) || ';'
+    FROM pg_proc p
+    JOIN pg_namespace n ON n.oid = p.pronamespace
+    WHERE p.prokind IN ('f', 'p') AND "#,
+    user_object!("p.oid"),
+    r#"
+    ORDER BY n.nspname, p.proname, p.oid
+    "#
+);
+
+const DUMP_TABLES: &str = concat!(
+    r#"
+    SELECT format(
+        'CREATE TABLE %I.%I (%s);',
+        n.nspname,
+        c.relname,
+        (
+            SELECT string_agg(
+                format(E'\n    %I %s', a.attname, format_type(a.atttypid, a.atttypmod))
+                || CASE a.attidentity
+                    WHEN 'a' THEN ' GENERATED ALWAYS AS IDENTITY'
+                    WHEN 'd' THEN ' GENERATED BY DEFAULT AS IDENTITY'
+                    ELSE ''
+                END
+                || CASE
+                    WHEN a.attgenerated = 's'
+                        THEN ' GENERATED ALWAYS AS (' || pg_get_expr(d.adbin, d.adrelid) || ') STORED'
+                    ELSE COALESCE(' DEFAULT ' || pg_get_expr(d.adbin, d.adrelid), '')
+                END
+                || CASE WHEN a.attnotnull THEN ' NOT NULL' ELSE '' END,
+                ',' ORDER BY a.attnum
+            ) || E'\n'
+            FROM pg_attribute a
+            LEFT JOIN pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum
+            WHERE a.attrelid = c.oid AND a.attnum > 0 AND NOT a.attisdropped
+        )
+    )
+    FROM pg_class c
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    WHERE c.relkind IN ('r', 'p')
+        AND "#,
+    not_excluded!("c.oid"),
+    " AND ",
+    user_object!("c.oid"),
+    r#"
+    ORDER BY n.nspname, c.relname
+    "#
+);
+
+// Sequences created by `SERIAL` columns.
+const DUMP_SEQUENCE_OWNERS: &str = concat!(
+    r#"
+    SELECT format('ALTER SEQUENCE %I.%I OWNED BY %I.%I.%I;', sn.nspname, s.relname, n.nspname, c.relname, a.attname)
+    FROM pg_depend d
+    JOIN pg_class s ON s.oid = d.objid AND s.relkind = 'S'
+    JOIN pg_namespace sn ON sn.oid = s.relnamespace
+    JOIN pg_class c ON c.oid = d.refobjid
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    JOIN pg_attribute a ON a.attrelid = c.oid AND a.attnum = d.refobjsubid
+    WHERE d.classid = 'pg_class'::regclass
+        AND d.refclassid = 'pg_class'::regclass
+        AND d.deptype = 'a'
+        AND "#,
+    not_excluded!("c.oid"),
+    " AND ",
+    user_object!("c.oid"),
+    r#"
+    ORDER BY sn.nspname, s.relname
+    "#
+);
+
+// Foreign keys are added last so that all referenced keys exist.
+const DUMP_CONSTRAINTS: &str = concat!(
+    r#"
+    SELECT format('ALTER TABLE %I.%I ADD CONSTRAINT %I %s;', n.nspname, c.relname, con.conname, pg_get_constraintdef(con.oid))
+    FROM pg_constraint con
+    JOIN pg_class c ON c.oid = con.conrelid
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    WHERE con.contype IN ('p', 'u', 'c', 'x', 'f')
+        AND con.conislocal
+        AND "#,
+    not_excluded!("c.oid"),
+    " AND ",
+    user_object!("c.oid"),
+    r#"
+    ORDER BY con.contype = 'f', n.nspname, c.relname, con.conname
+    "#
+);
+
+// Indexes backing constraints are created by the constraints themselves.
+const DUMP_INDEXES: &str = concat!(
+    r#"
+    SELECT pg_get_indexdef(i.indexrelid) || ';'
+    FROM pg_index i
+    JOIN pg_class ic ON ic.oid = i.indexrelid
+    JOIN pg_class c ON c.oid = i.indrelid
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    WHERE NOT EXISTS (SELECT 1 FROM pg_constraint con WHERE con.conindid = i.indexrelid)
+        AND "#,
+    not_excluded!("c.oid"),
+    " AND ",
+    user_object!("c.oid"),
+    r#"
+    ORDER BY n.nspname, c.relname, ic.relname
+    "#
+);
+
+// Views are ordered by creation so that views can depend on each other.
+const DUMP_VIEWS: &str = concat!(
+    r#"
+    SELECT format(
+        E'CREATE %sVIEW %I.%I AS\n%s',
+        CASE c.relkind WHEN 'm' THEN 'MATERIALIZED ' ELSE '' END,
+        n.nspname,
+        c.relname,
+        pg_get_viewdef(c.oid)
+    )
+    FROM pg_class c
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    WHERE c.relkind IN ('v', 'm') AND "#,
+    user_object!("c.oid"),
+    r#"
+    ORDER BY c.oid
+    "#
+);
+
+const DUMP_TRIGGERS: &str = concat!(
+    r#"
+    SELECT pg_get_triggerdef(t.oid) || ';'
+    FROM pg_trigger t
+    JOIN pg_class c ON c.oid = t.tgrelid
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    WHERE NOT t.tgisinternal AND "#,
+    user_object!("c.oid"),
+    r#"
+    ORDER BY n.nspname, c.relname, t.tgname
+    "#
+);
+
 async fn current_database(conn: &mut PgConnection) -> Result<String, sqlx::Error> {
     query_scalar("SELECT current_database()")
         .fetch_one(conn)
@@ -139,7 +913,21 @@ async fn current_database(conn: &mut PgConnection) -> Result<String, sqlx::Error
 }
 
 // inspired from rails: https://github.com/rails/rails/blob/6e49cc77ab3d16c06e12f93158eaf3e507d4120e/activerecord/lib/active_record/migration.rb#L1308
-fn generate_lock_id(database_name: &str) -> i64 {
+// Bigint advisory lock keys are split into the high (`classid`)
+// and low (`objid`) 32 bits in `pg_locks`.
+pub(super) const LOCK_HELD: &str = r#"
+    SELECT EXISTS (
+        SELECT 1 FROM pg_locks
+        WHERE
+            locktype = 'advisory'
+            AND pid = pg_backend_pid()
+            AND granted
+            AND objsubid = 1
+            AND ((classid::BIGINT << 32) | objid::BIGINT) = $1
+    )
+"#;
+
+pub(super) fn generate_lock_id(database_name: &str) -> i64 {
     const CRC_IEEE: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
     // 0x20871d5f chosen by fair dice roll
     0x20871d5f * (CRC_IEEE.checksum(database_name.as_bytes()) as i64)
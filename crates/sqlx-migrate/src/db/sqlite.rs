@@ -1,68 +1,72 @@
 use async_trait::async_trait;
-use sqlx::{query, query_as};
-use std::{borrow::Cow, time::Duration};
-use time::OffsetDateTime;
+use sqlx::{query, query_as, query_scalar, Connection};
+use std::time::Duration;
 
-use super::AppliedMigration;
+use super::{
+    dialect::{
+        applied_migration, ensure_unique_versions, migration_run, release, Dialect, MigrationRow,
+        ReleaseRow, RunRow, ADDED_COLUMNS,
+    },
+    DeferredMigration, DeferredState, MigrationRun, MissingPrivilege, Release, TableStats,
+};
+use crate::MigrationPhase;
+
+const DIALECT: Dialect = Dialect::Sqlite;
 
 #[async_trait(?Send)]
 impl super::Migrations for sqlx::SqliteConnection {
     async fn ensure_migrations_table(&mut self, table_name: &str) -> Result<(), sqlx::Error> {
-        query(&format!(
-            r#"
-                CREATE TABLE IF NOT EXISTS {} (
-                    version BIGINT PRIMARY KEY,
-                    name TEXT NOT NULL,
-                    applied_on INTEGER NOT NULL,
-                    checksum BLOB NOT NULL,
-                    execution_time BIGINT NOT NULL
-                );
-                "#,
-            table_name
-        ))
-        .execute(self)
-        .await?;
+        query(&migrations_table(table_name))
+            .execute(&mut *self)
+            .await?;
 
-        Ok(())
+        // Tables created by older versions lack some of the columns.
+        let column_count = DIALECT.column_count();
+
+        for (column, definition) in ADDED_COLUMNS {
+            let columns: i64 = query_scalar(&column_count)
+                .bind(table_name)
+                .bind(column)
+                .fetch_one(&mut *self)
+                .await?;
+
+            if columns == 0 {
+                query(&format!(
+                    r#"ALTER TABLE {table_name} ADD COLUMN {definition}"#
+                ))
+                .execute(&mut *self)
+                .await?;
+            }
+        }
+
+        ensure_unique_versions::<sqlx::Sqlite>(self, DIALECT, table_name).await
     }
 
     async fn lock(&mut self) -> Result<(), sqlx::Error> {
         Ok(())
     }
 
+    async fn try_lock(&mut self) -> Result<bool, sqlx::Error> {
+        Ok(true)
+    }
+
     async fn unlock(&mut self) -> Result<(), sqlx::Error> {
         Ok(())
     }
 
+    async fn lock_held(&mut self) -> Result<bool, sqlx::Error> {
+        Ok(true)
+    }
+
     async fn list_migrations(
         &mut self,
         table_name: &str,
     ) -> Result<Vec<super::AppliedMigration<'static>>, sqlx::Error> {
-        let rows: Vec<(i64, String, Vec<u8>, i64)> = query_as(&format!(
-            r#"
-            SELECT
-                version,
-                name,
-                checksum,
-                execution_time
-            FROM
-                {}
-            ORDER BY version
-            "#,
-            table_name
-        ))
-        .fetch_all(self)
-        .await?;
+        let rows: Vec<MigrationRow> = query_as(&DIALECT.list_migrations(table_name))
+            .fetch_all(self)
+            .await?;
 
-        Ok(rows
-            .into_iter()
-            .map(|row| AppliedMigration {
-                version: row.0 as u64,
-                name: Cow::Owned(row.1),
-                checksum: Cow::Owned(row.2),
-                execution_time: Duration::from_nanos(row.3 as _),
-            })
-            .collect())
+        Ok(rows.into_iter().map(applied_migration).collect())
     }
 
     async fn add_migration(
@@ -70,20 +74,16 @@ impl super::Migrations for sqlx::SqliteConnection {
         table_name: &str,
         migration: super::AppliedMigration<'static>,
     ) -> Result<(), sqlx::Error> {
-        query(&format!(
-            r#"
-                INSERT INTO {} ( version, name, checksum, execution_time, applied_on )
-                VALUES ( $1, $2, $3, $4, $5 )
-            "#,
-            table_name
-        ))
-        .bind(migration.version as i64)
-        .bind(&*migration.name.clone())
-        .bind(&*migration.checksum.clone())
-        .bind(migration.execution_time.as_nanos() as i64)
-        .bind(OffsetDateTime::now_utc().unix_timestamp())
-        .execute(self)
-        .await?;
+        query(&DIALECT.insert_migration(table_name))
+            .bind(migration.version as i64)
+            .bind(&*migration.name.clone())
+            .bind(&*migration.checksum.clone())
+            .bind(migration.execution_time.as_nanos() as i64)
+            .bind(migration.phase.map(MigrationPhase::as_str))
+            .bind(migration.skipped)
+            .bind(migration.rows_affected.map(|rows| rows as i64))
+            .execute(self)
+            .await?;
 
         Ok(())
     }
@@ -93,7 +93,22 @@ impl super::Migrations for sqlx::SqliteConnection {
         table_name: &str,
         version: u64,
     ) -> Result<(), sqlx::Error> {
-        query(&format!(r#"DELETE FROM {} WHERE version = $1"#, table_name))
+        query(&DIALECT.remove_migration(table_name))
+            .bind(version as i64)
+            .execute(self)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn rename_migration(
+        &mut self,
+        table_name: &str,
+        version: u64,
+        name: &str,
+    ) -> Result<(), sqlx::Error> {
+        query(&DIALECT.rename_migration(table_name))
+            .bind(name)
             .bind(version as i64)
             .execute(self)
             .await?;
@@ -102,9 +117,289 @@ impl super::Migrations for sqlx::SqliteConnection {
     }
 
     async fn clear_migrations(&mut self, table_name: &str) -> Result<(), sqlx::Error> {
-        query(&format!("TRUNCATE {}", table_name))
+        query(&DIALECT.clear_migrations(table_name))
             .execute(self)
             .await?;
         Ok(())
     }
+
+    async fn ensure_deferred_table(&mut self, table_name: &str) -> Result<(), sqlx::Error> {
+        query(&deferred_table(table_name)).execute(self).await?;
+
+        Ok(())
+    }
+
+    async fn ensure_runs_table(&mut self, table_name: &str) -> Result<(), sqlx::Error> {
+        query(&runs_table(table_name)).execute(self).await?;
+
+        Ok(())
+    }
+
+    async fn add_run(&mut self, table_name: &str, run: &MigrationRun) -> Result<(), sqlx::Error> {
+        query(&DIALECT.insert_run(table_name))
+            .bind(run.version.map(|version| version as i64))
+            .bind(&run.name)
+            .bind(run.execution_time.as_nanos() as i64)
+            .execute(self)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list_runs(&mut self, table_name: &str) -> Result<Vec<MigrationRun>, sqlx::Error> {
+        let rows: Vec<RunRow> = query_as(&DIALECT.list_runs(table_name))
+            .fetch_all(self)
+            .await?;
+
+        Ok(rows.into_iter().map(migration_run).collect())
+    }
+
+    async fn ensure_releases_table(&mut self, table_name: &str) -> Result<(), sqlx::Error> {
+        query(&releases_table(table_name)).execute(self).await?;
+
+        Ok(())
+    }
+
+    async fn tag_release(
+        &mut self,
+        table_name: &str,
+        name: &str,
+        version: u64,
+    ) -> Result<Release, sqlx::Error> {
+        let row: ReleaseRow = query_as(&DIALECT.tag_release(table_name))
+            .bind(name)
+            .bind(version as i64)
+            .fetch_one(self)
+            .await?;
+
+        Ok(release(row))
+    }
+
+    async fn list_releases(&mut self, table_name: &str) -> Result<Vec<Release>, sqlx::Error> {
+        let rows: Vec<ReleaseRow> = query_as(&DIALECT.list_releases(table_name))
+            .fetch_all(self)
+            .await?;
+
+        Ok(rows.into_iter().map(release).collect())
+    }
+
+    async fn schedule_deferred(&mut self, table_name: &str, name: &str) -> Result<(), sqlx::Error> {
+        query(&DIALECT.schedule_deferred(table_name))
+            .bind(name)
+            .execute(self)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list_deferred(
+        &mut self,
+        table_name: &str,
+    ) -> Result<Vec<DeferredMigration>, sqlx::Error> {
+        let rows: Vec<(String, String, Option<String>, Option<i64>)> = query_as(&format!(
+            r#"
+            SELECT
+                name,
+                state,
+                error,
+                execution_time
+            FROM
+                {table_name}
+            ORDER BY scheduled_on, name
+            "#
+        ))
+        .fetch_all(self)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| DeferredMigration {
+                name: row.0,
+                state: row.1.parse().unwrap_or(DeferredState::Failed),
+                error: row.2,
+                execution_time: row.3.map(|t| Duration::from_nanos(t as _)),
+            })
+            .collect())
+    }
+
+    async fn claim_deferred(&mut self, table_name: &str, name: &str) -> Result<bool, sqlx::Error> {
+        let res = query(&DIALECT.claim_deferred(table_name))
+            .bind(name)
+            .execute(self)
+            .await?;
+
+        Ok(res.rows_affected() == 1)
+    }
+
+    async fn finish_deferred(
+        &mut self,
+        table_name: &str,
+        name: &str,
+        execution_time: Duration,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        query(&DIALECT.finish_deferred(table_name))
+            .bind(name)
+            .bind(if error.is_some() {
+                DeferredState::Failed.as_str()
+            } else {
+                DeferredState::Completed.as_str()
+            })
+            .bind(error)
+            .bind(execution_time.as_nanos() as i64)
+            .execute(self)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn table_stats(&mut self, _table: &str) -> Result<Option<TableStats>, sqlx::Error> {
+        // SQLite keeps no cheap row estimates, and it has no table level locks either.
+        Ok(None)
+    }
+
+    async fn explain(&mut self, _statement: &str) -> Result<Option<String>, sqlx::Error> {
+        Ok(None)
+    }
+
+    async fn rehearsal_database(&mut self, _name: &str) -> Result<Option<Self>, sqlx::Error> {
+        let mut conn = Self::connect(REHEARSAL_DATABASE).await?;
+        query("BEGIN").execute(&mut conn).await?;
+        Ok(Some(conn))
+    }
+
+    async fn table_columns(&mut self, table: &str) -> Result<Vec<String>, sqlx::Error> {
+        query_scalar(TABLE_COLUMNS)
+            .bind(table)
+            .fetch_all(self)
+            .await
+    }
+
+    async fn ensure_extensions(&mut self, extensions: &[String]) -> Result<(), sqlx::Error> {
+        match extensions.first() {
+            Some(extension) => Err(sqlx::Error::Configuration(
+                format!("SQLite does not support extensions: {extension}").into(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    async fn ensure_schemas(&mut self, _table: &str) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+
+    async fn missing_privileges(
+        &mut self,
+        _table_name: &str,
+    ) -> Result<Vec<MissingPrivilege>, sqlx::Error> {
+        // SQLite has no privileges, but the database file can be read-only.
+        let mut tx = self.begin().await?;
+        let res = query(WRITE_CHECK).execute(&mut *tx).await;
+        tx.rollback().await?;
+
+        match res {
+            Ok(_) => Ok(Vec::new()),
+            Err(error) if super::is_permission_denied(&error) => Ok(vec![read_only()]),
+            Err(error) => Err(error),
+        }
+    }
+
+    async fn dump_schema(
+        &mut self,
+        excluded_tables: &[String],
+    ) -> Result<Vec<String>, sqlx::Error> {
+        query_scalar(DUMP_SCHEMA)
+            .bind(excluded_tables.join("\n"))
+            .fetch_all(self)
+            .await
+    }
+}
+
+// Fails on read-only databases, the table is never committed.
+// SQLite databases have no schemas, migrations are rehearsed in memory.
+pub(super) const REHEARSAL_DATABASE: &str = "sqlite::memory:";
+
+pub(super) const WRITE_CHECK: &str = "CREATE TABLE _sqlx_migrate_write_check (id INTEGER)";
+
+pub(super) fn read_only() -> MissingPrivilege {
+    MissingPrivilege {
+        privilege: "WRITE".into(),
+        object: "DATABASE main".into(),
+        grant: None,
+    }
+}
+
+pub(super) const TABLE_COLUMNS: &str = "SELECT name FROM pragma_table_info(?1) ORDER BY cid";
+
+pub(super) fn migrations_table(table_name: &str) -> String {
+    format!(
+        r#"
+            CREATE TABLE IF NOT EXISTS {table_name} (
+                version BIGINT PRIMARY KEY,
+                name TEXT NOT NULL,
+                applied_on INTEGER NOT NULL,
+                checksum BLOB NOT NULL,
+                execution_time BIGINT NOT NULL,
+                phase TEXT,
+                skipped BOOLEAN NOT NULL DEFAULT FALSE,
+                rows_affected BIGINT
+            );
+            "#
+    )
 }
+
+pub(super) fn deferred_table(table_name: &str) -> String {
+    format!(
+        r#"
+            CREATE TABLE IF NOT EXISTS {table_name} (
+                name TEXT PRIMARY KEY,
+                state TEXT NOT NULL,
+                scheduled_on INTEGER NOT NULL,
+                error TEXT,
+                execution_time BIGINT
+            );
+            "#
+    )
+}
+
+pub(super) fn runs_table(table_name: &str) -> String {
+    format!(
+        r#"
+            CREATE TABLE IF NOT EXISTS {table_name} (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                version BIGINT,
+                name TEXT NOT NULL,
+                execution_time BIGINT NOT NULL,
+                ran_on INTEGER NOT NULL
+            );
+            "#
+    )
+}
+
+pub(super) fn releases_table(table_name: &str) -> String {
+    format!(
+        r#"
+            CREATE TABLE IF NOT EXISTS {table_name} (
+                name TEXT PRIMARY KEY,
+                version BIGINT NOT NULL,
+                tagged_on INTEGER NOT NULL
+            );
+            "#
+    )
+}
+
+// SQLite stores the original DDL of every object,
+// the rowid order follows creation order.
+//
+// The names of the excluded tables are separated by newlines.
+pub(super) const DUMP_SCHEMA: &str = r#"
+    SELECT
+        sql || ';'
+    FROM
+        sqlite_master
+    WHERE
+        sql IS NOT NULL
+        AND name NOT LIKE 'sqlite\_%' ESCAPE '\'
+        AND instr(char(10) || ?1 || char(10), char(10) || tbl_name || char(10)) = 0
+    ORDER BY rowid
+"#;
@@ -1,8 +1,13 @@
-use std::borrow::Cow;
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    borrow::Cow,
+    fmt, io,
+    path::PathBuf,
+};
 
 use thiserror::Error;
 
-use crate::MigrationError;
+use crate::{lint::Lint, MigrationError};
 
 /// An aggregated error type for the [`Migrator`].
 #[derive(Debug, Error)]
@@ -19,32 +24,212 @@ pub enum Error {
     },
     #[error("there were no local migrations found")]
     NoMigrations,
+    #[error("migration not found: {name}")]
+    MigrationNotFound { name: String },
+    #[error("release not found: {name}")]
+    ReleaseNotFound { name: String },
+    #[error("migration names must not be empty")]
+    EmptyMigrationName,
+    #[error("duplicate migration name: {name}")]
+    DuplicateMigration { name: Cow<'static, str> },
+    #[error("duplicate migration set: {name}")]
+    DuplicateMigrationSet { name: Cow<'static, str> },
+    #[error(
+        "migration {name} of set {set} requires set {dependency} to be at version {version} or later"
+    )]
+    UnmetDependency {
+        set: Cow<'static, str>,
+        name: Cow<'static, str>,
+        dependency: Cow<'static, str>,
+        version: u64,
+    },
+    #[error("migration set {name}: {error}")]
+    MigrationSet {
+        name: Cow<'static, str>,
+        error: Box<Error>,
+    },
     #[error("missing migrations ({local_count} local, but {db_count} already applied)")]
     MissingMigrations { local_count: usize, db_count: usize },
+    #[error("the database is newer than this binary ({db_count} migrations applied, but only {local_count} local)")]
+    NewerDatabase { local_count: usize, db_count: usize },
+    #[error("pending migrations ({local_count} local, but only {db_count} applied)")]
+    PendingMigrations { local_count: usize, db_count: usize },
     #[error("error applying migration: {error}")]
     Migration {
         name: Cow<'static, str>,
         version: u64,
+        #[source]
         error: MigrationError,
+        backtrace: Box<Backtrace>,
     },
     #[error("error reverting migration: {error}")]
     Revert {
         name: Cow<'static, str>,
         version: u64,
+        #[source]
+        error: MigrationError,
+        backtrace: Box<Backtrace>,
+    },
+    #[error("error running deferred migration {name}: {error}")]
+    Deferred {
+        name: Cow<'static, str>,
+        #[source]
+        error: MigrationError,
+        backtrace: Box<Backtrace>,
+    },
+    #[error("error backing up before destructive migration {name}: {error}")]
+    Backup {
+        name: Cow<'static, str>,
+        version: u64,
+        #[source]
         error: MigrationError,
     },
+    #[error("the migration lock was lost while running migration {name}")]
+    LockLost { name: Cow<'static, str> },
+    #[error("migration {version} was recorded concurrently by another migrator")]
+    ConcurrentMigration { version: u64 },
+    #[error("error executing statement after commit for migration {name}: {error}")]
+    AfterCommit {
+        name: Cow<'static, str>,
+        statement: String,
+        error: sqlx::Error,
+    },
+    #[error("error writing statement log {}: {error}", path.display())]
+    StatementLog { path: PathBuf, error: io::Error },
+    #[error(
+        "the migrations table {table} was not created by sqlx-migrate{}, it is missing columns: {}",
+        tool.map(|tool| format!(" but probably by {tool}")).unwrap_or_default(),
+        missing.join(", ")
+    )]
+    ForeignMigrationsTable {
+        table: String,
+        /// The tool that probably created the table.
+        tool: Option<&'static str>,
+        missing: Vec<&'static str>,
+    },
+    #[error(
+        "the migrations table {table} is missing required columns: {}",
+        missing.join(", ")
+    )]
+    MigrationsTableColumns {
+        table: String,
+        missing: Vec<&'static str>,
+    },
+    #[error("the migrations table {table} is inaccessible: {error}")]
+    MigrationsTableInaccessible { table: String, error: sqlx::Error },
+    #[error(
+        "migrations without down migrations cannot be reverted: {}",
+        migrations
+            .iter()
+            .map(|(version, name)| format!("{version} ({name})"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )]
+    Irreversible {
+        migrations: Vec<(u64, Cow<'static, str>)>,
+    },
+    #[error(
+        "reverting crosses the irreversible boundaries of migrations: {}",
+        migrations
+            .iter()
+            .map(|(version, name)| format!("{version} ({name})"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )]
+    IrreversibleBoundary {
+        migrations: Vec<(u64, Cow<'static, str>)>,
+    },
+    #[error("invalid migration history: {0}")]
+    InvalidHistory(String),
+    #[error("the migrations table already contains {applied} applied migrations")]
+    HistoryExists { applied: u64 },
+    #[error("migration {version} cannot be marked, the database is at version {db_version}")]
+    MarkVersion { version: u64, db_version: u64 },
     #[error("expected migration {version} to be {local_name} but it was applied as {db_name}")]
     NameMismatch {
         version: u64,
         local_name: Cow<'static, str>,
         db_name: Cow<'static, str>,
     },
+    #[error("the database must be at version {through} to be squashed, but it is at version {db_version}")]
+    SquashVersionMismatch { through: u64, db_version: u64 },
+    #[error(
+        "migration {version} is potentially dangerous ({}) and must be explicitly allowed",
+        lints.iter().map(|lint| lint.rule.name()).collect::<Vec<_>>().join(", ")
+    )]
+    UnsafeMigration {
+        name: Cow<'static, str>,
+        version: u64,
+        lints: Vec<Lint>,
+    },
     #[error("invalid checksum for migration {version}")]
     ChecksumMismatch {
         version: u64,
         local_checksum: Cow<'static, [u8]>,
         db_checksum: Cow<'static, [u8]>,
     },
+    #[error("{0}")]
+    Cli(String),
+}
+
+impl Error {
+    /// The backtrace of a failed migration.
+    ///
+    /// Backtraces are only captured if enabled with the
+    /// `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` environment variables.
+    #[must_use]
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        match self {
+            Error::Migration { backtrace, .. }
+            | Error::Revert { backtrace, .. }
+            | Error::Deferred { backtrace, .. } => {
+                Some(&**backtrace).filter(|bt| bt.status() == BacktraceStatus::Captured)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the error was caused by a lost or unusable database connection,
+    /// including the errors of migrations that failed because of it.
+    ///
+    /// The pending migrations are committed together, so none of them are applied
+    /// if the connection is lost before the commit, and they can be resumed by
+    /// connecting again, see [`Migrator::migrate`](crate::Migrator::migrate).
+    /// Statements queued with [`MigrationContext::after_commit`](crate::MigrationContext::after_commit)
+    /// are executed after the commit, and deferred migrations keep the batches
+    /// they committed before the connection was lost.
+    #[must_use]
+    pub fn is_connection_error(&self) -> bool {
+        fn is_connection_error(error: &sqlx::Error) -> bool {
+            matches!(
+                error,
+                sqlx::Error::Io(_)
+                    | sqlx::Error::Tls(_)
+                    | sqlx::Error::PoolTimedOut
+                    | sqlx::Error::PoolClosed
+                    | sqlx::Error::WorkerCrashed
+            )
+        }
+
+        if let Error::Database(error) = self {
+            return is_connection_error(error);
+        }
+
+        let mut source = std::error::Error::source(self);
+
+        while let Some(error) = source {
+            if error
+                .downcast_ref::<sqlx::Error>()
+                .is_some_and(is_connection_error)
+            {
+                return true;
+            }
+
+            source = error.source();
+        }
+
+        false
+    }
 }
 
 impl From<sqlx::Error> for Error {
@@ -52,3 +237,67 @@ impl From<sqlx::Error> for Error {
         Self::Database(err)
     }
 }
+
+/// Context helpers for the errors of migration functions.
+///
+/// ```
+/// use sqlx_migrate::prelude::*;
+///
+/// fn parse_limit(value: &str) -> Result<u32, MigrationError> {
+///     value.parse::<u32>().context("invalid batch limit")
+/// }
+///
+/// assert_eq!(parse_limit("foo").unwrap_err().to_string(), "invalid batch limit");
+/// ```
+pub trait ErrorContext<T> {
+    /// Wrap the error with the given context.
+    ///
+    /// # Errors
+    ///
+    /// The wrapped error is returned, the original error is its [`source`](std::error::Error::source).
+    fn context<C>(self, context: C) -> Result<T, MigrationError>
+    where
+        C: fmt::Display + Send + Sync + 'static;
+
+    /// Wrap the error with context that is only evaluated if an error occurs.
+    ///
+    /// # Errors
+    ///
+    /// The wrapped error is returned, the original error is its [`source`](std::error::Error::source).
+    fn with_context<C, F>(self, f: F) -> Result<T, MigrationError>
+    where
+        C: fmt::Display + Send + Sync + 'static,
+        F: FnOnce() -> C;
+}
+
+impl<T, E> ErrorContext<T> for Result<T, E>
+where
+    E: Into<MigrationError>,
+{
+    fn context<C>(self, context: C) -> Result<T, MigrationError>
+    where
+        C: fmt::Display + Send + Sync + 'static,
+    {
+        self.with_context(|| context)
+    }
+
+    fn with_context<C, F>(self, f: F) -> Result<T, MigrationError>
+    where
+        C: fmt::Display + Send + Sync + 'static,
+        F: FnOnce() -> C,
+    {
+        self.map_err(|error| {
+            Box::new(ContextError {
+                context: f().to_string(),
+                source: error.into(),
+            }) as MigrationError
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("{context}")]
+struct ContextError {
+    context: String,
+    source: MigrationError,
+}
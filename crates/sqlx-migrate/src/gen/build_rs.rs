@@ -1,24 +1,96 @@
-use crate::DatabaseType;
+use crate::{DatabaseType, MigrationsDirs};
 use proc_macro2::{Ident, Span};
 use std::{fs, path::Path};
 use syn::parse_quote;
 use walkdir::WalkDir;
 
+/// Options for [`generate_with`].
+#[derive(Debug, Clone, Default)]
+pub struct GenerateOptions {
+    /// How migrations without a revert migration are handled.
+    pub reversibility: Reversibility,
+    /// Only check the reversibility of migrations created at or after
+    /// the given date (e.g. `20240101000000`), so that existing irreversible
+    /// migrations are allowed.
+    pub reversible_since: Option<u64>,
+    /// How the checksums of SQL migrations are calculated.
+    pub sql_checksum: SqlChecksum,
+}
+
+/// How migrations without a revert migration are handled
+/// by the generator, see [`GenerateOptions::reversibility`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Reversibility {
+    /// Irreversible migrations are allowed.
+    #[default]
+    Optional,
+    /// Irreversible migrations are reported as cargo warnings.
+    Warn,
+    /// Irreversible migrations fail the generation.
+    Required,
+}
+
+/// How the checksums of SQL migrations are calculated,
+/// see [`GenerateOptions::sql_checksum`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SqlChecksum {
+    /// The checksum of the SQL file (or the rendered template) is calculated
+    /// during generation, so migrations are not executed for verification
+    /// and the checksums do not depend on variable substitution.
+    ///
+    /// SQL specific to a database is selected at runtime for
+    /// [`DatabaseType::Any`], those checksums are calculated
+    /// from the executed statements instead.
+    #[default]
+    Source,
+    /// The checksum is calculated from the executed statements,
+    /// the same way as for Rust migrations.
+    Executed,
+}
+
 /// Generate Rust code from a migrations directory.
 /// It is meant to be used in `build.rs`.
 ///
+/// Multiple directories can be given, e.g. `&["migrations/core", "migrations/billing"]`,
+/// see [`MigrationsDirs`].
+///
 /// # Panics
 ///
-/// This function is meant to be used in `build.rs` and will panic on errors.
+/// This function is meant to be used in `build.rs` and will panic on errors,
+/// including migrations with the same name or date in different directories.
 pub fn generate(
-    migrations_dir: impl AsRef<Path>,
+    migrations_dir: impl MigrationsDirs,
+    module_path: impl AsRef<Path>,
+    db_type: DatabaseType,
+) {
+    generate_with(
+        migrations_dir,
+        module_path,
+        db_type,
+        &GenerateOptions::default(),
+    );
+}
+
+/// Same as [`generate`], but with the given options.
+///
+/// # Panics
+///
+/// This function is meant to be used in `build.rs` and will panic on errors,
+/// including irreversible migrations with [`Reversibility::Required`].
+pub fn generate_with(
+    migrations_dir: impl MigrationsDirs,
     module_path: impl AsRef<Path>,
     db_type: DatabaseType,
+    options: &GenerateOptions,
 ) {
-    cargo_rerun(migrations_dir.as_ref());
+    let migrations_dirs = migrations_dir.migrations_dirs();
+
+    for dir in &migrations_dirs {
+        cargo_rerun(dir);
+    }
 
-    let modules = super::migration_modules(migrations_dir.as_ref());
-    let migrations = super::migrations(db_type, migrations_dir.as_ref());
+    let modules = super::migration_modules(&migrations_dirs);
+    let (migrations, meta) = super::migrations(db_type, &migrations_dirs, options);
 
     if let Some(p) = module_path.as_ref().parent() {
         fs::create_dir_all(p).unwrap();
@@ -38,6 +110,8 @@ pub fn generate(
                 #migrations
             }
 
+            /// The metadata of all the migrations, in the same order as [`migrations`].
+            pub const MIGRATIONS: &[sqlx_migrate::MigrationMeta] = &#meta;
         }),
     )
     .unwrap();
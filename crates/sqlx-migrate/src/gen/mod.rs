@@ -1,57 +1,30 @@
-use crate::DatabaseType;
-use proc_macro2::{Ident, Span, TokenStream};
+use crate::{migrate_ignore::MigrateIgnore, DatabaseType};
+use proc_macro2::{Ident, Literal, Span, TokenStream};
 use quote::{format_ident, quote};
 use sha2::{Digest, Sha256};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::{self, DirEntry},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 mod build_rs;
+mod workspace;
 
-pub use build_rs::generate;
+pub use build_rs::{generate, generate_with, GenerateOptions, Reversibility, SqlChecksum};
+pub use workspace::{generate_workspace, generate_workspace_with};
 
 #[must_use]
-pub fn migration_modules(migrations_path: &Path) -> TokenStream {
-    assert!(
-        migrations_path.is_dir(),
-        "migrations path must be a directory ({migrations_path:?})",
-    );
-
+pub fn migration_modules(migrations_dirs: &[PathBuf]) -> TokenStream {
     let mut modules = quote! {};
 
-    let mut files = fs::read_dir(migrations_path)
-        .unwrap()
-        .map(Result::unwrap)
-        .filter(|file| {
-            let file_path = file.path();
-
-            if file_path.is_dir() {
-                return false;
-            }
-
-            let fname = file.file_name();
-
-            let file_name = fname.to_string_lossy();
-            let file_name_lower = file_name.to_ascii_lowercase();
-
-            if !(file_name_lower.ends_with(".migrate.rs")
-                || file_name_lower.ends_with(".revert.rs")
-                || file_name_lower.ends_with(".migrate.sql")
-                || file_name_lower.ends_with(".revert.sql"))
-            {
-                return false;
-            }
-
-            true
-        })
-        .collect::<Vec<_>>();
-
-    files.sort_by_key(DirEntry::file_name);
+    let files = migration_entries(migrations_dirs);
 
     let mut version = 0;
 
+    // Files specific to a database share the module of the migration.
+    let mut seen = HashSet::new();
+
     for file in files {
         let file_path = file.path();
 
@@ -64,11 +37,7 @@ pub fn migration_modules(migrations_path: &Path) -> TokenStream {
         let file_name = fname.to_string_lossy();
         let file_name_lower = file_name.to_ascii_lowercase();
 
-        if !(file_name_lower.ends_with(".migrate.rs")
-            || file_name_lower.ends_with(".revert.rs")
-            || file_name_lower.ends_with(".migrate.sql")
-            || file_name_lower.ends_with(".revert.sql"))
-        {
+        if !is_migration_file(&file_name_lower) {
             continue;
         }
 
@@ -79,8 +48,13 @@ pub fn migration_modules(migrations_path: &Path) -> TokenStream {
             kind,
             source,
             date,
+            dialect: _,
         } = split;
 
+        if !seen.insert((name.clone(), matches!(kind, MigrationKind::Up))) {
+            continue;
+        }
+
         let file_path_str = file_path.to_string_lossy().to_string();
 
         let docstr = format!(" Created at {date}.");
@@ -115,7 +89,7 @@ pub fn migration_modules(migrations_path: &Path) -> TokenStream {
                     pub use #name_ident::*;
                 });
             }
-            MigrationSourceKind::Sql => {
+            MigrationSourceKind::Sql | MigrationSourceKind::Template => {
                 modules.extend(quote! {
                     #[allow(dead_code)]
                     #[allow(clippy::all, clippy::pedantic)]
@@ -132,167 +106,378 @@ pub fn migration_modules(migrations_path: &Path) -> TokenStream {
 // The length of dates before the migration names.
 const MIG_DATE_PREFIX_LEN: usize = "20001010235912_".len();
 
+// The directive for post-deployment migrations, e.g. `-- sqlx-migrate:phase post`.
+const PHASE_DIRECTIVE: &str = "sqlx-migrate:phase";
+
 struct Migration {
     date: u64,
     name: String,
-    up_fn: Option<TokenStream>,
-    down_fn: Option<TokenStream>,
+    up_files: Vec<MigrationFile>,
+    down_files: Vec<MigrationFile>,
+}
+
+// A single source file of a migration.
+struct MigrationFile {
+    path: PathBuf,
+    source: MigrationSourceKind,
+    dialect: Option<DatabaseType>,
 }
 
-#[allow(clippy::too_many_lines)]
+// Returns the migrations and their metadata (`MigrationMeta`) in the same order.
 #[must_use]
-pub fn migrations(db: DatabaseType, migrations_path: &Path) -> TokenStream {
-    assert!(
-        migrations_path.is_dir(),
-        "migrations path must be a directory ({migrations_path:?})",
-    );
+pub fn migrations(
+    db: DatabaseType,
+    migrations_dirs: &[PathBuf],
+    options: &GenerateOptions,
+) -> (TokenStream, TokenStream) {
+    let migrations = load_migrations(migrations_dirs);
 
-    // Migrations by their name.
-    let mut migrations: HashMap<String, Migration> = HashMap::new();
+    let mut migration_tokens = quote! {};
+    let mut meta_tokens = quote! {};
 
-    let db_ident = format_ident!("{}", db.sqlx_type());
+    for (idx, mig) in migrations.into_iter().enumerate() {
+        let Migration {
+            date,
+            name,
+            mut up_files,
+            mut down_files,
+        } = mig;
 
-    for file in fs::read_dir(migrations_path).unwrap() {
-        let file = file.unwrap();
+        // Keep the generated code independent of the directory order.
+        up_files.sort_by(|a, b| a.path.cmp(&b.path));
+        down_files.sort_by(|a, b| a.path.cmp(&b.path));
 
-        let file_path = file.path();
+        let post_deploy = post_deploy(&name, &up_files);
 
-        if file_path.is_dir() {
-            continue;
+        let up_fn = migration_fn(db, &name, &MigrationKind::Up, &up_files);
+        let down_fn = migration_fn(db, &name, &MigrationKind::Down, &down_files);
+
+        assert!(up_fn.is_some(), "missing up migration for {}", &name);
+
+        let reversible = down_fn.is_some();
+
+        if down_fn.is_none() && options.reversible_since.is_none_or(|since| date >= since) {
+            match options.reversibility {
+                Reversibility::Optional => {}
+                Reversibility::Warn => {
+                    println!("cargo:warning=migration {name} has no revert migration");
+                }
+                Reversibility::Required => {
+                    panic!("migration {name} has no revert migration, but it is required");
+                }
+            }
         }
 
-        let fname = file.file_name();
+        migration_tokens.extend(quote! {
+            sqlx_migrate::Migration::new(
+                #name, |ctx| std::boxed::Box::pin(async move {
+                    #up_fn
+                })
+            )
+        });
 
-        let file_name = fname.to_string_lossy();
-        let file_name_lower = file_name.to_ascii_lowercase();
+        let created_at = date.to_string();
+        let source_file = source_file(db, &up_files).unwrap();
+        let source_path = source_file.path.to_string_lossy().to_string();
 
-        if !(file_name_lower.ends_with(".migrate.rs")
-            || file_name_lower.ends_with(".revert.rs")
-            || file_name_lower.ends_with(".migrate.sql")
-            || file_name_lower.ends_with(".revert.sql"))
-        {
-            continue;
+        migration_tokens.extend(quote! {
+            .with_created_at(#created_at)
+            .with_source_path(#source_path)
+        });
+
+        if options.sql_checksum == SqlChecksum::Source {
+            if let Some(checksum) = sql_checksum(db, &name, &up_files) {
+                let checksum = Literal::byte_string(&checksum);
+
+                migration_tokens.extend(quote! {
+                    .with_checksum(#checksum.as_slice())
+                });
+            }
         }
 
-        let split = split_name(&file_name, &file_name_lower);
+        if post_deploy {
+            migration_tokens.extend(quote! {
+                .in_phase(sqlx_migrate::MigrationPhase::Post)
+            });
+        }
 
-        let mig = migrations.entry(split.name.clone()).or_insert(Migration {
-            date: split.date,
-            name: split.name,
-            up_fn: None,
-            down_fn: None,
+        if let Some(down) = down_fn {
+            migration_tokens.extend(quote! {
+                .reversible(|ctx| std::boxed::Box::pin(async move {
+                    #down
+                })
+                )
+            });
+        }
+
+        migration_tokens.extend(quote!(,));
+
+        let version = idx as u64 + 1;
+        let is_sql = !matches!(source_file.source, MigrationSourceKind::Rust);
+
+        meta_tokens.extend(quote! {
+            sqlx_migrate::MigrationMeta {
+                name: #name,
+                version: #version,
+                created_at: #created_at,
+                source_path: #source_path,
+                is_sql: #is_sql,
+                reversible: #reversible,
+            },
         });
+    }
 
-        match split.kind {
-            MigrationKind::Up => {
-                assert!(
-                    mig.up_fn.is_none(),
-                    "duplicate up migration for {}",
-                    &mig.name
-                );
+    (quote! {[#migration_tokens]}, quote! {[#meta_tokens]})
+}
 
-                let source_string = fs::read_to_string(&file_path).unwrap();
+// The migration files in all the directories, sorted by their names.
+//
+// Migrations with the same name or date in different directories
+// cannot be ordered reliably and are rejected.
+fn migration_entries(migrations_dirs: &[PathBuf]) -> Vec<DirEntry> {
+    assert!(
+        !migrations_dirs.is_empty(),
+        "at least one migrations directory is required"
+    );
 
-                let mut hasher = Sha256::new();
-                hasher.update(source_string.as_bytes());
+    let mut files = Vec::new();
 
-                let file_path_str = file_path.to_string_lossy().to_string();
+    // The directories of migration names and dates.
+    let mut names: HashMap<String, &Path> = HashMap::new();
+    let mut dates: HashMap<u64, (&Path, String)> = HashMap::new();
 
-                let mig_ident = Ident::new(&mig.name, Span::call_site());
+    for migrations_path in migrations_dirs {
+        assert!(
+            migrations_path.is_dir(),
+            "migrations path must be a directory ({})",
+            migrations_path.display()
+        );
 
-                match split.source {
-                    MigrationSourceKind::Rust => {
-                        mig.up_fn = Some(quote! {
-                            #[path = #file_path_str]
-                            mod #mig_ident;
+        let ignore = load_ignore(migrations_path);
 
-                            #mig_ident::#mig_ident(ctx).await?;
+        for file in fs::read_dir(migrations_path).unwrap() {
+            let file = file.unwrap();
 
-                            Ok(())
-                        });
-                    }
-                    MigrationSourceKind::Sql => {
-                        mig.up_fn = Some(quote! {
-                            use sqlx::Executor;
-                            let ctx: &mut sqlx_migrate::prelude::MigrationContext<sqlx::#db_ident> = ctx;
-                            ctx.tx().execute(include_str!(#file_path_str)).await?;
-                            Ok(())
-                        });
-                    }
-                }
+            let file_path = file.path();
+
+            if file_path.is_dir() || ignore.is_ignored(&file_path) {
+                continue;
             }
-            MigrationKind::Down => {
-                assert!(
-                    mig.down_fn.is_none(),
-                    "duplicate down migration for {}",
-                    &mig.name
-                );
 
-                let file_path_str = file_path.to_string_lossy().to_string();
+            let fname = file.file_name();
 
-                let mig_ident = Ident::new(&format!("revert_{}", &mig.name), Span::call_site());
+            let file_name = fname.to_string_lossy();
+            let file_name_lower = file_name.to_ascii_lowercase();
 
-                match split.source {
-                    MigrationSourceKind::Rust => {
-                        mig.down_fn = Some(quote! {
-                            #[path = #file_path_str]
-                            mod #mig_ident;
+            if !is_migration_file(&file_name_lower) {
+                continue;
+            }
 
-                            #mig_ident::#mig_ident(ctx).await?;
+            let split = split_name(&file_name, &file_name_lower);
+
+            let name_dir = *names.entry(split.name.clone()).or_insert(migrations_path);
+            assert!(
+                name_dir == migrations_path,
+                "migration {} exists in both {} and {}",
+                split.name,
+                name_dir.display(),
+                migrations_path.display()
+            );
+
+            let (date_dir, date_name) = dates
+                .entry(split.date)
+                .or_insert((migrations_path, split.name.clone()));
+            assert!(
+                *date_dir == migrations_path || *date_name == split.name,
+                "migrations {date_name} in {} and {} in {} have the same date ({})",
+                date_dir.display(),
+                split.name,
+                migrations_path.display(),
+                split.date
+            );
+
+            files.push(file);
+        }
+    }
 
-                            Ok(())
-                        });
-                    }
-                    MigrationSourceKind::Sql => {
-                        mig.down_fn = Some(quote! {
-                            use sqlx::Executor;
-                            let ctx: &mut sqlx_migrate::prelude::MigrationContext<sqlx::#db_ident> = ctx;
-                            ctx.tx().execute(include_str!(#file_path_str)).await?;
-                            Ok(())
-                        });
-                    }
-                }
-            }
+    files.sort_by_key(DirEntry::file_name);
+
+    files
+}
+
+// The migrations in the directories, sorted by their dates.
+fn load_migrations(migrations_dirs: &[PathBuf]) -> Vec<Migration> {
+    // Migrations by their name.
+    let mut migrations: HashMap<String, Migration> = HashMap::new();
+
+    for file in migration_entries(migrations_dirs) {
+        let file_path = file.path();
+
+        let fname = file.file_name();
+
+        let file_name = fname.to_string_lossy();
+        let file_name_lower = file_name.to_ascii_lowercase();
+
+        let split = split_name(&file_name, &file_name_lower);
+
+        let mig = migrations.entry(split.name.clone()).or_insert(Migration {
+            date: split.date,
+            name: split.name,
+            up_files: Vec::new(),
+            down_files: Vec::new(),
+        });
+
+        let mig_file = MigrationFile {
+            path: file_path,
+            source: split.source,
+            dialect: split.dialect,
+        };
+
+        match split.kind {
+            MigrationKind::Up => mig.up_files.push(mig_file),
+            MigrationKind::Down => mig.down_files.push(mig_file),
         }
     }
 
     let mut migrations = migrations.into_values().collect::<Vec<_>>();
 
-    migrations.sort_by(|a, b| a.date.cmp(&b.date));
+    migrations.sort_by_key(|m| m.date);
 
-    let mut migration_tokens = quote! {};
+    migrations
+}
 
-    for mig in migrations {
-        let Migration {
-            date: _,
-            name,
-            up_fn,
-            down_fn,
-        } = mig;
+// The file of the migration that is used for the database,
+// the generic one for `Any` if there is one.
+fn source_file(db: DatabaseType, files: &[MigrationFile]) -> Option<&MigrationFile> {
+    files
+        .iter()
+        .find(|file| file.dialect == Some(db))
+        .or_else(|| files.iter().find(|file| file.dialect.is_none()))
+        .or_else(|| files.first())
+}
 
-        assert!(up_fn.is_some(), "missing up migration for {}", &name);
+// Whether the migration is run after deployment, all files of the
+// migration must agree on the phase.
+fn post_deploy(name: &str, up_files: &[MigrationFile]) -> bool {
+    let mut phases = up_files.iter().map(|file| {
+        let source_string = fs::read_to_string(&file.path).unwrap();
+
+        phase_directive(&source_string).is_some_and(|phase| {
+            assert!(
+                phase == "pre" || phase == "post",
+                "invalid migration phase for {name} ({phase})",
+            );
+            phase == "post"
+        })
+    });
 
-        migration_tokens.extend(quote! {
-            sqlx_migrate::Migration::new(
-                #name, |ctx| std::boxed::Box::pin(async move {
-                    #up_fn
-                })
-            )
+    let post_deploy = phases.next().unwrap_or_default();
+
+    assert!(
+        phases.all(|post| post == post_deploy),
+        "inconsistent migration phase for {name}"
+    );
+
+    post_deploy
+}
+
+// The body of the up or down function of a migration.
+//
+// SQL files specific to the generated database take precedence over generic ones,
+// for `Any` the SQL is selected based on the database at runtime.
+fn migration_fn(
+    db: DatabaseType,
+    name: &str,
+    kind: &MigrationKind,
+    files: &[MigrationFile],
+) -> Option<TokenStream> {
+    let (kind_name, mig_ident) = match kind {
+        MigrationKind::Up => ("up", Ident::new(name, Span::call_site())),
+        MigrationKind::Down => ("down", format_ident!("revert_{}", name)),
+    };
+
+    if let Some(file) = files
+        .iter()
+        .find(|file| matches!(file.source, MigrationSourceKind::Rust))
+    {
+        assert!(
+            files.len() == 1,
+            "duplicate {kind_name} migration for {name}"
+        );
+
+        let file_path_str = file.path.to_string_lossy().to_string();
+
+        return Some(quote! {
+            #[path = #file_path_str]
+            mod #mig_ident;
+
+            #mig_ident::#mig_ident(ctx).await?;
+
+            Ok(())
         });
+    }
 
-        if let Some(down) = down_fn {
-            migration_tokens.extend(quote! {
-                .reversible(|ctx| std::boxed::Box::pin(async move {
-                    #down
-                })
-                )
-            });
+    for (i, file) in files.iter().enumerate() {
+        assert!(
+            files[..i].iter().all(|other| other.dialect != file.dialect),
+            "duplicate {kind_name} migration for {name}"
+        );
+    }
+
+    let generic = files.iter().find(|file| file.dialect.is_none());
+
+    let sql = if let DatabaseType::Any = db {
+        let generic_sql = generic.map(|file| sql_source(db, name, &file.path, &file.source));
+
+        let arms = files
+            .iter()
+            .filter_map(|file| {
+                let dialect = file.dialect?;
+                let backend_name = dialect.backend_name()?;
+                let sql = sql_source(dialect, name, &file.path, &file.source);
+                Some(quote!(#backend_name => #sql,))
+            })
+            .collect::<Vec<_>>();
+
+        if arms.is_empty() {
+            generic_sql?
+        } else {
+            let fallback = generic_sql.map_or_else(
+                || {
+                    quote! {
+                        backend => {
+                            return Err(format!("migration {} does not support {backend}", #name).into())
+                        }
+                    }
+                },
+                |sql| quote!(_ => #sql,),
+            );
+
+            quote! {
+                match ctx.backend_name() {
+                    #(#arms)*
+                    #fallback
+                }
+            }
         }
+    } else {
+        let file = files
+            .iter()
+            .find(|file| file.dialect == Some(db))
+            .or(generic)?;
 
-        migration_tokens.extend(quote!(,));
-    }
+        sql_source(file.dialect.unwrap_or(db), name, &file.path, &file.source)
+    };
+
+    let db_ident = format_ident!("{}", db.sqlx_type());
 
-    quote! {[#migration_tokens]}
+    Some(quote! {
+        use sqlx::Executor;
+        let ctx: &mut sqlx_migrate::prelude::MigrationContext<sqlx::#db_ident> = ctx;
+        let sql = ctx.substitute(#sql);
+        ctx.tx().execute(&*sql).await?;
+        Ok(())
+    })
 }
 
 enum MigrationKind {
@@ -303,6 +488,7 @@ enum MigrationKind {
 enum MigrationSourceKind {
     Rust,
     Sql,
+    Template,
 }
 
 struct MigrationSplit {
@@ -310,6 +496,153 @@ struct MigrationSplit {
     name: String,
     kind: MigrationKind,
     source: MigrationSourceKind,
+    /// The database of SQL specific to a database, e.g. `name.migrate.pg.sql`.
+    dialect: Option<DatabaseType>,
+}
+
+fn load_ignore(migrations_path: &Path) -> MigrateIgnore {
+    match MigrateIgnore::load(migrations_path) {
+        Ok(ignore) => ignore,
+        Err(error) => panic!(
+            "invalid ignore file in {}: {error}",
+            migrations_path.display()
+        ),
+    }
+}
+
+fn is_migration_file(file_name_lower: &str) -> bool {
+    split_suffix(file_name_lower).is_some()
+}
+
+// Split the suffix of a migration file, e.g. `.migrate.pg.sql`,
+// returns the length of the rest of the file name.
+fn split_suffix(
+    file_name_lower: &str,
+) -> Option<(
+    usize,
+    MigrationKind,
+    MigrationSourceKind,
+    Option<DatabaseType>,
+)> {
+    let (stem, extension) = file_name_lower.rsplit_once('.')?;
+
+    let source = match extension {
+        "rs" => MigrationSourceKind::Rust,
+        "sql" => MigrationSourceKind::Sql,
+        "jinja" => MigrationSourceKind::Template,
+        _ => return None,
+    };
+
+    let (stem, dialect) = match stem.rsplit_once('.') {
+        Some((rest, tag)) if !matches!(source, MigrationSourceKind::Rust) => {
+            match DatabaseType::from_dialect_tag(tag) {
+                Some(dialect) => (rest, Some(dialect)),
+                None => (stem, None),
+            }
+        }
+        _ => (stem, None),
+    };
+
+    let (rest, kind) = match stem.rsplit_once('.')? {
+        (rest, "migrate") => (rest, MigrationKind::Up),
+        (rest, "revert") => (rest, MigrationKind::Down),
+        _ => return None,
+    };
+
+    Some((rest.len(), kind, source, dialect))
+}
+
+// The checksum of the SQL of a migration, the same as the checksum of the
+// executed statements without variables. `None` for Rust migrations and for
+// `Any` with SQL specific to a database, which is selected at runtime.
+fn sql_checksum(db: DatabaseType, name: &str, files: &[MigrationFile]) -> Option<Vec<u8>> {
+    if files
+        .iter()
+        .any(|file| matches!(file.source, MigrationSourceKind::Rust))
+    {
+        return None;
+    }
+
+    let generic = files.iter().find(|file| file.dialect.is_none());
+
+    let file = if let DatabaseType::Any = db {
+        if files.iter().any(|file| file.dialect.is_some()) {
+            return None;
+        }
+
+        generic?
+    } else {
+        files
+            .iter()
+            .find(|file| file.dialect == Some(db))
+            .or(generic)?
+    };
+
+    let sql = if let MigrationSourceKind::Template = file.source {
+        render_template(file.dialect.unwrap_or(db), name, &file.path)
+    } else {
+        fs::read_to_string(&file.path).unwrap()
+    };
+
+    Some(Sha256::digest(sql).to_vec())
+}
+
+// The SQL of a migration, SQL files are included as they are,
+// templates are rendered during generation.
+fn sql_source(
+    db: DatabaseType,
+    name: &str,
+    file_path: &Path,
+    source: &MigrationSourceKind,
+) -> TokenStream {
+    if let MigrationSourceKind::Template = source {
+        let sql = render_template(db, name, file_path);
+        quote!(#sql)
+    } else {
+        let file_path_str = file_path.to_string_lossy().to_string();
+        quote!(include_str!(#file_path_str))
+    }
+}
+
+// Templates are rendered with the migration name and the database
+// type (e.g. `postgres`) as context, so that the output is deterministic.
+#[cfg(feature = "templates")]
+fn render_template(db: DatabaseType, name: &str, file_path: &Path) -> String {
+    let source = fs::read_to_string(file_path).unwrap();
+
+    let mut env = minijinja::Environment::new();
+    env.set_undefined_behavior(minijinja::UndefinedBehavior::Strict);
+    env.set_keep_trailing_newline(true);
+
+    let db = db.sqlx_type().to_ascii_lowercase();
+
+    env.render_str(&source, minijinja::context! { name, db })
+        .unwrap_or_else(|err| {
+            panic!(
+                "failed to render migration template ({}): {err:#}",
+                file_path.display()
+            )
+        })
+}
+
+#[cfg(not(feature = "templates"))]
+fn render_template(_db: DatabaseType, _name: &str, file_path: &Path) -> String {
+    panic!(
+        "the `templates` feature is required for migration templates ({})",
+        file_path.display()
+    )
+}
+
+// The phase given in a `-- sqlx-migrate:phase <phase>` (SQL)
+// or `// sqlx-migrate:phase <phase>` (Rust) comment.
+fn phase_directive(source: &str) -> Option<&str> {
+    source.lines().find_map(|line| {
+        let line = line.trim();
+        let comment = line
+            .strip_prefix("--")
+            .or_else(|| line.strip_prefix("//"))?;
+        comment.trim().strip_prefix(PHASE_DIRECTIVE).map(str::trim)
+    })
 }
 
 // (full_name, date, name, sql)
@@ -326,30 +659,20 @@ fn split_name(file_name: &str, file_name_lower: &str) -> MigrationSplit {
 
     let date: u64 = file_name[..MIG_DATE_PREFIX_LEN - 1].parse().unwrap();
 
-    let mut split = file_name_lower[MIG_DATE_PREFIX_LEN..].rsplitn(3, '.');
+    let (name_end, kind, source, dialect) = split_suffix(file_name_lower).unwrap();
 
-    let source = match split.next().unwrap() {
-        "rs" => MigrationSourceKind::Rust,
-        "sql" => MigrationSourceKind::Sql,
-        _ => unreachable!(),
-    };
-
-    let kind = match split.next().unwrap() {
-        "migrate" => MigrationKind::Up,
-        "revert" => MigrationKind::Down,
-        _ => unreachable!(),
-    };
+    assert!(
+        name_end >= MIG_DATE_PREFIX_LEN,
+        "invalid migration file name ({file_name})",
+    );
 
-    let name = file_name[MIG_DATE_PREFIX_LEN..]
-        .rsplitn(3, '.')
-        .nth(2)
-        .unwrap()
-        .to_string();
+    let name = file_name[MIG_DATE_PREFIX_LEN..name_end].to_string();
 
     MigrationSplit {
         date,
         name,
         kind,
         source,
+        dialect,
     }
 }
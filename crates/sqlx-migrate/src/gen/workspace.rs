@@ -0,0 +1,115 @@
+use super::{generate_with, GenerateOptions};
+use crate::DatabaseType;
+use serde_json::Value;
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+// The key of the member metadata, `[package.metadata.sqlx-migrate]`.
+const METADATA_KEY: &str = "sqlx-migrate";
+
+/// Generate Rust code from the migrations directories of all workspace members.
+/// It is meant to be used in the `build.rs` of the crate that runs the migrations.
+///
+/// Members declare their migrations directories relative to their manifests:
+///
+/// ```toml
+/// [package.metadata.sqlx-migrate]
+/// migrations = "migrations"
+/// ```
+///
+/// A list of directories is also accepted.
+///
+/// The migrations of all members are merged into a single `migrations()` function
+/// ordered by date, see [`generate`](super::generate).
+///
+/// # Panics
+///
+/// This function is meant to be used in `build.rs` and will panic on errors,
+/// including when `cargo metadata` fails or no member declares migrations.
+pub fn generate_workspace(module_path: impl AsRef<Path>, db_type: DatabaseType) {
+    generate_workspace_with(module_path, db_type, &GenerateOptions::default());
+}
+
+/// Same as [`generate_workspace`], but with the given options.
+///
+/// # Panics
+///
+/// This function is meant to be used in `build.rs` and will panic on errors.
+pub fn generate_workspace_with(
+    module_path: impl AsRef<Path>,
+    db_type: DatabaseType,
+    options: &GenerateOptions,
+) {
+    let migrations_dirs = workspace_migrations_dirs();
+
+    assert!(
+        !migrations_dirs.is_empty(),
+        "no workspace member declares migrations in [package.metadata.{METADATA_KEY}]"
+    );
+
+    generate_with(migrations_dirs, module_path, db_type, options);
+}
+
+// The migrations directories of the workspace members,
+// ordered by the package names so that the output is stable.
+fn workspace_migrations_dirs() -> Vec<PathBuf> {
+    let manifest_dir = env::var_os("CARGO_MANIFEST_DIR")
+        .map_or_else(|| env::current_dir().unwrap(), PathBuf::from);
+
+    let output = Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+        .args(["metadata", "--format-version", "1", "--no-deps"])
+        .current_dir(&manifest_dir)
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "cargo metadata failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let metadata: Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    let mut packages = metadata["packages"]
+        .as_array()
+        .expect("invalid cargo metadata")
+        .iter()
+        .collect::<Vec<_>>();
+
+    packages.sort_by_key(|package| package["name"].as_str().unwrap_or_default());
+
+    let mut migrations_dirs = Vec::new();
+
+    for package in packages {
+        let name = package["name"].as_str().unwrap_or_default();
+        let manifest_path = Path::new(
+            package["manifest_path"]
+                .as_str()
+                .expect("invalid cargo metadata"),
+        );
+
+        println!("cargo:rerun-if-changed={}", manifest_path.display());
+
+        let dirs = match &package["metadata"][METADATA_KEY]["migrations"] {
+            Value::Null => continue,
+            Value::String(dir) => vec![dir.as_str()],
+            Value::Array(dirs) => dirs
+                .iter()
+                .map(|dir| {
+                    dir.as_str()
+                        .unwrap_or_else(|| panic!("invalid migrations directory in {name}"))
+                })
+                .collect(),
+            _ => panic!("invalid migrations directory in {name}"),
+        };
+
+        let package_dir = manifest_path.parent().unwrap();
+
+        migrations_dirs.extend(dirs.into_iter().map(|dir| package_dir.join(dir)));
+    }
+
+    migrations_dirs
+}
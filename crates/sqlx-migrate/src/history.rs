@@ -0,0 +1,105 @@
+//! Export and import of the migrations table.
+//!
+//! The history can be used to recreate the bookkeeping of a database
+//! that was moved or restored without the migrations table,
+//! see [`Migrator::export_history`](crate::Migrator::export_history)
+//! and [`Migrator::import_history`](crate::Migrator::import_history).
+use crate::{db::AppliedMigration, Error, MigrationPhase};
+use std::{borrow::Cow, fmt::Write, time::Duration};
+
+/// The applied migrations recorded in a migrations table.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationHistory {
+    /// The applied migrations ordered by their versions.
+    pub migrations: Vec<HistoryEntry>,
+}
+
+/// An applied migration in a [`MigrationHistory`].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub version: u64,
+    pub name: String,
+    /// The hex-encoded checksum.
+    pub checksum: String,
+    pub execution_time: Duration,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub phase: Option<MigrationPhase>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub skipped: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub rows_affected: Option<u64>,
+}
+
+impl From<AppliedMigration<'_>> for HistoryEntry {
+    fn from(mig: AppliedMigration<'_>) -> Self {
+        Self {
+            version: mig.version,
+            name: mig.name.into_owned(),
+            checksum: encode_hex(&mig.checksum),
+            execution_time: mig.execution_time,
+            phase: mig.phase,
+            skipped: mig.skipped,
+            rows_affected: mig.rows_affected,
+        }
+    }
+}
+
+impl MigrationHistory {
+    /// Convert the history to migrations that can be recorded in a database.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the versions are not consecutive
+    /// starting from 1, or a checksum is not valid hex.
+    pub fn applied_migrations(&self) -> Result<Vec<AppliedMigration<'static>>, Error> {
+        self.migrations
+            .iter()
+            .enumerate()
+            .map(|(idx, mig)| {
+                let expected_version = idx as u64 + 1;
+
+                if mig.version != expected_version {
+                    return Err(Error::InvalidHistory(format!(
+                        "expected version {expected_version} but found {}",
+                        mig.version
+                    )));
+                }
+
+                let checksum = decode_hex(&mig.checksum).ok_or_else(|| {
+                    Error::InvalidHistory(format!("invalid checksum for version {}", mig.version))
+                })?;
+
+                Ok(AppliedMigration {
+                    version: mig.version,
+                    name: Cow::Owned(mig.name.clone()),
+                    checksum: Cow::Owned(checksum),
+                    execution_time: mig.execution_time,
+                    phase: mig.phase,
+                    skipped: mig.skipped,
+                    applied_on: None,
+                    rows_affected: mig.rows_affected,
+                })
+            })
+            .collect()
+    }
+}
+
+pub(crate) fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        write!(s, "{b:02x}").unwrap();
+        s
+    })
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,780 @@
+//! Detection of dangerous patterns in migration SQL.
+//!
+//! The [`Linter`] works on SQL text only, it does not know anything about
+//! the database it is executed against. Migrations are linted as a whole,
+//! statements that target tables created in the same migration are not reported,
+//! since those tables are known to be empty.
+//!
+//! Lints can be suppressed for a migration with a comment anywhere in its SQL:
+//!
+//! ```sql
+//! -- sqlx-migrate:allow drop-column, alter-column-type
+//! ALTER TABLE users DROP COLUMN legacy_id;
+//! ```
+//!
+//! The rules can be configured for all migrations in a [`CONFIG_FILE`]
+//! in the migrations directory, see [`Linter::configure`]:
+//!
+//! ```text
+//! # Dropping columns is fine, the applications are always deployed first.
+//! drop-column = allow
+//! non-concurrent-index = deny
+//! ```
+use crate::{
+    db::TableStats,
+    sql::{split_statements, tokens, TokenKind},
+};
+use std::{collections::HashMap, fmt, str::FromStr};
+
+/// The prefix of lint suppression comments.
+pub const ALLOW_COMMENT_PREFIX: &str = "sqlx-migrate:allow";
+
+/// The name of the lint configuration file in the migrations directory.
+pub const CONFIG_FILE: &str = ".migratelint";
+
+/// The severity of a lint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warning" | "warn" => Ok(Severity::Warning),
+            "error" | "deny" => Ok(Severity::Error),
+            _ => Err(format!("invalid severity: {s}")),
+        }
+    }
+}
+
+/// A dangerous SQL pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LintRule {
+    /// Changing the type of a column, which usually rewrites the entire table.
+    AlterColumnType,
+    /// Adding a `NOT NULL` column without a default,
+    /// which fails for tables that are not empty.
+    NotNullWithoutDefault,
+    /// Creating an index without `CONCURRENTLY`, which blocks writes to the table.
+    NonConcurrentIndex,
+    /// Dropping a column that might still be used by running applications.
+    DropColumn,
+}
+
+impl LintRule {
+    /// All the available rules.
+    pub const ALL: [LintRule; 4] = [
+        LintRule::AlterColumnType,
+        LintRule::NotNullWithoutDefault,
+        LintRule::NonConcurrentIndex,
+        LintRule::DropColumn,
+    ];
+
+    /// The name of the rule used in suppression comments.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            LintRule::AlterColumnType => "alter-column-type",
+            LintRule::NotNullWithoutDefault => "not-null-without-default",
+            LintRule::NonConcurrentIndex => "non-concurrent-index",
+            LintRule::DropColumn => "drop-column",
+        }
+    }
+
+    /// A short description of the issue.
+    #[must_use]
+    pub fn description(self) -> &'static str {
+        match self {
+            LintRule::AlterColumnType => "changing the type of a column might rewrite the table",
+            LintRule::NotNullWithoutDefault => {
+                "adding a NOT NULL column without a default fails if the table has rows"
+            }
+            LintRule::NonConcurrentIndex => {
+                "creating an index without CONCURRENTLY blocks writes to the table"
+            }
+            LintRule::DropColumn => "dropping a column breaks applications that still use it",
+        }
+    }
+
+    /// The severity of the rule unless configured otherwise.
+    #[must_use]
+    pub fn default_severity(self) -> Severity {
+        match self {
+            LintRule::AlterColumnType | LintRule::NotNullWithoutDefault => Severity::Error,
+            LintRule::NonConcurrentIndex | LintRule::DropColumn => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for LintRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for LintRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LintRule::ALL
+            .into_iter()
+            .find(|rule| rule.name() == s)
+            .ok_or_else(|| format!("unknown lint rule: {s}"))
+    }
+}
+
+/// A dangerous pattern found in a statement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    pub rule: LintRule,
+    pub severity: Severity,
+    /// The offending statement with normalized whitespace.
+    pub statement: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} [{}]: {}",
+            self.severity,
+            self.rule,
+            self.rule.description()
+        )
+    }
+}
+
+/// Lints found in a single migration.
+#[derive(Debug, Clone)]
+pub struct MigrationLints {
+    pub version: u64,
+    pub name: String,
+    pub lints: Vec<Lint>,
+}
+
+/// Exclusive locks taken by a single migration.
+#[derive(Debug, Clone)]
+pub struct MigrationLocks {
+    pub version: u64,
+    pub name: String,
+    /// The locks along with the current size of the locked tables, if known.
+    pub locks: Vec<(ExclusiveLock, Option<TableStats>)>,
+    /// The query plans of the DML statements of the migration,
+    /// see [`MigratorOptions::explain`](crate::MigratorOptions::explain).
+    pub plans: Vec<StatementPlan>,
+}
+
+/// The query plan of a statement as reported by `EXPLAIN`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatementPlan {
+    /// The statement with normalized whitespace.
+    pub statement: String,
+    /// The plan, `None` if the statement could not be explained.
+    pub plan: Option<String>,
+    /// The error of `EXPLAIN`, e.g. if the statement depends
+    /// on changes of earlier migrations that are not applied.
+    pub error: Option<String>,
+}
+
+/// A configurable SQL linter.
+///
+/// All rules are enabled with their default severities by default.
+#[derive(Debug, Clone, Default)]
+pub struct Linter {
+    rules: HashMap<LintRule, Option<Severity>>,
+}
+
+impl Linter {
+    /// Create a linter with all rules enabled.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Override the severity of a rule, `None` disables it.
+    #[must_use]
+    pub fn rule(mut self, rule: LintRule, severity: Option<Severity>) -> Self {
+        self.rules.insert(rule, severity);
+        self
+    }
+
+    /// Apply the contents of a [`CONFIG_FILE`].
+    ///
+    /// Every line configures a single rule as `<rule> = allow|warn|deny`,
+    /// empty lines and lines starting with `#` are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first invalid line.
+    pub fn configure(mut self, config: &str) -> Result<Self, String> {
+        for (idx, line) in config.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((rule, severity)) = line.split_once('=') else {
+                return Err(format!("line {}: expected `<rule> = <severity>`", idx + 1));
+            };
+
+            let rule = rule
+                .trim()
+                .parse::<LintRule>()
+                .map_err(|err| format!("line {}: {err}", idx + 1))?;
+
+            let severity = match severity.trim() {
+                "allow" => None,
+                severity => Some(
+                    severity
+                        .parse::<Severity>()
+                        .map_err(|err| format!("line {}: {err}", idx + 1))?,
+                ),
+            };
+
+            self = self.rule(rule, severity);
+        }
+
+        Ok(self)
+    }
+
+    /// The effective severity of a rule, `None` if it is disabled.
+    #[must_use]
+    pub fn severity(&self, rule: LintRule) -> Option<Severity> {
+        match self.rules.get(&rule) {
+            Some(severity) => *severity,
+            None => Some(rule.default_severity()),
+        }
+    }
+
+    /// Lint the SQL of a single migration.
+    #[must_use]
+    pub fn lint(&self, sql: &str) -> Vec<Lint> {
+        self.lint_statements([sql])
+    }
+
+    /// Lint SQL executed by a single migration, possibly
+    /// split across multiple queries.
+    #[must_use]
+    pub fn lint_statements<'a>(&self, sql: impl IntoIterator<Item = &'a str>) -> Vec<Lint> {
+        let mut statements = Vec::new();
+        let mut allowed = Vec::new();
+
+        for sql in sql {
+            let parsed = parse(sql);
+            statements.extend(parsed.statements);
+
+            for comment in parsed.comments {
+                let Some(rules) = comment.trim().strip_prefix(ALLOW_COMMENT_PREFIX) else {
+                    continue;
+                };
+
+                allowed.extend(
+                    rules
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|r| !r.is_empty())
+                        .map(ToString::to_string),
+                );
+            }
+        }
+
+        let created_tables = created_tables(&statements);
+
+        let mut lints = Vec::new();
+
+        for stmt in &statements {
+            for (rule, table) in check(&stmt.tokens) {
+                if table.is_some_and(|table| created_tables.contains(&table_key(&table))) {
+                    continue;
+                }
+
+                if allowed.iter().any(|r| r == rule.name()) {
+                    continue;
+                }
+
+                let Some(severity) = self.severity(rule) else {
+                    continue;
+                };
+
+                lints.push(Lint {
+                    rule,
+                    severity,
+                    statement: stmt.sql.clone(),
+                });
+            }
+        }
+
+        lints
+    }
+}
+
+/// A statement that takes an `ACCESS EXCLUSIVE` lock on a table in PostgreSQL,
+/// blocking all reads and writes until the end of the transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExclusiveLock {
+    /// The locked table as written in the statement.
+    pub table: String,
+    /// Whether the table is likely rewritten while it is locked,
+    /// the lock is held proportionally to the table size.
+    pub rewrite: bool,
+    /// The statement with normalized whitespace.
+    pub statement: String,
+}
+
+/// Find statements that take `ACCESS EXCLUSIVE` locks on existing tables.
+///
+/// Tables created in the same SQL are not reported.
+#[must_use]
+pub fn exclusive_locks<'a>(sql: impl IntoIterator<Item = &'a str>) -> Vec<ExclusiveLock> {
+    let statements: Vec<Statement> = sql
+        .into_iter()
+        .flat_map(|sql| parse(sql).statements)
+        .collect();
+    let created_tables = created_tables(&statements);
+
+    statements
+        .iter()
+        .filter_map(|stmt| {
+            let (table, rewrite) = exclusive_lock(&stmt.tokens)?;
+
+            if created_tables.contains(&table_key(&table)) {
+                return None;
+            }
+
+            Some(ExclusiveLock {
+                table,
+                rewrite,
+                statement: stmt.sql.clone(),
+            })
+        })
+        .collect()
+}
+
+/// The DML statements (`INSERT`, `UPDATE`, `DELETE` and `MERGE`) of the given SQL,
+/// statements that target tables created by the same SQL are skipped.
+#[must_use]
+pub fn dml_statements<'a>(sql: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let statements: Vec<Statement> = sql
+        .into_iter()
+        .flat_map(|sql| parse(sql).statements)
+        .collect();
+    let created_tables = created_tables(&statements);
+
+    statements
+        .iter()
+        .filter_map(|stmt| {
+            let table = dml_table(&stmt.tokens)?;
+
+            if created_tables.contains(&table_key(&table)) {
+                return None;
+            }
+
+            Some(stmt.sql.clone())
+        })
+        .collect()
+}
+
+/// The tables modified by the DML statements of the given SQL, in order.
+#[cfg(any(feature = "postgres", feature = "sqlite", feature = "any"))]
+pub(crate) fn dml_tables(sql: &str) -> Vec<String> {
+    let mut tables: Vec<String> = Vec::new();
+
+    for stmt in parse(sql).statements {
+        if let Some(table) = dml_table(&stmt.tokens) {
+            if !tables.contains(&table) {
+                tables.push(table);
+            }
+        }
+    }
+
+    tables
+}
+
+/// The table targeted by a DML statement.
+fn dml_table(t: &[String]) -> Option<String> {
+    let mut idx = if matches_at(t, 0, &["INSERT", "INTO"])
+        || matches_at(t, 0, &["DELETE", "FROM"])
+        || matches_at(t, 0, &["MERGE", "INTO"])
+    {
+        2
+    } else if matches_at(t, 0, &["UPDATE"]) {
+        1
+    } else {
+        return None;
+    };
+
+    if matches_at(t, idx, &["ONLY"]) {
+        idx += 1;
+    }
+
+    object_name(t, idx).map(|(table, _)| table)
+}
+
+/// The table locked in `ACCESS EXCLUSIVE` mode by a statement,
+/// and whether the table is rewritten.
+fn exclusive_lock(t: &[String]) -> Option<(String, bool)> {
+    let skip = |idx: &mut usize, keywords: &[&str]| {
+        if matches_at(t, *idx, keywords) {
+            *idx += keywords.len();
+        }
+    };
+
+    if matches_at(t, 0, &["ALTER", "TABLE"]) {
+        let mut idx = 2;
+        skip(&mut idx, &["IF", "EXISTS"]);
+        skip(&mut idx, &["ONLY"]);
+
+        let (table, idx) = object_name(t, idx)?;
+
+        let mut exclusive = false;
+        let mut rewrite = false;
+
+        for action in split_top_level(&t[idx..]) {
+            // Skip the column name.
+            let column_action = if matches_at(action, 1, &["COLUMN"]) {
+                3
+            } else {
+                2
+            };
+
+            let is_alter = matches_at(action, 0, &["ALTER"]);
+
+            if is_alter
+                && (matches_at(action, column_action, &["TYPE"])
+                    || matches_at(action, column_action, &["SET", "DATA", "TYPE"]))
+            {
+                rewrite = true;
+            }
+
+            // Actions that take weaker locks.
+            let weak = matches_at(action, 0, &["VALIDATE"])
+                || (is_alter && matches_at(action, column_action, &["SET", "STATISTICS"]))
+                || (matches_at(action, 0, &["ADD"])
+                    && action
+                        .windows(2)
+                        .any(|w| w[0] == "FOREIGN" && w[1] == "KEY"));
+
+            exclusive |= !weak;
+        }
+
+        return exclusive.then_some((table, rewrite));
+    }
+
+    let (rewrite, mut idx) = if matches_at(t, 0, &["DROP", "TABLE"]) {
+        (false, 2)
+    } else if matches_at(t, 0, &["TRUNCATE"]) {
+        (false, 1)
+    } else if matches_at(t, 0, &["LOCK"]) {
+        let mode = t.iter().position(|tok| tok == "IN");
+        if mode.is_some_and(|mode| !matches_at(t, mode + 1, &["ACCESS", "EXCLUSIVE"])) {
+            return None;
+        }
+        (false, 1)
+    } else if matches_at(t, 0, &["VACUUM", "FULL"]) {
+        (true, 2)
+    } else if matches_at(t, 0, &["CLUSTER"]) {
+        (true, 1)
+    } else if matches_at(t, 0, &["REFRESH", "MATERIALIZED", "VIEW"])
+        && !matches_at(t, 3, &["CONCURRENTLY"])
+    {
+        (true, 3)
+    } else {
+        return None;
+    };
+
+    skip(&mut idx, &["TABLE"]);
+    skip(&mut idx, &["IF", "EXISTS"]);
+    skip(&mut idx, &["ONLY"]);
+
+    object_name(t, idx).map(|(table, _)| (table, rewrite))
+}
+
+/// Find dangerous patterns in a single statement,
+/// the affected table is returned if known.
+fn check(t: &[String]) -> Vec<(LintRule, Option<String>)> {
+    let mut found = Vec::new();
+
+    if matches_at(t, 0, &["CREATE"]) {
+        let mut idx = 1;
+
+        if matches_at(t, idx, &["UNIQUE"]) {
+            idx += 1;
+        }
+
+        if !matches_at(t, idx, &["INDEX"]) || matches_at(t, idx + 1, &["CONCURRENTLY"]) {
+            return found;
+        }
+
+        let table = t
+            .iter()
+            .position(|tok| tok == "ON")
+            .map(|on| {
+                let mut idx = on + 1;
+                if matches_at(t, idx, &["ONLY"]) {
+                    idx += 1;
+                }
+                idx
+            })
+            .and_then(|idx| object_name(t, idx))
+            .map(|(name, _)| name);
+
+        found.push((LintRule::NonConcurrentIndex, table));
+        return found;
+    }
+
+    if !matches_at(t, 0, &["ALTER", "TABLE"]) {
+        return found;
+    }
+
+    let mut idx = 2;
+
+    if matches_at(t, idx, &["IF", "EXISTS"]) {
+        idx += 2;
+    }
+
+    if matches_at(t, idx, &["ONLY"]) {
+        idx += 1;
+    }
+
+    let Some((table, idx)) = object_name(t, idx) else {
+        return found;
+    };
+
+    for action in split_top_level(&t[idx..]) {
+        if matches_at(action, 0, &["DROP"]) && !matches_at(action, 1, &["CONSTRAINT"]) {
+            found.push((LintRule::DropColumn, Some(table.clone())));
+        }
+
+        if matches_at(action, 0, &["ALTER"]) {
+            let mut idx = 1;
+            if matches_at(action, idx, &["COLUMN"]) {
+                idx += 1;
+            }
+            // The column name.
+            idx += 1;
+
+            if matches_at(action, idx, &["TYPE"])
+                || matches_at(action, idx, &["SET", "DATA", "TYPE"])
+            {
+                found.push((LintRule::AlterColumnType, Some(table.clone())));
+            }
+        }
+
+        if matches_at(action, 0, &["ADD"])
+            && ![
+                "CONSTRAINT",
+                "PRIMARY",
+                "UNIQUE",
+                "FOREIGN",
+                "CHECK",
+                "EXCLUDE",
+            ]
+            .into_iter()
+            .any(|kw| matches_at(action, 1, &[kw]))
+        {
+            let not_null = action.windows(2).any(|w| w[0] == "NOT" && w[1] == "NULL");
+            let has_value = action
+                .iter()
+                .any(|tok| tok == "DEFAULT" || tok == "GENERATED");
+
+            if not_null && !has_value {
+                found.push((LintRule::NotNullWithoutDefault, Some(table.clone())));
+            }
+        }
+    }
+
+    found
+}
+
+pub(crate) fn matches_at(tokens: &[String], idx: usize, expected: &[&str]) -> bool {
+    tokens.len() >= idx + expected.len()
+        && tokens[idx..idx + expected.len()]
+            .iter()
+            .zip(expected)
+            .all(|(tok, exp)| tok == exp)
+}
+
+/// Read a possibly qualified object name starting at the given index,
+/// the name and the index after the name are returned.
+///
+/// Unquoted identifiers are folded to lower case.
+pub(crate) fn object_name(tokens: &[String], mut idx: usize) -> Option<(String, usize)> {
+    let mut name = String::new();
+
+    loop {
+        let tok = tokens.get(idx)?;
+
+        if !is_identifier(tok) {
+            return None;
+        }
+
+        if tok.starts_with('"') {
+            name += tok;
+        } else {
+            name += &tok.to_lowercase();
+        }
+        idx += 1;
+
+        if tokens.get(idx).is_some_and(|tok| tok == ".") {
+            name.push('.');
+            idx += 1;
+        } else {
+            break;
+        }
+    }
+
+    Some((name, idx))
+}
+
+/// The unqualified and unquoted name of a table used for comparisons,
+/// tables in migrations are rarely qualified.
+fn table_key(name: &str) -> String {
+    let name = match name.rsplit_once('.') {
+        Some((_, name)) => name,
+        None => name,
+    };
+
+    name.trim_matches(['"', '`']).to_lowercase()
+}
+
+/// Tables created by the given statements.
+fn created_tables(statements: &[Statement]) -> Vec<String> {
+    statements
+        .iter()
+        .filter_map(|stmt| {
+            let t = &stmt.tokens;
+
+            if !matches_at(t, 0, &["CREATE"]) {
+                return None;
+            }
+
+            let mut idx = 1;
+            while matches_at(t, idx, &["TEMP"])
+                || matches_at(t, idx, &["TEMPORARY"])
+                || matches_at(t, idx, &["UNLOGGED"])
+            {
+                idx += 1;
+            }
+
+            if !matches_at(t, idx, &["TABLE"]) {
+                return None;
+            }
+            idx += 1;
+
+            if matches_at(t, idx, &["IF", "NOT", "EXISTS"]) {
+                idx += 3;
+            }
+
+            object_name(t, idx).map(|(name, _)| table_key(&name))
+        })
+        .collect()
+}
+
+fn is_identifier(token: &str) -> bool {
+    token.starts_with(['"', '`'])
+        || token
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+}
+
+/// Split tokens at top-level commas.
+pub(crate) fn split_top_level(tokens: &[String]) -> Vec<&[String]> {
+    let mut parts = Vec::new();
+    let mut depth = 0_usize;
+    let mut start = 0;
+
+    for (idx, tok) in tokens.iter().enumerate() {
+        match tok.as_str() {
+            "(" => depth += 1,
+            ")" => depth = depth.saturating_sub(1),
+            "," if depth == 0 => {
+                parts.push(&tokens[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+
+    parts.push(&tokens[start..]);
+    parts
+}
+
+pub(crate) struct Statement {
+    /// The statement with normalized whitespace and without comments.
+    pub(crate) sql: String,
+    /// Upper-case keywords and identifiers, quoted identifiers,
+    /// punctuation, literals are replaced with `''`.
+    pub(crate) tokens: Vec<String>,
+}
+
+pub(crate) struct Parsed {
+    pub(crate) statements: Vec<Statement>,
+    comments: Vec<String>,
+}
+
+/// Split SQL into statements and collect the comments.
+///
+/// The statements are split with [`split_statements`], so the linter sees
+/// the same statements that `MigrationContext::execute_batch` executes.
+pub(crate) fn parse(sql: &str) -> Parsed {
+    let comments = tokens(sql)
+        .filter(|token| token.kind == TokenKind::Comment)
+        .map(|token| {
+            let text = token.text;
+            match text.strip_prefix("/*") {
+                Some(text) => text.strip_suffix("*/").unwrap_or(text).to_string(),
+                None => text.strip_prefix("--").unwrap_or(text).to_string(),
+            }
+        })
+        .collect();
+
+    let statements = split_statements(sql)
+        .into_iter()
+        .filter_map(|statement| {
+            let mut text = String::new();
+            let mut tokens_upper: Vec<String> = Vec::new();
+
+            for token in tokens(statement) {
+                match token.kind {
+                    TokenKind::Comment => {
+                        text.push(' ');
+                        continue;
+                    }
+                    TokenKind::Whitespace => {}
+                    TokenKind::Quoted if !token.text.starts_with('\'') => {
+                        tokens_upper.push(token.text.to_string());
+                    }
+                    TokenKind::Quoted | TokenKind::DollarQuoted => tokens_upper.push("''".into()),
+                    TokenKind::Word => tokens_upper.push(token.text.to_uppercase()),
+                    TokenKind::Other => tokens_upper.push(token.text.to_string()),
+                }
+
+                text += token.text;
+            }
+
+            (!tokens_upper.is_empty()).then(|| Statement {
+                sql: text.split_whitespace().collect::<Vec<_>>().join(" "),
+                tokens: tokens_upper,
+            })
+        })
+        .collect();
+
+    Parsed {
+        statements,
+        comments,
+    }
+}
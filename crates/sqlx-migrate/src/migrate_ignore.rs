@@ -0,0 +1,37 @@
+//! Support for `.migrateignore` files in migrations directories.
+//!
+//! The file uses gitignore syntax, matching files are not considered
+//! to be migrations.
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::Path;
+
+/// The name of the ignore file in the migrations directory.
+pub(crate) const IGNORE_FILE: &str = ".migrateignore";
+
+/// The ignore rules of a migrations directory.
+pub(crate) struct MigrateIgnore(Gitignore);
+
+impl MigrateIgnore {
+    /// Load the rules from the ignore file in the given directory,
+    /// a missing file ignores nothing.
+    pub(crate) fn load(migrations_path: &Path) -> Result<Self, ignore::Error> {
+        let path = migrations_path.join(IGNORE_FILE);
+
+        if !path.is_file() {
+            return Ok(Self(Gitignore::empty()));
+        }
+
+        let mut builder = GitignoreBuilder::new(migrations_path);
+
+        if let Some(error) = builder.add(path) {
+            return Err(error);
+        }
+
+        builder.build().map(Self)
+    }
+
+    /// Whether the given file in the migrations directory is ignored.
+    pub(crate) fn is_ignored(&self, path: &Path) -> bool {
+        self.0.matched(path, path.is_dir()).is_ignore()
+    }
+}
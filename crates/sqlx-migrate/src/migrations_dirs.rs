@@ -0,0 +1,56 @@
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+};
+
+/// One or more migrations directories.
+///
+/// The migrations of all the directories are merged into a single set
+/// ordered by their dates, so that larger workspaces can keep migrations
+/// next to the modules that own them, e.g.
+/// `["migrations/core", "migrations/billing"]`.
+///
+/// Migrations with the same name or date in different directories
+/// are rejected.
+pub trait MigrationsDirs {
+    /// The directories in the given order.
+    fn migrations_dirs(&self) -> Vec<PathBuf>;
+}
+
+macro_rules! impl_single {
+    ($($ty:ty),*) => {
+        $(
+            impl MigrationsDirs for $ty {
+                fn migrations_dirs(&self) -> Vec<PathBuf> {
+                    vec![AsRef::<Path>::as_ref(self).to_path_buf()]
+                }
+            }
+        )*
+    };
+}
+
+impl_single!(str, String, Path, PathBuf, OsStr, OsString);
+
+impl<T: MigrationsDirs + ?Sized> MigrationsDirs for &T {
+    fn migrations_dirs(&self) -> Vec<PathBuf> {
+        (**self).migrations_dirs()
+    }
+}
+
+impl<P: AsRef<Path>> MigrationsDirs for [P] {
+    fn migrations_dirs(&self) -> Vec<PathBuf> {
+        self.iter().map(|p| p.as_ref().to_path_buf()).collect()
+    }
+}
+
+impl<P: AsRef<Path>, const N: usize> MigrationsDirs for [P; N] {
+    fn migrations_dirs(&self) -> Vec<PathBuf> {
+        self.as_slice().migrations_dirs()
+    }
+}
+
+impl<P: AsRef<Path>> MigrationsDirs for Vec<P> {
+    fn migrations_dirs(&self) -> Vec<PathBuf> {
+        self.as_slice().migrations_dirs()
+    }
+}
@@ -0,0 +1,142 @@
+//! Webhook notifications about applied migrations.
+//!
+//! Notifications are meant for deployment channels, a failed notification
+//! is logged but it never fails the migrations.
+use crate::{Error, MigrationSummary};
+use futures_channel::oneshot;
+use std::{thread, time::Duration};
+
+/// A migration event that is sent to a [`Webhook`].
+#[derive(Debug)]
+pub enum MigrationEvent<'a> {
+    /// Migrations are about to be applied.
+    Started {
+        /// The version the migrations are applied up to.
+        target_version: u64,
+    },
+    /// The migrations were applied.
+    Succeeded {
+        /// The summary of the applied migrations.
+        summary: &'a MigrationSummary,
+    },
+    /// Applying the migrations failed.
+    Failed {
+        /// The version the migrations were applied up to.
+        target_version: u64,
+        /// The error that occurred.
+        error: &'a Error,
+    },
+}
+
+impl MigrationEvent<'_> {
+    /// The name of the event in the payload.
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            MigrationEvent::Started { .. } => "started",
+            MigrationEvent::Succeeded { .. } => "succeeded",
+            MigrationEvent::Failed { .. } => "failed",
+        }
+    }
+
+    /// The JSON payload of the event.
+    ///
+    /// Payloads have an `event` name and a human-readable `text`,
+    /// so they can be posted to chat webhooks (e.g. Slack) as they are.
+    #[must_use]
+    pub fn payload(&self) -> serde_json::Value {
+        let event = self.name();
+
+        match self {
+            MigrationEvent::Started { target_version } => serde_json::json!({
+                "event": event,
+                "text": format!("Applying migrations up to version {target_version}"),
+                "target_version": target_version,
+            }),
+            MigrationEvent::Succeeded { summary } => {
+                let old_version = summary.old_version.unwrap_or(0);
+                let new_version = summary.new_version.unwrap_or(0);
+
+                serde_json::json!({
+                    "event": event,
+                    "text": format!("Migrations applied (version {old_version} -> {new_version})"),
+                    "old_version": summary.old_version,
+                    "new_version": summary.new_version,
+                })
+            }
+            MigrationEvent::Failed {
+                target_version,
+                error,
+            } => serde_json::json!({
+                "event": event,
+                "text": format!("Applying migrations up to version {target_version} failed: {error}"),
+                "target_version": target_version,
+                "error": error.to_string(),
+            }),
+        }
+    }
+}
+
+/// A webhook that receives the JSON payloads of [`MigrationEvent`]s.
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    url: String,
+    timeout: Duration,
+}
+
+impl Webhook {
+    /// The default timeout of the requests.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Create a webhook for the given URL.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Set the timeout of the requests.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send the event to the webhook.
+    ///
+    /// The request is sent from a separate thread so that it does not block
+    /// the async runtime, this waits until it completes. Errors are logged.
+    pub async fn notify(&self, event: &MigrationEvent<'_>) {
+        let (tx, rx) = oneshot::channel();
+
+        let url = self.url.clone();
+        let timeout = self.timeout;
+        let payload = event.payload();
+
+        let spawned = thread::Builder::new()
+            .name("sqlx-migrate-webhook".into())
+            .spawn(move || {
+                let res = ureq::post(&url).timeout(timeout).send_json(payload);
+                let _ = tx.send(res.map(drop).map_err(|error| error.to_string()));
+            });
+
+        if let Err(error) = spawned {
+            tracing::warn!(event = event.name(), %error, "failed to send webhook notification");
+            return;
+        }
+
+        match rx.await {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => {
+                tracing::warn!(event = event.name(), %error, "failed to send webhook notification");
+            }
+            Err(oneshot::Canceled) => {
+                tracing::warn!(
+                    event = event.name(),
+                    "failed to send webhook notification, the request panicked"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Registration of migrations across crates.
+//!
+//! Library crates can contribute migrations to an application without the
+//! application listing them, by annotating functions that return migrations
+//! with [`register_migration`]:
+//!
+//! ```ignore
+//! use sqlx_migrate::{prelude::*, register::register_migration};
+//! use sqlx::{Executor, Postgres};
+//!
+//! #[register_migration(key = "20240101000000_plugin_events")]
+//! fn plugin_events() -> Migration<Postgres> {
+//!     Migration::new("plugin_events", |ctx| {
+//!         Box::pin(async move {
+//!             ctx.tx().execute("CREATE TABLE plugin_events ();").await?;
+//!             Ok(())
+//!         })
+//!     })
+//! }
+//! ```
+//!
+//! The application collects them with [`registered_migrations`].
+//! Only migrations of crates that are linked into the application are collected.
+use crate::Migration;
+use sqlx::Database;
+
+#[doc(hidden)]
+pub use inventory;
+
+/// Register the migration returned by the annotated function.
+///
+/// The migrations are ordered by their keys, the key defaults to the name
+/// of the function and can be set with `#[register_migration(key = "...")]`.
+/// Date-prefixed keys keep the order of migrations of different crates stable.
+pub use sqlx_migrate_macros::register_migration;
+
+/// A migration registered with [`register_migration`].
+pub struct RegisteredMigration<Db: Database> {
+    key: &'static str,
+    migration: fn() -> Migration<Db>,
+}
+
+impl<Db: Database> RegisteredMigration<Db> {
+    #[doc(hidden)]
+    #[must_use]
+    pub const fn new(key: &'static str, migration: fn() -> Migration<Db>) -> Self {
+        Self { key, migration }
+    }
+
+    /// The key the migration is ordered by.
+    #[must_use]
+    pub fn key(&self) -> &'static str {
+        self.key
+    }
+}
+
+#[cfg(feature = "postgres")]
+inventory::collect!(RegisteredMigration<sqlx::Postgres>);
+
+#[cfg(feature = "sqlite")]
+inventory::collect!(RegisteredMigration<sqlx::Sqlite>);
+
+#[cfg(feature = "any")]
+inventory::collect!(RegisteredMigration<sqlx::Any>);
+
+/// All registered migrations for the database, ordered by their keys.
+///
+/// # Panics
+///
+/// If multiple migrations are registered with the same key.
+#[must_use]
+pub fn registered_migrations<Db>() -> Vec<Migration<Db>>
+where
+    Db: Database,
+    RegisteredMigration<Db>: inventory::Collect,
+{
+    let mut registered: Vec<&RegisteredMigration<Db>> = inventory::iter::<RegisteredMigration<Db>>
+        .into_iter()
+        .collect();
+
+    registered.sort_by_key(|mig| mig.key);
+
+    for pair in registered.windows(2) {
+        assert!(
+            pair[0].key != pair[1].key,
+            "duplicate registered migration key: {}",
+            pair[0].key
+        );
+    }
+
+    registered.iter().map(|mig| (mig.migration)()).collect()
+}
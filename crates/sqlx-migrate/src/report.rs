@@ -0,0 +1,342 @@
+//! Tables of migration statuses and summaries as rendered by the CLI,
+//! for applications that embed the migrator and want to show
+//! the same tables in their logs or user interfaces.
+
+use crate::{
+    db::MigrationRun, Error, MigrationPhase, MigrationStatus, MigrationSummary, Rehearsal,
+};
+use comfy_table::{Cell, CellAlignment, ContentArrangement, Table};
+use std::time::Duration;
+
+// The number of most recent runs shown in the history of `timings_table`.
+const TIMINGS_HISTORY: usize = 5;
+
+/// Whether the migration is reported as valid in [`status_table`].
+///
+/// Migrations are invalid if they are only applied in the database,
+/// or their checksums, names or versions do not match.
+#[must_use]
+pub fn status_ok(status: &MigrationStatus) -> bool {
+    if status.missing_local {
+        return false;
+    }
+
+    match &status.applied {
+        Some(applied) => status.checksum_ok && status.name_ok && status.version == applied.version,
+        None => true,
+    }
+}
+
+/// Render the table of the given statuses, see [`Migrator::status`](crate::Migrator::status).
+///
+/// `wide` adds the creation dates and the source paths of the migrations.
+#[must_use]
+pub fn status_table(status: &[MigrationStatus], wide: bool) -> String {
+    let mut table = Table::new();
+
+    let mut header = Vec::from([
+        Cell::new("Version").set_alignment(CellAlignment::Center),
+        Cell::new("Name").set_alignment(CellAlignment::Center),
+        Cell::new("Applied").set_alignment(CellAlignment::Center),
+        Cell::new("Valid").set_alignment(CellAlignment::Center),
+        Cell::new("Revertible").set_alignment(CellAlignment::Center),
+        Cell::new("Phase").set_alignment(CellAlignment::Center),
+        Cell::new("Rows Affected").set_alignment(CellAlignment::Center),
+    ]);
+
+    if wide {
+        header.push(Cell::new("Created At").set_alignment(CellAlignment::Center));
+        header.push(Cell::new("Source").set_alignment(CellAlignment::Center));
+    }
+
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(header);
+
+    for mig in status {
+        let ok = status_ok(mig);
+
+        let mut row = Vec::from([
+            Cell::new(mig.version.to_string().as_str()).set_alignment(CellAlignment::Center),
+            Cell::new(&mig.name).set_alignment(CellAlignment::Center),
+            Cell::new(match &mig.applied {
+                Some(applied) if applied.skipped => "skipped",
+                Some(_) => "x",
+                None => "",
+            })
+            .set_alignment(CellAlignment::Center),
+            Cell::new(if ok { "x" } else { "INVALID" }).set_alignment(CellAlignment::Center),
+            Cell::new(if mig.reversible { "x" } else { "" }).set_alignment(CellAlignment::Center),
+            Cell::new(mig.phase.map_or("", MigrationPhase::as_str))
+                .set_alignment(CellAlignment::Center),
+            Cell::new(
+                mig.applied
+                    .as_ref()
+                    .and_then(|applied| applied.rows_affected)
+                    .map_or_else(String::new, |rows| rows.to_string()),
+            )
+            .set_alignment(CellAlignment::Right),
+        ]);
+
+        if wide {
+            row.push(
+                Cell::new(mig.metadata.created_at.as_deref().unwrap_or(""))
+                    .set_alignment(CellAlignment::Center),
+            );
+            row.push(Cell::new(mig.metadata.source_path.as_deref().unwrap_or("")));
+        }
+
+        table.add_row(row);
+    }
+
+    table.to_string()
+}
+
+/// Render the table of the local migrations without their database
+/// statuses, see [`MigrationStatus::local`].
+#[must_use]
+pub fn local_table(status: &[MigrationStatus]) -> String {
+    let mut table = Table::new();
+
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(Vec::from([
+            Cell::new("Version").set_alignment(CellAlignment::Center),
+            Cell::new("Name").set_alignment(CellAlignment::Center),
+            Cell::new("Revertible").set_alignment(CellAlignment::Center),
+            Cell::new("Phase").set_alignment(CellAlignment::Center),
+            Cell::new("Created At").set_alignment(CellAlignment::Center),
+            Cell::new("Source").set_alignment(CellAlignment::Center),
+        ]));
+
+    for mig in status {
+        table.add_row(Vec::from([
+            Cell::new(mig.version.to_string()).set_alignment(CellAlignment::Center),
+            Cell::new(&mig.name).set_alignment(CellAlignment::Center),
+            Cell::new(if mig.reversible { "x" } else { "" }).set_alignment(CellAlignment::Center),
+            Cell::new(mig.phase.map_or("", MigrationPhase::as_str))
+                .set_alignment(CellAlignment::Center),
+            Cell::new(mig.metadata.created_at.as_deref().unwrap_or(""))
+                .set_alignment(CellAlignment::Center),
+            Cell::new(mig.metadata.source_path.as_deref().unwrap_or("")),
+        ]));
+    }
+
+    table.to_string()
+}
+
+/// Render the table of the versions before and after the
+/// operation, and the number of applied and reverted migrations.
+#[must_use]
+pub fn summary_table(summary: &MigrationSummary) -> String {
+    let mut table = Table::new();
+
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(Vec::from([
+            Cell::new("Old Version").set_alignment(CellAlignment::Center),
+            Cell::new("New Version").set_alignment(CellAlignment::Center),
+            Cell::new("Applied Migrations").set_alignment(CellAlignment::Center),
+            Cell::new("Reverted Migrations").set_alignment(CellAlignment::Center),
+        ]));
+
+    let mut s = Vec::<Cell>::new();
+
+    s.push(match summary.old_version {
+        Some(v) => Cell::new(v.to_string()).set_alignment(CellAlignment::Center),
+        None => "".into(),
+    });
+
+    s.push(match summary.new_version {
+        Some(v) => Cell::new(v.to_string()).set_alignment(CellAlignment::Center),
+        None => "".into(),
+    });
+
+    s.push(match (summary.old_version, summary.new_version) {
+        (Some(old), Some(new)) => {
+            if new >= old {
+                Cell::new((new - old).to_string()).set_alignment(CellAlignment::Center)
+            } else {
+                Cell::new("0").set_alignment(CellAlignment::Center)
+            }
+        }
+        (None, Some(new)) => Cell::new(new.to_string()).set_alignment(CellAlignment::Center),
+        (_, None) => Cell::new("0").set_alignment(CellAlignment::Center),
+    });
+
+    s.push(match (summary.old_version, summary.new_version) {
+        (Some(old), Some(new)) => {
+            if new <= old {
+                Cell::new((old - new).to_string()).set_alignment(CellAlignment::Center)
+            } else {
+                Cell::new("0").set_alignment(CellAlignment::Center)
+            }
+        }
+        (Some(old), None) => Cell::new(old.to_string()).set_alignment(CellAlignment::Center),
+        (None, _) => Cell::new("0").set_alignment(CellAlignment::Center),
+    });
+
+    table.add_row(s);
+
+    table.to_string()
+}
+
+/// Render the table of the migrations that were removed from the
+/// migrations table without being reverted, if there are any.
+#[must_use]
+pub fn irreversible_table(summary: &MigrationSummary) -> Option<String> {
+    if summary.irreversible.is_empty() {
+        return None;
+    }
+
+    let mut table = Table::new();
+
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(Vec::from([
+            Cell::new("Version").set_alignment(CellAlignment::Center),
+            Cell::new("Not Reverted").set_alignment(CellAlignment::Center),
+        ]));
+
+    for (version, name) in &summary.irreversible {
+        table.add_row(Vec::from([
+            Cell::new(version.to_string()).set_alignment(CellAlignment::Center),
+            Cell::new(name).set_alignment(CellAlignment::Center),
+        ]));
+    }
+
+    Some(table.to_string())
+}
+
+/// Render the table of the rehearsed migrations and their durations,
+/// followed by the migration that failed, see [`Migrator::rehearse`](crate::Migrator::rehearse).
+#[must_use]
+pub fn rehearsal_table(rehearsal: &Rehearsal) -> String {
+    let mut table = Table::new();
+
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(Vec::from([
+            Cell::new("Version").set_alignment(CellAlignment::Center),
+            Cell::new("Name").set_alignment(CellAlignment::Center),
+            Cell::new("Duration").set_alignment(CellAlignment::Center),
+            Cell::new("Result").set_alignment(CellAlignment::Center),
+        ]));
+
+    for mig in &rehearsal.applied {
+        table.add_row(Vec::from([
+            Cell::new(mig.version.to_string()).set_alignment(CellAlignment::Center),
+            Cell::new(&mig.name).set_alignment(CellAlignment::Center),
+            Cell::new(format_duration(mig.execution_time)).set_alignment(CellAlignment::Right),
+            Cell::new("ok").set_alignment(CellAlignment::Center),
+        ]));
+    }
+
+    if let Some(Error::Migration { name, version, .. }) = &rehearsal.error {
+        table.add_row(Vec::from([
+            Cell::new(version.to_string()).set_alignment(CellAlignment::Center),
+            Cell::new(name).set_alignment(CellAlignment::Center),
+            Cell::new(""),
+            Cell::new("FAILED").set_alignment(CellAlignment::Center),
+        ]));
+    }
+
+    table.to_string()
+}
+
+/// Render the table of the recorded run durations of every migration
+/// per database, see [`Migrator::runs`](crate::Migrator::runs).
+///
+/// The databases are given with their labels, e.g. the environments.
+/// The change compares the last run to the average of the runs before it,
+/// so that regressions of migrations that are run repeatedly stand out.
+#[must_use]
+pub fn timings_table(databases: &[(String, Vec<MigrationRun>)]) -> String {
+    let mut table = Table::new();
+
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(Vec::from([
+            Cell::new("Version").set_alignment(CellAlignment::Center),
+            Cell::new("Name").set_alignment(CellAlignment::Center),
+            Cell::new("Database").set_alignment(CellAlignment::Center),
+            Cell::new("Runs").set_alignment(CellAlignment::Center),
+            Cell::new("Last").set_alignment(CellAlignment::Center),
+            Cell::new("Average").set_alignment(CellAlignment::Center),
+            Cell::new("Max").set_alignment(CellAlignment::Center),
+            Cell::new("Change").set_alignment(CellAlignment::Center),
+            Cell::new("History").set_alignment(CellAlignment::Center),
+        ]));
+
+    let mut migrations = databases
+        .iter()
+        .flat_map(|(_, runs)| runs.iter().map(|run| (run.version, run.name.as_str())))
+        .collect::<Vec<_>>();
+
+    // Deferred migrations have no versions and are listed last.
+    migrations.sort_by_key(|(version, name)| (version.is_none(), *version, *name));
+    migrations.dedup();
+
+    for (version, name) in migrations {
+        for (database, runs) in databases {
+            let durations = runs
+                .iter()
+                .filter(|run| run.version == version && run.name == name)
+                .map(|run| run.execution_time)
+                .collect::<Vec<_>>();
+
+            let Some((last, previous)) = durations.split_last() else {
+                continue;
+            };
+
+            table.add_row(Vec::from([
+                Cell::new(version.map_or_else(String::new, |v| v.to_string()))
+                    .set_alignment(CellAlignment::Center),
+                Cell::new(name).set_alignment(CellAlignment::Center),
+                Cell::new(database).set_alignment(CellAlignment::Center),
+                Cell::new(durations.len().to_string()).set_alignment(CellAlignment::Center),
+                Cell::new(format_duration(*last)).set_alignment(CellAlignment::Right),
+                Cell::new(average(&durations).map_or_else(String::new, format_duration))
+                    .set_alignment(CellAlignment::Right),
+                Cell::new(
+                    durations
+                        .iter()
+                        .max()
+                        .copied()
+                        .map_or_else(String::new, format_duration),
+                )
+                .set_alignment(CellAlignment::Right),
+                Cell::new(average(previous).filter(|avg| !avg.is_zero()).map_or_else(
+                    String::new,
+                    |avg| {
+                        let change = (last.as_secs_f64() / avg.as_secs_f64() - 1.0) * 100.0;
+                        format!("{change:+.0}%")
+                    },
+                ))
+                .set_alignment(CellAlignment::Right),
+                Cell::new(
+                    durations
+                        .iter()
+                        .skip(durations.len().saturating_sub(TIMINGS_HISTORY))
+                        .map(|duration| format_duration(*duration))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+            ]));
+        }
+    }
+
+    table.to_string()
+}
+
+fn average(durations: &[Duration]) -> Option<Duration> {
+    if durations.is_empty() {
+        return None;
+    }
+
+    Some(durations.iter().sum::<Duration>() / durations.len() as u32)
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{duration:.1?}")
+}
@@ -0,0 +1,288 @@
+//! Multiple independent migration sets in a single database.
+//!
+//! Each [`MigrationSet`] has its own local migrations and migrations table,
+//! a [`MigrationSetRegistry`] verifies and applies them together.
+use crate::{
+    db, Error, Migration, MigrationStatus, MigrationSummary, Migrator, MigratorOptions,
+    DEFAULT_MIGRATIONS_TABLE,
+};
+use sqlx::{Connection, Database, Executor};
+use std::{borrow::Cow, collections::HashMap};
+
+/// An independent set of migrations with its own migrations table.
+pub struct MigrationSet<Db: Database> {
+    name: Cow<'static, str>,
+    table: Cow<'static, str>,
+    migrations: Box<dyn Fn() -> Vec<Migration<Db>>>,
+}
+
+impl<Db: Database> MigrationSet<Db> {
+    /// Create a new migration set with the given name.
+    ///
+    /// The migrations are created with the given function whenever
+    /// they are needed, e.g. the `migrations` function of generated code.
+    ///
+    /// The migrations table of the set is named after the default
+    /// migrations table and the name of the set, e.g. `_sqlx_migrations_core`.
+    pub fn new<I>(name: impl Into<Cow<'static, str>>, migrations: impl Fn() -> I + 'static) -> Self
+    where
+        I: IntoIterator<Item = Migration<Db>>,
+    {
+        let name = name.into();
+
+        Self {
+            table: Cow::Owned(format!("{DEFAULT_MIGRATIONS_TABLE}_{name}")),
+            name,
+            migrations: Box::new(move || migrations().into_iter().collect()),
+        }
+    }
+
+    /// Override the migrations table of the set.
+    ///
+    /// The table name is used as-is in queries, **DO NOT USE UNTRUSTED STRINGS**.
+    #[must_use]
+    pub fn with_table(mut self, table: impl Into<Cow<'static, str>>) -> Self {
+        self.table = table.into();
+        self
+    }
+
+    /// The name of the set.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+
+    /// The migrations table of the set.
+    #[must_use]
+    pub fn table(&self) -> &str {
+        self.table.as_ref()
+    }
+}
+
+impl<Db: Database> std::fmt::Debug for MigrationSet<Db> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MigrationSet")
+            .field("name", &self.name)
+            .field("table", &self.table)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The status of the migrations of a [`MigrationSet`].
+#[derive(Debug, Clone)]
+pub struct MigrationSetStatus {
+    /// The name of the set.
+    pub name: String,
+    /// The status of the migrations in the set.
+    pub migrations: Vec<MigrationStatus>,
+}
+
+/// Verifies and applies multiple migration sets in the same database.
+///
+/// Every operation connects to the database separately for each set,
+/// the sets are processed in the order they were added.
+pub struct MigrationSetRegistry<Db: Database> {
+    connect_options: <Db::Connection as Connection>::Options,
+    options: MigratorOptions,
+    sets: Vec<MigrationSet<Db>>,
+}
+
+impl<Db> MigrationSetRegistry<Db>
+where
+    Db: Database,
+    Db::Connection: db::Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    /// Create a new registry that connects with the given options.
+    pub fn new(connect_options: <Db::Connection as Connection>::Options) -> Self {
+        Self {
+            connect_options,
+            options: MigratorOptions::default(),
+            sets: Vec::new(),
+        }
+    }
+
+    /// Override the options of the migrators of all sets.
+    pub fn set_options(&mut self, options: MigratorOptions) {
+        self.options = options;
+    }
+
+    /// Add a migration set to the registry.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if a set with the same name or
+    /// migrations table already exists.
+    pub fn add_set(&mut self, set: MigrationSet<Db>) -> Result<(), Error> {
+        if self
+            .sets
+            .iter()
+            .any(|other| other.name == set.name || other.table == set.table)
+        {
+            return Err(Error::DuplicateMigrationSet { name: set.name });
+        }
+
+        self.sets.push(set);
+        Ok(())
+    }
+
+    /// All the migration sets.
+    #[must_use]
+    pub fn sets(&self) -> &[MigrationSet<Db>] {
+        &self.sets
+    }
+
+    /// Verify the migrations of all sets.
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`Migrator::verify`] are returned for the first invalid set.
+    pub async fn verify(&self) -> Result<(), Error> {
+        for set in &self.sets {
+            self.migrator(set)
+                .await?
+                .verify()
+                .await
+                .map_err(|error| set_error(set, error))?;
+        }
+
+        Ok(())
+    }
+
+    /// List the local and applied migrations of all sets.
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`Migrator::status`] are returned.
+    pub async fn status(&self) -> Result<Vec<MigrationSetStatus>, Error> {
+        let mut status = Vec::with_capacity(self.sets.len());
+
+        for set in &self.sets {
+            status.push(MigrationSetStatus {
+                name: set.name.to_string(),
+                migrations: self
+                    .migrator(set)
+                    .await?
+                    .status()
+                    .await
+                    .map_err(|error| set_error(set, error))?,
+            });
+        }
+
+        Ok(status)
+    }
+
+    /// Check the dependencies of the pending migrations on other sets
+    /// (see [`Migration::depends_on`]).
+    ///
+    /// Sets are expected to be fully migrated in the order they were added,
+    /// so migrations can depend on pending migrations of preceding sets.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::UnmetDependency`] is returned for the first migration with
+    /// a dependency that would not be met, or depends on an unknown set.
+    ///
+    /// Connection and database errors are returned.
+    pub async fn check_dependencies(&self) -> Result<(), Error> {
+        let status = self.status().await?;
+
+        // The versions of the sets at the time the current set is migrated.
+        let mut versions: HashMap<&str, u64> = self
+            .sets
+            .iter()
+            .zip(&status)
+            .map(|(set, status)| {
+                let applied = status
+                    .migrations
+                    .iter()
+                    .filter(|mig| mig.applied.is_some())
+                    .count();
+                (set.name(), applied as u64)
+            })
+            .collect();
+
+        for set in &self.sets {
+            let migrations = (set.migrations)();
+            let db_version = versions.get(set.name()).copied().unwrap_or(0);
+
+            for mig in migrations.iter().skip(db_version as usize) {
+                for (dependency, version) in mig.dependencies() {
+                    if versions
+                        .get(dependency.as_ref())
+                        .is_none_or(|v| v < version)
+                    {
+                        return Err(Error::UnmetDependency {
+                            set: set.name.clone(),
+                            name: mig.name.clone(),
+                            dependency: dependency.clone(),
+                            version: *version,
+                        });
+                    }
+                }
+            }
+
+            versions.insert(set.name(), migrations.len() as u64);
+        }
+
+        Ok(())
+    }
+
+    /// Apply all migrations of all sets.
+    ///
+    /// All sets are verified and the dependencies of the pending migrations are
+    /// checked before any migrations are applied, the migrations of each set are
+    /// applied in a separate transaction.
+    ///
+    /// The summaries are returned along with the names of the sets.
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`MigrationSetRegistry::verify`], [`MigrationSetRegistry::check_dependencies`]
+    /// and [`Migrator::migrate_all`] are returned, the sets before the failed one remain migrated.
+    pub async fn migrate_all(&self) -> Result<Vec<(String, MigrationSummary)>, Error> {
+        self.verify().await?;
+        self.check_dependencies().await?;
+
+        let mut summaries = Vec::with_capacity(self.sets.len());
+
+        for set in &self.sets {
+            let summary = self
+                .migrator(set)
+                .await?
+                .migrate_all()
+                .await
+                .map_err(|error| set_error(set, error))?;
+
+            summaries.push((set.name.to_string(), summary));
+        }
+
+        Ok(summaries)
+    }
+
+    async fn migrator(&self, set: &MigrationSet<Db>) -> Result<Migrator<Db>, Error> {
+        let mut migrator = Migrator::connect_with(&self.connect_options).await?;
+        migrator.set_options(self.options.clone());
+        migrator.set_migrations_table(&set.table);
+        migrator
+            .try_add_migrations((set.migrations)())
+            .map_err(|error| set_error(set, error))?;
+        Ok(migrator)
+    }
+}
+
+impl<Db: Database> std::fmt::Debug for MigrationSetRegistry<Db> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MigrationSetRegistry")
+            .field("options", &self.options)
+            .field("sets", &self.sets)
+            .finish_non_exhaustive()
+    }
+}
+
+fn set_error<Db: Database>(set: &MigrationSet<Db>, error: Error) -> Error {
+    Error::MigrationSet {
+        name: set.name.clone(),
+        error: Box::new(error),
+    }
+}
@@ -0,0 +1,371 @@
+//! Utilities for working with SQL text.
+use crate::lint::{matches_at, object_name, parse, split_top_level};
+use std::{borrow::Cow, collections::HashMap, hash::BuildHasher};
+
+/// Split SQL text into individual statements.
+///
+/// Semicolons do not end statements inside comments, string literals
+/// (including `E'...'` escape strings), quoted identifiers, dollar-quoted
+/// bodies and `BEGIN ... END` blocks of `CREATE` statements (e.g. `SQLite`
+/// triggers or `BEGIN ATOMIC` function bodies).
+///
+/// The statements are returned as written without the terminating semicolon,
+/// statements that contain only whitespace and comments are skipped.
+#[must_use]
+pub fn split_statements(sql: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+
+    let mut start = 0;
+
+    // Whether the current statement has anything other than comments.
+    let mut has_content = false;
+    let mut first_word: Option<&str> = None;
+    // Nesting of `BEGIN`/`CASE` ... `END` in `CREATE` statements.
+    let mut block_depth = 0_usize;
+
+    for token in tokens(sql) {
+        match token.kind {
+            TokenKind::Comment | TokenKind::Whitespace => {}
+            TokenKind::Word => {
+                let word = token.text;
+
+                if first_word.is_none() {
+                    first_word = Some(word);
+                } else if first_word.is_some_and(|w| w.eq_ignore_ascii_case("create")) {
+                    if word.eq_ignore_ascii_case("begin") || word.eq_ignore_ascii_case("case") {
+                        block_depth += 1;
+                    } else if word.eq_ignore_ascii_case("end") {
+                        block_depth = block_depth.saturating_sub(1);
+                    }
+                }
+
+                has_content = true;
+            }
+            TokenKind::Other if token.text == ";" && block_depth == 0 => {
+                if has_content {
+                    statements.push(sql[start..token.start].trim());
+                }
+
+                has_content = false;
+                first_word = None;
+                start = token.start + 1;
+            }
+            _ => has_content = true,
+        }
+    }
+
+    if has_content {
+        statements.push(sql[start..].trim());
+    }
+
+    statements
+}
+
+/// The kind of a [`Token`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TokenKind {
+    /// A line or block comment.
+    Comment,
+    /// A string literal or a quoted identifier.
+    Quoted,
+    /// A dollar-quoted string, e.g. a function body.
+    DollarQuoted,
+    /// A keyword, an identifier or a number.
+    Word,
+    Whitespace,
+    /// Any other character, e.g. punctuation.
+    Other,
+}
+
+/// A token of SQL text, see [`tokens`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Token<'a> {
+    pub(crate) kind: TokenKind,
+    pub(crate) text: &'a str,
+    /// The byte offset of the token in the SQL text.
+    pub(crate) start: usize,
+}
+
+/// Split SQL text into tokens, together the tokens cover the whole text.
+///
+/// This is the lexer shared by [`split_statements`] and the linter,
+/// so that both agree on what is a comment, a string or a statement.
+pub(crate) fn tokens(sql: &str) -> impl Iterator<Item = Token<'_>> {
+    let mut i = 0;
+
+    std::iter::from_fn(move || {
+        if i >= sql.len() {
+            return None;
+        }
+
+        let (kind, end) = next_token(sql, i);
+        let token = Token {
+            kind,
+            text: &sql[i..end],
+            start: i,
+        };
+        i = end;
+
+        Some(token)
+    })
+}
+
+/// The kind and the end of the token that starts at `i`.
+fn next_token(sql: &str, i: usize) -> (TokenKind, usize) {
+    let bytes = sql.as_bytes();
+    let c = bytes[i];
+
+    // Line comments.
+    if c == b'-' && bytes.get(i + 1) == Some(&b'-') {
+        let end = sql[i..].find('\n').map_or(bytes.len(), |p| i + p);
+        return (TokenKind::Comment, end);
+    }
+
+    // Block comments.
+    if c == b'/' && bytes.get(i + 1) == Some(&b'*') {
+        let end = sql[i + 2..]
+            .find("*/")
+            .map_or(bytes.len(), |p| i + 2 + p + 2);
+        return (TokenKind::Comment, end);
+    }
+
+    // String literals and quoted identifiers.
+    if c == b'\'' || c == b'"' || c == b'`' {
+        let backslash_escapes = c == b'\''
+            && i > 0
+            && matches!(bytes[i - 1], b'e' | b'E')
+            && !bytes
+                .get(i.wrapping_sub(2))
+                .is_some_and(|&b| is_word_byte(b));
+
+        let mut j = i + 1;
+        while j < bytes.len() {
+            if backslash_escapes && bytes[j] == b'\\' {
+                j += 2;
+                continue;
+            }
+            if bytes[j] == c {
+                if bytes.get(j + 1) == Some(&c) {
+                    j += 2;
+                    continue;
+                }
+                break;
+            }
+            j += 1;
+        }
+
+        return (TokenKind::Quoted, (j + 1).min(bytes.len()));
+    }
+
+    // Dollar-quoted strings.
+    if c == b'$' {
+        if let Some(tag_len) = dollar_tag_len(&bytes[i..]) {
+            let tag = &sql[i..i + tag_len];
+            let end = sql[i + tag_len..]
+                .find(tag)
+                .map_or(bytes.len(), |p| i + tag_len + p + tag_len);
+            return (TokenKind::DollarQuoted, end);
+        }
+    }
+
+    if is_word_byte(c) {
+        let end = bytes[i..]
+            .iter()
+            .position(|&b| !(is_word_byte(b) || b == b'$'))
+            .map_or(bytes.len(), |p| i + p);
+        return (TokenKind::Word, end);
+    }
+
+    if c.is_ascii_whitespace() {
+        return (TokenKind::Whitespace, i + 1);
+    }
+
+    // Other bytes are ASCII, the rest are part of words.
+    (TokenKind::Other, i + 1)
+}
+
+/// Replace `${name}` placeholders with the values of the given variables.
+///
+/// Names consist of ASCII letters, digits and underscores, placeholders of
+/// variables that are not defined are left as they are.
+#[must_use]
+pub fn substitute_variables<'a, S: BuildHasher>(
+    sql: &'a str,
+    variables: &HashMap<String, String, S>,
+) -> Cow<'a, str> {
+    if variables.is_empty() || !sql.contains("${") {
+        return Cow::Borrowed(sql);
+    }
+
+    let mut out = String::with_capacity(sql.len());
+    let mut rest = sql;
+
+    while let Some(start) = rest.find("${") {
+        out += &rest[..start];
+        rest = &rest[start..];
+
+        let name = rest[2..]
+            .find('}')
+            .map(|end| &rest[2..2 + end])
+            .filter(|name| {
+                !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+            });
+
+        if let Some((name, value)) = name.and_then(|name| Some((name, variables.get(name)?))) {
+            out += value;
+            rest = &rest[name.len() + 3..];
+        } else {
+            if let Some(name) = name {
+                tracing::warn!(variable = name, "undefined variable in migration SQL");
+            }
+
+            out += "${";
+            rest = &rest[2..];
+        }
+    }
+
+    out += rest;
+
+    Cow::Owned(out)
+}
+
+/// Generate SQL that reverts the given migration SQL.
+///
+/// `CREATE TABLE`, `CREATE INDEX` and `ALTER TABLE ... ADD COLUMN` statements
+/// are reverted in reverse order. Other statements cannot be reverted safely
+/// without knowing what they did, they are left as `TODO` comments.
+///
+/// ```
+/// use sqlx_migrate::sql::revert_sql;
+///
+/// let sql = revert_sql("CREATE TABLE users (id INT); ALTER TABLE users ADD COLUMN name TEXT;");
+/// assert_eq!(sql, "ALTER TABLE users DROP COLUMN name;\n\nDROP TABLE users;\n");
+/// ```
+#[must_use]
+pub fn revert_sql(sql: &str) -> String {
+    parse(sql)
+        .statements
+        .iter()
+        .rev()
+        .map(|stmt| match revert_statement(&stmt.tokens) {
+            Some(revert) => format!("{revert};\n"),
+            None => format!(
+                "-- TODO: revert the following statement manually:\n-- {};\n",
+                stmt.sql
+            ),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The statement that reverts a statement, if it can be reverted safely.
+fn revert_statement(t: &[String]) -> Option<String> {
+    if matches_at(t, 0, &["CREATE"]) {
+        let mut idx = 1;
+        while ["TEMP", "TEMPORARY", "UNLOGGED", "UNIQUE"]
+            .into_iter()
+            .any(|kw| matches_at(t, idx, &[kw]))
+        {
+            idx += 1;
+        }
+
+        let kind = if matches_at(t, idx, &["TABLE"]) {
+            "TABLE"
+        } else if matches_at(t, idx, &["INDEX"]) {
+            "INDEX"
+        } else {
+            return None;
+        };
+        idx += 1;
+
+        let concurrently = kind == "INDEX" && matches_at(t, idx, &["CONCURRENTLY"]);
+        if concurrently {
+            idx += 1;
+        }
+
+        if matches_at(t, idx, &["IF", "NOT", "EXISTS"]) {
+            idx += 3;
+        }
+
+        // Indexes without names cannot be dropped by name.
+        if kind == "INDEX" && matches_at(t, idx, &["ON"]) {
+            return None;
+        }
+
+        let (name, _) = object_name(t, idx)?;
+
+        return Some(if concurrently {
+            format!("DROP INDEX CONCURRENTLY {name}")
+        } else {
+            format!("DROP {kind} {name}")
+        });
+    }
+
+    if !matches_at(t, 0, &["ALTER", "TABLE"]) {
+        return None;
+    }
+
+    let mut idx = 2;
+
+    if matches_at(t, idx, &["IF", "EXISTS"]) {
+        idx += 2;
+    }
+
+    if matches_at(t, idx, &["ONLY"]) {
+        idx += 1;
+    }
+
+    let (table, idx) = object_name(t, idx)?;
+
+    let mut actions = Vec::new();
+
+    for action in split_top_level(&t[idx..]) {
+        if !matches_at(action, 0, &["ADD"])
+            || [
+                "CONSTRAINT",
+                "PRIMARY",
+                "UNIQUE",
+                "FOREIGN",
+                "CHECK",
+                "EXCLUDE",
+            ]
+            .into_iter()
+            .any(|kw| matches_at(action, 1, &[kw]))
+        {
+            return None;
+        }
+
+        let mut idx = 1;
+
+        if matches_at(action, idx, &["COLUMN"]) {
+            idx += 1;
+        }
+
+        if matches_at(action, idx, &["IF", "NOT", "EXISTS"]) {
+            idx += 3;
+        }
+
+        let (column, _) = object_name(action, idx)?;
+        actions.push(format!("DROP COLUMN {column}"));
+    }
+
+    actions.reverse();
+
+    Some(format!("ALTER TABLE {table} {}", actions.join(", ")))
+}
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || !b.is_ascii()
+}
+
+/// The length of the dollar-quote tag (e.g. `$body$`) at the start of the
+/// given bytes, positional parameters like `$1` are not tags.
+fn dollar_tag_len(bytes: &[u8]) -> Option<usize> {
+    if bytes.get(1).is_some_and(u8::is_ascii_digit) {
+        return None;
+    }
+
+    let end = bytes[1..].iter().position(|&b| !is_word_byte(b))? + 1;
+
+    (bytes[end] == b'$').then_some(end + 1)
+}
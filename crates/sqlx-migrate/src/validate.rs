@@ -0,0 +1,168 @@
+//! Detection of generated code that is out of date with its migrations directories.
+use crate::{migrate_ignore::MigrateIgnore, DatabaseType, MigrationsDirs};
+use std::{
+    collections::BTreeSet,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+// The tokens that precede the paths of migration files in the generated code.
+const PATH_MARKERS: [&str; 3] = ["include_str!(", "#[path =", ".with_source_path("];
+
+/// The differences between generated code and its migrations directories,
+/// see [`validate_generated`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StaleCode {
+    /// The files referenced by the generated code that do not exist.
+    pub missing_files: Vec<PathBuf>,
+    /// The migration files that are not part of the generated code.
+    pub ungenerated_files: Vec<PathBuf>,
+}
+
+impl StaleCode {
+    /// Whether the generated code is up to date.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.missing_files.is_empty() && self.ungenerated_files.is_empty()
+    }
+}
+
+impl fmt::Display for StaleCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "the generated code is up to date");
+        }
+
+        write!(
+            f,
+            "the generated code is stale ({} referenced files are missing, {} migration files are not generated), \
+            re-run the build script, e.g. by touching `build.rs` or the migrations directory",
+            self.missing_files.len(),
+            self.ungenerated_files.len()
+        )
+    }
+}
+
+/// Compare code generated by `generate` with the migrations directories it was
+/// generated from.
+///
+/// Generated code includes the migration files by their paths when the crate
+/// is compiled, so migrations that were removed, renamed or moved since the
+/// build script last ran surface as confusing compile or runtime errors.
+///
+/// Files ignored by the `.migrateignore` file of their directory are skipped.
+///
+/// # Errors
+///
+/// Returns an error if the generated file or the migrations directories
+/// cannot be read.
+pub fn validate_generated(
+    migrations_dirs: impl MigrationsDirs,
+    generated_rs: impl AsRef<Path>,
+) -> io::Result<StaleCode> {
+    let generated_rs = generated_rs.as_ref();
+    let code = fs::read_to_string(generated_rs)?;
+
+    // Relative paths are resolved the same way as `include_str!` does.
+    let base_dir = generated_rs.parent().unwrap_or(Path::new(""));
+
+    let referenced = referenced_paths(&code)
+        .into_iter()
+        .map(|path| base_dir.join(path))
+        .collect::<BTreeSet<_>>();
+
+    let generated_stems = referenced
+        .iter()
+        .filter_map(|path| migration_stem(&path.file_name()?.to_string_lossy()))
+        .collect::<BTreeSet<_>>();
+
+    let mut stale = StaleCode {
+        missing_files: referenced
+            .iter()
+            .filter(|path| !path.exists())
+            .cloned()
+            .collect(),
+        ungenerated_files: Vec::new(),
+    };
+
+    for migrations_path in migrations_dirs.migrations_dirs() {
+        let ignore = MigrateIgnore::load(&migrations_path).map_err(io::Error::other)?;
+
+        let mut entries = fs::read_dir(&migrations_path)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(fs::DirEntry::file_name);
+
+        for entry in entries {
+            let path = entry.path();
+
+            if path.is_dir() || ignore.is_ignored(&path) {
+                continue;
+            }
+
+            let Some(stem) = migration_stem(&entry.file_name().to_string_lossy()) else {
+                continue;
+            };
+
+            if !generated_stems.contains(&stem) {
+                stale.ungenerated_files.push(path);
+            }
+        }
+    }
+
+    Ok(stale)
+}
+
+// The string literals that follow the path markers in the generated code.
+fn referenced_paths(code: &str) -> Vec<String> {
+    let mut paths = Vec::new();
+
+    for marker in PATH_MARKERS {
+        for (idx, _) in code.match_indices(marker) {
+            if let Some(path) = string_literal(&code[idx + marker.len()..]) {
+                paths.push(path);
+            }
+        }
+    }
+
+    paths
+}
+
+// Parse the string literal at the start of the code, after any whitespace.
+fn string_literal(code: &str) -> Option<String> {
+    let mut chars = code.trim_start().strip_prefix('"')?.chars();
+    let mut literal = String::new();
+
+    loop {
+        match chars.next()? {
+            '"' => return Some(literal),
+            '\\' => literal.push(chars.next()?),
+            c => literal.push(c),
+        }
+    }
+}
+
+// The date and name of a migration file, e.g. `20211215162220_plush_sharks`
+// for `20211215162220_plush_sharks.migrate.pg.sql`.
+fn migration_stem(file_name: &str) -> Option<String> {
+    let file_name_lower = file_name.to_ascii_lowercase();
+
+    let (stem, extension) = file_name_lower.rsplit_once('.')?;
+
+    if !["rs", "sql", "jinja"].contains(&extension) {
+        return None;
+    }
+
+    // SQL can be specific to a database, e.g. `name.migrate.pg.sql`.
+    let stem = match stem.rsplit_once('.') {
+        Some((rest, tag)) if extension != "rs" && DatabaseType::from_dialect_tag(tag).is_some() => {
+            rest
+        }
+        _ => stem,
+    };
+
+    let stem = stem
+        .strip_suffix(".migrate")
+        .or_else(|| stem.strip_suffix(".revert"))?;
+
+    stem.contains('_')
+        .then(|| file_name[..stem.len()].to_string())
+}
@@ -0,0 +1,47 @@
+//! Tests of [`AnyConnection`] with SQLite, and with PostgreSQL if `DATABASE_URL` is set.
+#![cfg(all(feature = "any", feature = "sqlite"))]
+
+mod common;
+
+use sqlx::{AnyConnection, Connection};
+
+#[tokio::test]
+async fn bookkeeping_sqlite() {
+    sqlx::any::install_default_drivers();
+
+    let mut conn = AnyConnection::connect(&common::sqlite_url("any"))
+        .await
+        .unwrap();
+
+    common::bookkeeping(&mut conn, "_sqlx_migrations", "_sqlx_deferred").await;
+}
+
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn bookkeeping_postgres() {
+    use sqlx::Executor;
+
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+
+    sqlx::any::install_default_drivers();
+
+    let mut conn = AnyConnection::connect(&url).await.unwrap();
+
+    let tables = ["_sqlx_migrate_test_any", "_sqlx_migrate_test_any_deferred"];
+
+    for table in tables {
+        conn.execute(&*format!("DROP TABLE IF EXISTS {table}"))
+            .await
+            .unwrap();
+    }
+
+    common::bookkeeping(&mut conn, tables[0], tables[1]).await;
+
+    for table in tables {
+        conn.execute(&*format!("DROP TABLE IF EXISTS {table}"))
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,277 @@
+//! Checks shared by the tests of every database.
+// Not every test uses every check.
+#![allow(dead_code)]
+
+use std::{borrow::Cow, time::Duration};
+
+use sqlx::{Connection, Database, Executor};
+use sqlx_migrate::{
+    db::{AppliedMigration, DeferredState, Migrations},
+    Error, Migration, Migrator,
+};
+use tokio::sync::{Mutex, MutexGuard};
+
+/// The URL of the PostgreSQL database in `DATABASE_URL`,
+/// the tests are skipped if it is not set.
+pub fn postgres_url() -> Option<String> {
+    let url = std::env::var("DATABASE_URL").ok();
+
+    if url.is_none() {
+        eprintln!("DATABASE_URL is not set, skipping");
+    }
+
+    url
+}
+
+/// The URL of a new empty SQLite database with the given name in a temporary file.
+pub fn sqlite_url(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("sqlx-migrate-{name}-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    format!("sqlite://{}?mode=rwc", path.display())
+}
+
+/// Run tests that share the migration lock of a database one at a time,
+/// the PostgreSQL lock is held for the whole database.
+pub async fn serial() -> MutexGuard<'static, ()> {
+    static SERIAL: Mutex<()> = Mutex::const_new(());
+    SERIAL.lock().await
+}
+
+fn applied(version: u64, name: &'static str) -> AppliedMigration<'static> {
+    AppliedMigration {
+        version,
+        name: Cow::Borrowed(name),
+        checksum: Cow::Owned(vec![version as u8; 32]),
+        execution_time: Duration::from_millis(version),
+        phase: None,
+        skipped: false,
+        applied_on: None,
+        rows_affected: Some(version),
+    }
+}
+
+/// Exercise the bookkeeping of the migrations and the deferred migrations tables.
+pub async fn bookkeeping<C: Migrations>(conn: &mut C, table: &str, deferred_table: &str) {
+    // Creating the table again must not fail.
+    conn.ensure_migrations_table(table).await.unwrap();
+    conn.ensure_migrations_table(table).await.unwrap();
+
+    for (version, name) in [(1, "first"), (2, "second"), (3, "third")] {
+        conn.add_migration(table, applied(version, name))
+            .await
+            .unwrap();
+    }
+
+    conn.rename_migration(table, 2, "renamed").await.unwrap();
+    conn.remove_migration(table, 3).await.unwrap();
+
+    let migrations = conn.list_migrations(table).await.unwrap();
+    assert_eq!(migrations.len(), 2);
+    assert_eq!(migrations[0].version, 1);
+    assert_eq!(migrations[0].name, "first");
+    assert_eq!(migrations[0].checksum.as_ref(), [1; 32]);
+    assert_eq!(migrations[0].execution_time, Duration::from_millis(1));
+    assert_eq!(migrations[0].rows_affected, Some(1));
+    assert!(!migrations[0].skipped);
+    assert!(migrations[0].applied_on.is_some());
+    assert_eq!(migrations[1].version, 2);
+    assert_eq!(migrations[1].name, "renamed");
+
+    conn.clear_migrations(table).await.unwrap();
+    assert!(conn.list_migrations(table).await.unwrap().is_empty());
+
+    conn.ensure_deferred_table(deferred_table).await.unwrap();
+
+    // Scheduling the same migration again is a no-op.
+    conn.schedule_deferred(deferred_table, "backfill")
+        .await
+        .unwrap();
+    conn.schedule_deferred(deferred_table, "backfill")
+        .await
+        .unwrap();
+
+    assert!(conn
+        .claim_deferred(deferred_table, "backfill")
+        .await
+        .unwrap());
+    assert!(!conn
+        .claim_deferred(deferred_table, "backfill")
+        .await
+        .unwrap());
+
+    conn.finish_deferred(
+        deferred_table,
+        "backfill",
+        Duration::from_millis(5),
+        Some("boom"),
+    )
+    .await
+    .unwrap();
+
+    let deferred = conn.list_deferred(deferred_table).await.unwrap();
+    assert_eq!(deferred.len(), 1);
+    assert_eq!(deferred[0].name, "backfill");
+    assert_eq!(deferred[0].state, DeferredState::Failed);
+    assert_eq!(deferred[0].error.as_deref(), Some("boom"));
+    assert_eq!(deferred[0].execution_time, Some(Duration::from_millis(5)));
+
+    // Failed migrations can be claimed again.
+    assert!(conn
+        .claim_deferred(deferred_table, "backfill")
+        .await
+        .unwrap());
+
+    conn.finish_deferred(deferred_table, "backfill", Duration::from_millis(1), None)
+        .await
+        .unwrap();
+
+    let deferred = conn.list_deferred(deferred_table).await.unwrap();
+    assert_eq!(deferred[0].state, DeferredState::Completed);
+    assert_eq!(deferred[0].error, None);
+}
+
+/// Migrate, revert and force the given migrations with a fresh migrator for every step.
+///
+/// The migrations must be reversible, and reverting them
+/// must succeed even if they were not applied, at least two are required.
+pub async fn lifecycle<Db>(url: &str, table: &str, migrations: fn() -> Vec<Migration<Db>>)
+where
+    Db: Database,
+    Db::Connection: Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    let migrator = || async {
+        let mut migrator = Migrator::<Db>::connect(url).await.unwrap();
+        migrator.set_migrations_table(table);
+        migrator.add_migrations(migrations());
+        migrator
+    };
+
+    let count = migrations().len() as u64;
+
+    let summary = migrator().await.migrate_all().await.unwrap();
+    assert_eq!(summary.old_version, None);
+    assert_eq!(summary.new_version, Some(count));
+
+    migrator().await.verify().await.unwrap();
+
+    let status = migrator().await.status().await.unwrap();
+    assert_eq!(status.len() as u64, count);
+    assert!(status
+        .iter()
+        .all(|s| s.applied.is_some() && s.checksum_ok && s.name_ok));
+
+    let summary = migrator().await.revert(2).await.unwrap();
+    assert_eq!(summary.new_version, Some(1));
+
+    let status = migrator().await.status().await.unwrap();
+    assert!(status[0].applied.is_some());
+    assert!(status[1..].iter().all(|s| s.applied.is_none()));
+
+    // Forcing the version clears the migrations table.
+    migrator().await.force_version(count).await.unwrap();
+    migrator().await.verify().await.unwrap();
+
+    let status = migrator().await.status().await.unwrap();
+    assert!(status.iter().all(|s| s.applied.is_some()));
+
+    let summary = migrator().await.revert_all().await.unwrap();
+    assert_eq!(summary.new_version, None);
+
+    let status = migrator().await.status().await.unwrap();
+    assert!(status.iter().all(|s| s.applied.is_none()));
+}
+
+/// Apply the given migrations, then tamper with the migrations table
+/// and check that the changes are reported.
+///
+/// The migrations table is cleared in the end, the changes of
+/// the migrations are not reverted.
+pub async fn verification<Db>(url: &str, table: &str, migrations: fn() -> Vec<Migration<Db>>)
+where
+    Db: Database,
+    Db::Connection: Migrations,
+    for<'a> &'a mut Db::Connection: Executor<'a>,
+{
+    let migrator = || async {
+        let mut migrator = Migrator::<Db>::connect(url).await.unwrap();
+        migrator.set_migrations_table(table);
+        migrator.add_migrations(migrations());
+        migrator
+    };
+
+    let count = migrations().len() as u64;
+
+    migrator().await.migrate_all().await.unwrap();
+    migrator().await.verify().await.unwrap();
+
+    let mut conn = Db::Connection::connect(url).await.unwrap();
+
+    let db_migrations = conn.list_migrations(table).await.unwrap();
+    let mut tampered = db_migrations[0].clone();
+    tampered.checksum = Cow::Owned(vec![0; 32]);
+
+    conn.remove_migration(table, 1).await.unwrap();
+    conn.add_migration(table, tampered).await.unwrap();
+
+    match migrator().await.verify().await {
+        Err(Error::ChecksumMismatch {
+            version,
+            db_checksum,
+            ..
+        }) => {
+            assert_eq!(version, 1);
+            assert_eq!(db_checksum.as_ref(), [0; 32]);
+        }
+        res => panic!("expected a checksum mismatch, got {res:?}"),
+    }
+
+    let status = migrator().await.status().await.unwrap();
+    assert!(!status[0].checksum_ok);
+    assert!(status[1..].iter().all(|s| s.checksum_ok));
+
+    conn.remove_migration(table, 1).await.unwrap();
+    conn.add_migration(table, db_migrations[0].clone())
+        .await
+        .unwrap();
+    conn.add_migration(table, applied(count + 1, "unknown"))
+        .await
+        .unwrap();
+
+    match migrator().await.verify().await {
+        Err(Error::MissingMigrations {
+            local_count,
+            db_count,
+        }) => {
+            assert_eq!(local_count as u64, count);
+            assert_eq!(db_count as u64, count + 1);
+        }
+        res => panic!("expected missing migrations, got {res:?}"),
+    }
+
+    let status = migrator().await.status().await.unwrap();
+    assert!(status.last().unwrap().missing_local);
+
+    conn.clear_migrations(table).await.unwrap();
+}
+
+/// Check that versions are unique in a migrations table
+/// that was created without a primary key.
+pub async fn unique_versions<C: Migrations>(conn: &mut C, table: &str) {
+    conn.ensure_migrations_table(table).await.unwrap();
+    conn.add_migration(table, applied(1, "first"))
+        .await
+        .unwrap();
+
+    let err = conn
+        .add_migration(table, applied(1, "duplicate"))
+        .await
+        .unwrap_err();
+    assert!(
+        err.as_database_error()
+            .is_some_and(|err| err.is_unique_violation()),
+        "expected a unique violation, got {err:?}"
+    );
+
+    assert_eq!(conn.list_migrations(table).await.unwrap().len(), 1);
+}
@@ -0,0 +1,865 @@
+//! Tests against the PostgreSQL database in `DATABASE_URL`, skipped if it is not set.
+//!
+//! The tests only touch tables prefixed with `_sqlx_migrate_test`.
+#![cfg(feature = "postgres")]
+
+mod common;
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use sqlx::{Connection, Executor, PgConnection, Postgres};
+use sqlx_migrate::{
+    db::Migrations, history::HistoryEntry, Credentials, LeaderOutcome, Migration, Migrator,
+    MigratorOptions,
+};
+
+async fn connect(url: &str) -> PgConnection {
+    PgConnection::connect(url).await.unwrap()
+}
+
+async fn drop_tables(conn: &mut PgConnection, tables: &[&str]) {
+    for table in tables {
+        conn.execute(&*format!("DROP TABLE IF EXISTS {table}"))
+            .await
+            .unwrap();
+    }
+}
+
+fn migrations() -> Vec<Migration<Postgres>> {
+    vec![
+        Migration::new("create_users", |ctx| {
+            Box::pin(async move {
+                ctx.tx()
+                    .execute("CREATE TABLE _sqlx_migrate_test_users (id BIGINT PRIMARY KEY)")
+                    .await?;
+                Ok(())
+            })
+        })
+        .reversible(|ctx| {
+            Box::pin(async move {
+                ctx.tx()
+                    .execute("DROP TABLE IF EXISTS _sqlx_migrate_test_users")
+                    .await?;
+                Ok(())
+            })
+        }),
+        Migration::new("create_posts", |ctx| {
+            Box::pin(async move {
+                ctx.tx()
+                    .execute("CREATE TABLE _sqlx_migrate_test_posts (id BIGINT PRIMARY KEY)")
+                    .await?;
+                Ok(())
+            })
+        })
+        .reversible(|ctx| {
+            Box::pin(async move {
+                ctx.tx()
+                    .execute("DROP TABLE IF EXISTS _sqlx_migrate_test_posts")
+                    .await?;
+                Ok(())
+            })
+        }),
+    ]
+}
+
+fn migrator(conn: PgConnection, table: &str) -> Migrator<Postgres> {
+    let mut migrator = Migrator::new(conn);
+    migrator.set_migrations_table(table);
+    migrator.add_migrations(migrations());
+    migrator
+}
+
+#[tokio::test]
+async fn bookkeeping() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let tables = [
+        "_sqlx_migrate_test_bookkeeping",
+        "_sqlx_migrate_test_deferred",
+    ];
+    drop_tables(&mut conn, &tables).await;
+
+    common::bookkeeping(&mut conn, tables[0], tables[1]).await;
+
+    drop_tables(&mut conn, &tables).await;
+}
+
+#[tokio::test]
+async fn lifecycle() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let tables = [
+        "_sqlx_migrate_test_lifecycle",
+        "_sqlx_migrate_test_users",
+        "_sqlx_migrate_test_posts",
+    ];
+    drop_tables(&mut conn, &tables).await;
+
+    common::lifecycle(&url, tables[0], migrations).await;
+
+    drop_tables(&mut conn, &tables).await;
+}
+
+#[tokio::test]
+async fn verification() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let tables = [
+        "_sqlx_migrate_test_verification",
+        "_sqlx_migrate_test_users",
+        "_sqlx_migrate_test_posts",
+    ];
+    drop_tables(&mut conn, &tables).await;
+
+    common::verification(&url, tables[0], migrations).await;
+
+    drop_tables(&mut conn, &tables).await;
+}
+
+#[tokio::test]
+async fn locking() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut first = connect(&url).await;
+    let mut second = connect(&url).await;
+
+    first.lock().await.unwrap();
+    assert!(first.lock_held().await.unwrap());
+    assert!(!second.lock_held().await.unwrap());
+    assert!(!second.try_lock().await.unwrap());
+
+    first.unlock().await.unwrap();
+    assert!(!first.lock_held().await.unwrap());
+    assert!(second.try_lock().await.unwrap());
+    assert!(second.lock_held().await.unwrap());
+    assert!(!first.try_lock().await.unwrap());
+
+    second.unlock().await.unwrap();
+
+    // The lock is released with the connection.
+    assert!(first.try_lock().await.unwrap());
+    first.close().await.unwrap();
+    assert!(second.try_lock().await.unwrap());
+    second.unlock().await.unwrap();
+}
+
+#[tokio::test]
+async fn migrate_if_leader() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let tables = [
+        "_sqlx_migrate_test_leader",
+        "_sqlx_migrate_test_users",
+        "_sqlx_migrate_test_posts",
+    ];
+    drop_tables(&mut conn, &tables).await;
+
+    let first = migrator(connect(&url).await, tables[0]);
+    let second = migrator(connect(&url).await, tables[0]);
+    let interval = Duration::from_millis(10);
+
+    let (first, second) = tokio::join!(
+        first.migrate_if_leader(interval),
+        second.migrate_if_leader(interval)
+    );
+
+    // Exactly one of the migrators applies the migrations.
+    match (first.unwrap(), second.unwrap()) {
+        (
+            LeaderOutcome::Migrated(summary),
+            LeaderOutcome::Followed { .. } | LeaderOutcome::UpToDate,
+        )
+        | (
+            LeaderOutcome::Followed { .. } | LeaderOutcome::UpToDate,
+            LeaderOutcome::Migrated(summary),
+        ) => {
+            assert_eq!(summary.new_version, Some(2));
+        }
+        outcomes => panic!("expected exactly one leader, got {outcomes:?}"),
+    }
+
+    assert_eq!(conn.list_migrations(tables[0]).await.unwrap().len(), 2);
+
+    drop_tables(&mut conn, &tables).await;
+}
+
+#[tokio::test]
+async fn migrate_waits_for_lock() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let tables = [
+        "_sqlx_migrate_test_wait",
+        "_sqlx_migrate_test_users",
+        "_sqlx_migrate_test_posts",
+    ];
+    drop_tables(&mut conn, &tables).await;
+
+    conn.lock().await.unwrap();
+
+    let migrator = migrator(connect(&url).await, tables[0]);
+    let delay = Duration::from_millis(300);
+    let start = Instant::now();
+
+    let (summary, ()) = tokio::join!(migrator.migrate_all(), async {
+        tokio::time::sleep(delay).await;
+        // Not even the migrations table is created without the lock.
+        assert!(conn.table_columns(tables[0]).await.unwrap().is_empty());
+        conn.unlock().await.unwrap();
+    });
+
+    assert_eq!(summary.unwrap().new_version, Some(2));
+    assert!(start.elapsed() >= delay);
+
+    drop_tables(&mut conn, &tables).await;
+}
+
+// Run the operation while the migration lock is held by the given connection
+// for a while, the operation must wait for the lock.
+async fn assert_waits_for_lock<T, E: std::fmt::Debug>(
+    conn: &mut PgConnection,
+    operation: impl std::future::Future<Output = Result<T, E>>,
+) -> T {
+    conn.lock().await.unwrap();
+
+    let delay = Duration::from_millis(300);
+    let start = Instant::now();
+
+    let (res, ()) = tokio::join!(operation, async {
+        tokio::time::sleep(delay).await;
+        conn.unlock().await.unwrap();
+    });
+
+    assert!(start.elapsed() >= delay);
+    res.unwrap()
+}
+
+#[tokio::test]
+async fn squash_waits_for_lock() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let tables = [
+        "_sqlx_migrate_test_squash",
+        "_sqlx_migrate_test_users",
+        "_sqlx_migrate_test_posts",
+    ];
+    drop_tables(&mut conn, &tables).await;
+
+    migrator(connect(&url).await, tables[0])
+        .migrate_all()
+        .await
+        .unwrap();
+
+    let summary = assert_waits_for_lock(
+        &mut conn,
+        migrator(connect(&url).await, tables[0]).squash(2, "baseline"),
+    )
+    .await;
+    assert_eq!(summary.squashed, 2);
+    assert_eq!(conn.list_migrations(tables[0]).await.unwrap().len(), 1);
+
+    drop_tables(&mut conn, &tables).await;
+}
+
+#[tokio::test]
+async fn prune_waits_for_lock() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let tables = [
+        "_sqlx_migrate_test_prune",
+        "_sqlx_migrate_test_users",
+        "_sqlx_migrate_test_posts",
+    ];
+    drop_tables(&mut conn, &tables).await;
+
+    migrator(connect(&url).await, tables[0])
+        .migrate_all()
+        .await
+        .unwrap();
+
+    let mut pruning = Migrator::new(connect(&url).await);
+    pruning.set_migrations_table(tables[0]);
+    pruning.add_migrations(migrations().into_iter().take(1));
+
+    let pruned = assert_waits_for_lock(&mut conn, pruning.prune()).await;
+    assert_eq!(pruned.len(), 1);
+    assert_eq!(conn.list_migrations(tables[0]).await.unwrap().len(), 1);
+
+    drop_tables(&mut conn, &tables).await;
+}
+
+#[tokio::test]
+async fn repair_waits_for_lock() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let tables = [
+        "_sqlx_migrate_test_repair",
+        "_sqlx_migrate_test_users",
+        "_sqlx_migrate_test_posts",
+    ];
+    drop_tables(&mut conn, &tables).await;
+
+    migrator(connect(&url).await, tables[0])
+        .migrate_all()
+        .await
+        .unwrap();
+
+    let mut repairing = Migrator::new(connect(&url).await);
+    repairing.set_migrations_table(tables[0]);
+    repairing.add_migrations([Migration::new("create_users_renamed", |ctx| {
+        Box::pin(async move {
+            ctx.tx()
+                .execute("CREATE TABLE _sqlx_migrate_test_users (id BIGINT PRIMARY KEY)")
+                .await?;
+            Ok(())
+        })
+    })
+    .alias("create_users")]);
+    repairing.add_migrations(migrations().into_iter().skip(1));
+
+    let summary = assert_waits_for_lock(&mut conn, repairing.repair()).await;
+    assert_eq!(
+        summary.renamed,
+        [(1, "create_users".into(), "create_users_renamed".into())]
+    );
+    assert_eq!(
+        conn.list_migrations(tables[0]).await.unwrap()[0].name,
+        "create_users_renamed"
+    );
+
+    drop_tables(&mut conn, &tables).await;
+}
+
+#[tokio::test]
+async fn import_history_waits_for_lock() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let tables = [
+        "_sqlx_migrate_test_export",
+        "_sqlx_migrate_test_import",
+        "_sqlx_migrate_test_users",
+        "_sqlx_migrate_test_posts",
+    ];
+    drop_tables(&mut conn, &tables).await;
+
+    migrator(connect(&url).await, tables[0])
+        .migrate_all()
+        .await
+        .unwrap();
+
+    let history = migrator(connect(&url).await, tables[0])
+        .export_history()
+        .await
+        .unwrap();
+
+    assert_waits_for_lock(
+        &mut conn,
+        migrator(connect(&url).await, tables[1]).import_history(&history, false),
+    )
+    .await;
+    let imported = conn.list_migrations(tables[1]).await.unwrap();
+    assert_eq!(
+        imported
+            .into_iter()
+            .map(HistoryEntry::from)
+            .collect::<Vec<_>>(),
+        history.migrations
+    );
+
+    drop_tables(&mut conn, &tables).await;
+}
+
+#[tokio::test]
+async fn concurrent_migrators() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let tables = [
+        "_sqlx_migrate_test_concurrent",
+        "_sqlx_migrate_test_users",
+        "_sqlx_migrate_test_posts",
+    ];
+    drop_tables(&mut conn, &tables).await;
+
+    let first = migrator(connect(&url).await, tables[0]);
+    let second = migrator(connect(&url).await, tables[0]);
+
+    let (first, second) = tokio::join!(first.migrate_all(), second.migrate_all());
+    let (first, second) = (first.unwrap(), second.unwrap());
+
+    // One migrator applies the migrations, the other one waits
+    // for it and finds them applied.
+    let (applied, waited) = if first.old_version.is_none() {
+        (first, second)
+    } else {
+        (second, first)
+    };
+    assert_eq!(applied.old_version, None);
+    assert_eq!(applied.new_version, Some(2));
+    assert_eq!(waited.old_version, Some(2));
+    assert_eq!(waited.new_version, Some(2));
+
+    assert_eq!(conn.list_migrations(tables[0]).await.unwrap().len(), 2);
+
+    drop_tables(&mut conn, &tables).await;
+}
+
+#[tokio::test]
+async fn unique_versions() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let tables = ["_sqlx_migrate_test_unique"];
+    drop_tables(&mut conn, &tables).await;
+
+    conn.execute(&*format!(
+        r#"
+        CREATE TABLE {} (
+            version BIGINT NOT NULL,
+            name TEXT NOT NULL,
+            applied_on TIMESTAMPTZ NOT NULL DEFAULT now(),
+            checksum BYTEA NOT NULL,
+            execution_time BIGINT NOT NULL
+        )
+        "#,
+        tables[0]
+    ))
+    .await
+    .unwrap();
+
+    common::unique_versions(&mut conn, tables[0]).await;
+
+    drop_tables(&mut conn, &tables).await;
+}
+
+#[tokio::test]
+async fn extensions() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let tables = [
+        "_sqlx_migrate_test_extensions",
+        "_sqlx_migrate_test_users",
+        "_sqlx_migrate_test_posts",
+    ];
+    drop_tables(&mut conn, &tables).await;
+
+    // Missing extensions fail before any migrations are applied.
+    let mut failing = migrator(connect(&url).await, tables[0]);
+    failing.ensure_extensions(["_sqlx_migrate_test_missing"]);
+    assert!(failing.migrate_all().await.is_err());
+    assert!(conn.list_migrations(tables[0]).await.unwrap().is_empty());
+
+    // Existing extensions are not created again.
+    let mut migrator = migrator(connect(&url).await, tables[0]);
+    migrator.ensure_extensions(["plpgsql"]);
+    let summary = migrator.migrate_all().await.unwrap();
+    assert_eq!(summary.new_version, Some(2));
+
+    drop_tables(&mut conn, &tables).await;
+}
+
+#[tokio::test]
+async fn create_schemas() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let schemas = [
+        "_sqlx_migrate_test_bootstrap",
+        "_sqlx_migrate_test_search_path",
+    ];
+
+    async fn drop_schemas(conn: &mut PgConnection, schemas: &[&str]) {
+        for schema in schemas {
+            conn.execute(&*format!("DROP SCHEMA IF EXISTS {schema} CASCADE"))
+                .await
+                .unwrap();
+        }
+    }
+
+    drop_schemas(&mut conn, &schemas).await;
+
+    let mut migrator_conn = connect(&url).await;
+    migrator_conn
+        .execute("SET search_path TO _sqlx_migrate_test_search_path, \"$user\", public")
+        .await
+        .unwrap();
+
+    let mut migrator = migrator(
+        migrator_conn,
+        "_sqlx_migrate_test_bootstrap._sqlx_migrate_test_migrations",
+    );
+    migrator.set_options(MigratorOptions {
+        create_schemas: true,
+        ..Default::default()
+    });
+    let summary = migrator.migrate_all().await.unwrap();
+    assert_eq!(summary.new_version, Some(2));
+
+    let created: Vec<String> = sqlx::query_scalar(
+        "SELECT nspname::TEXT FROM pg_namespace WHERE nspname LIKE '_sqlx_migrate_test_%' ORDER BY nspname",
+    )
+    .fetch_all(&mut conn)
+    .await
+    .unwrap();
+    assert_eq!(created, schemas);
+
+    // The unqualified tables of the migrations are created in the first schema of the search path.
+    assert_eq!(
+        conn.table_columns("_sqlx_migrate_test_search_path._sqlx_migrate_test_users")
+            .await
+            .unwrap(),
+        ["id"]
+    );
+
+    drop_schemas(&mut conn, &schemas).await;
+}
+
+#[tokio::test]
+async fn preflight() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let table = "_sqlx_migrate_test_preflight";
+    drop_tables(&mut conn, &[table]).await;
+
+    // The test user owns the database, only the queries are checked.
+    let missing = conn
+        .missing_privileges("_sqlx_migrate_test_no_schema._sqlx_migrations")
+        .await
+        .unwrap();
+    assert!(missing.is_empty());
+
+    let mut migrator = migrator(conn, table);
+    assert!(migrator.preflight().await.unwrap().is_empty());
+    migrator.migrate(1).await.unwrap();
+
+    // The privileges on the existing table are checked as well.
+    let mut conn = connect(&url).await;
+    assert!(conn.missing_privileges(table).await.unwrap().is_empty());
+
+    drop_tables(&mut conn, &[table, "_sqlx_migrate_test_users"]).await;
+}
+
+#[tokio::test]
+async fn explain() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let table = "_sqlx_migrate_test_explain";
+    let tables = [
+        table,
+        "_sqlx_migrate_test_users",
+        "_sqlx_migrate_test_posts",
+    ];
+    drop_tables(&mut conn, &tables).await;
+
+    migrator(conn, table).migrate(1).await.unwrap();
+
+    let mut migrator = migrator(connect(&url).await, table);
+    migrator.add_migrations([Migration::new("backfill", |ctx| {
+        Box::pin(async move {
+            ctx.tx()
+                .execute(
+                    "UPDATE _sqlx_migrate_test_users SET id = id + 1;
+                     DELETE FROM _sqlx_migrate_test_posts;
+                     CREATE TABLE _sqlx_migrate_test_tmp (id BIGINT);
+                     INSERT INTO _sqlx_migrate_test_tmp VALUES (1);",
+                )
+                .await?;
+            Ok(())
+        })
+    })]);
+    migrator.set_options(MigratorOptions {
+        explain: true,
+        ..Default::default()
+    });
+
+    let results = migrator.lock_impact().await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].version, 3);
+
+    // Tables created by the migration are skipped, tables created
+    // by earlier pending migrations cannot be explained.
+    let plans = &results[0].plans;
+    assert_eq!(plans.len(), 2);
+    assert!(plans[0]
+        .plan
+        .as_deref()
+        .is_some_and(|plan| plan.contains("Update on _sqlx_migrate_test_users")));
+    assert!(plans[1].plan.is_none());
+    assert!(plans[1].error.is_some());
+
+    let mut conn = connect(&url).await;
+    drop_tables(&mut conn, &tables).await;
+}
+
+#[tokio::test]
+async fn rehearse() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let table = "_sqlx_migrate_test_rehearse";
+    let tables = [
+        table,
+        "_sqlx_migrate_test_users",
+        "_sqlx_migrate_test_posts",
+        "_sqlx_migrate_test_accounts",
+    ];
+    drop_tables(&mut conn, &tables).await;
+
+    migrator(connect(&url).await, table)
+        .migrate(1)
+        .await
+        .unwrap();
+
+    // The existing table does not conflict with the rehearsal schema.
+    let rehearsal = migrator(connect(&url).await, table)
+        .rehearse()
+        .await
+        .unwrap();
+    assert!(rehearsal.error.is_none());
+    assert_eq!(rehearsal.applied.len(), 2);
+
+    let posts: Option<String> =
+        sqlx::query_scalar("SELECT to_regclass('_sqlx_migrate_test_posts')::TEXT")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+    assert_eq!(posts, None);
+
+    let schemas: i64 = sqlx::query_scalar(
+        "SELECT count(*) FROM pg_namespace WHERE nspname LIKE '_sqlx_migrate_rehearsal_%'",
+    )
+    .fetch_one(&mut conn)
+    .await
+    .unwrap();
+    assert_eq!(schemas, 0);
+
+    // Tables outside of the rehearsal schema are not visible to migrations.
+    conn.execute("CREATE TABLE _sqlx_migrate_test_accounts (id BIGINT)")
+        .await
+        .unwrap();
+
+    let mut altering = migrator(connect(&url).await, table);
+    altering.add_migrations([Migration::new("alter_accounts", |ctx| {
+        Box::pin(async move {
+            ctx.tx()
+                .execute("ALTER TABLE _sqlx_migrate_test_accounts ADD COLUMN x INT")
+                .await?;
+            Ok(())
+        })
+    })]);
+    let rehearsal = altering.rehearse().await.unwrap();
+    assert_eq!(rehearsal.applied.len(), 2);
+    assert!(rehearsal.error.is_some());
+
+    drop_tables(&mut conn, &tables).await;
+}
+
+#[tokio::test]
+async fn credentials() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+
+    let minted = Arc::new(AtomicUsize::new(0));
+
+    let counter = minted.clone();
+    let options = MigratorOptions {
+        credentials: Some(Credentials::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Ok(String::from("token")) }
+        })),
+        ..Default::default()
+    };
+    for _ in 0..2 {
+        let _migrator = Migrator::<Postgres>::connect_with_options(&url, options.clone())
+            .await
+            .unwrap();
+    }
+    assert_eq!(minted.load(Ordering::SeqCst), 2);
+
+    let options = MigratorOptions {
+        credentials: Some(Credentials::new(|| async { Err("no token".into()) })),
+        ..Default::default()
+    };
+    let err = Migrator::<Postgres>::connect_with_options(&url, options)
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(err, sqlx::Error::Configuration(err) if err.to_string() == "no token"));
+}
+
+#[tokio::test]
+async fn reconnect() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let tables = [
+        "_sqlx_migrate_test_reconnect",
+        "_sqlx_migrate_test_users",
+        "_sqlx_migrate_test_posts",
+    ];
+
+    // The connection is lost once while the last migration is applied.
+    let migrator = |reconnect_attempts: u32| {
+        let url = url.clone();
+        let terminated = Arc::new(AtomicUsize::new(0));
+        async move {
+            let options = MigratorOptions {
+                reconnect_attempts,
+                ..Default::default()
+            };
+            let mut migrator = Migrator::<Postgres>::connect_with_options(&url, options)
+                .await
+                .unwrap();
+            migrator.set_migrations_table(tables[0]);
+            migrator.add_migrations(migrations());
+            migrator.add_migrations([Migration::new("lose_connection", move |ctx| {
+                let terminated = terminated.clone();
+                Box::pin(async move {
+                    // Nothing is returned while the checksum is calculated.
+                    let executed = ctx.tx().fetch_optional("SELECT 1").await?.is_some();
+
+                    if executed && terminated.fetch_add(1, Ordering::SeqCst) == 0 {
+                        let _ = ctx
+                            .tx()
+                            .execute("SELECT pg_terminate_backend(pg_backend_pid())")
+                            .await;
+                        ctx.tx().execute("SELECT 1").await?;
+                    }
+                    Ok(())
+                })
+            })]);
+            migrator
+        }
+    };
+
+    drop_tables(&mut conn, &tables).await;
+    let error = migrator(0).await.migrate_all().await.unwrap_err();
+    assert!(error.is_connection_error(), "{error}");
+    assert!(conn.list_migrations(tables[0]).await.unwrap().is_empty());
+
+    drop_tables(&mut conn, &tables).await;
+    let summary = migrator(1).await.migrate_all().await.unwrap();
+    assert_eq!(summary.new_version, Some(3));
+    assert_eq!(conn.list_migrations(tables[0]).await.unwrap().len(), 3);
+
+    drop_tables(&mut conn, &tables).await;
+}
+
+#[tokio::test]
+async fn dump_schema_bookkeeping() {
+    let Some(url) = common::postgres_url() else {
+        return;
+    };
+    let _serial = common::serial().await;
+    let mut conn = connect(&url).await;
+
+    let table = "_sqlx_migrate_test_dump";
+    let runs_table = "_sqlx_migrate_test_dump_runs";
+    let releases_table = "_sqlx_migrate_test_dump_releases";
+    drop_tables(
+        &mut conn,
+        &[
+            table,
+            runs_table,
+            releases_table,
+            "_sqlx_migrate_test_users",
+            "_sqlx_migrate_test_posts",
+        ],
+    )
+    .await;
+
+    let mut recording = migrator(connect(&url).await, table);
+    recording.set_options(MigratorOptions {
+        record_runs: true,
+        ..Default::default()
+    });
+    recording.migrate_all().await.unwrap();
+
+    let release = migrator(connect(&url).await, table)
+        .tag_release("v1")
+        .await
+        .unwrap();
+    assert_eq!((release.name.as_str(), release.version), ("v1", 2));
+    assert!(release.tagged_on.is_some());
+
+    // The `SERIAL` sequence of the runs table is excluded along with the table.
+    let schema = conn
+        .dump_schema(&[table.into(), runs_table.into(), releases_table.into()])
+        .await
+        .unwrap();
+    assert!(schema
+        .iter()
+        .any(|sql| sql.contains("_sqlx_migrate_test_users")));
+    assert!(!schema.iter().any(|sql| sql.contains(table)), "{schema:#?}");
+}
@@ -0,0 +1,64 @@
+//! Tests of migrations registered with `#[register_migration]`.
+#![cfg(all(feature = "register", feature = "sqlite"))]
+
+use sqlx::{Executor, Sqlite};
+use sqlx_migrate::{register::register_migration, Migration};
+
+fn create_table(name: &'static str) -> Migration<Sqlite> {
+    Migration::new(name, move |ctx| {
+        Box::pin(async move {
+            ctx.tx()
+                .execute(&*format!("CREATE TABLE {name} (id INTEGER PRIMARY KEY)"))
+                .await?;
+            Ok(())
+        })
+    })
+}
+
+// Registered out of order, the keys determine the order.
+#[register_migration(key = "20240102000000_create_posts")]
+fn create_posts() -> Migration<Sqlite> {
+    create_table("posts")
+}
+
+#[register_migration(key = "20240101000000_create_users")]
+fn create_users() -> Migration<Sqlite> {
+    create_table("users")
+}
+
+#[test]
+fn ordered_by_key() {
+    let migrations = sqlx_migrate::register::registered_migrations::<Sqlite>();
+
+    assert_eq!(
+        migrations.iter().map(Migration::name).collect::<Vec<_>>(),
+        ["users", "posts"]
+    );
+}
+
+// Migrations of the other databases are collected separately.
+#[cfg(feature = "any")]
+mod any {
+    use sqlx::Any;
+    use sqlx_migrate::{register::register_migration, Migration};
+
+    fn noop(name: &'static str) -> Migration<Any> {
+        Migration::new(name, |_| Box::pin(async { Ok(()) }))
+    }
+
+    #[register_migration(key = "duplicate")]
+    fn first() -> Migration<Any> {
+        noop("first")
+    }
+
+    #[register_migration(key = "duplicate")]
+    fn second() -> Migration<Any> {
+        noop("second")
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate registered migration key: duplicate")]
+    fn duplicate_keys() {
+        let _ = sqlx_migrate::register::registered_migrations::<Any>();
+    }
+}
@@ -0,0 +1,1325 @@
+//! Tests against SQLite databases in temporary files.
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use sqlx::{Connection, Executor, Sqlite, SqliteConnection, Statement};
+use sqlx_migrate::{
+    db::Migrations, history::HistoryEntry, Error, HistoryDiff, Migration, MigrationStatus,
+    Migrator, MigratorOptions, Params, Progress, ProgressHandler, SchemaDrift, DESTRUCTIVE_TAG,
+};
+
+fn migrations() -> Vec<Migration<Sqlite>> {
+    vec![
+        Migration::new("create_users", |ctx| {
+            Box::pin(async move {
+                ctx.tx()
+                    .execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)")
+                    .await?;
+                Ok(())
+            })
+        })
+        .reversible(|ctx| {
+            Box::pin(async move {
+                ctx.tx().execute("DROP TABLE IF EXISTS users").await?;
+                Ok(())
+            })
+        }),
+        Migration::new("create_posts", |ctx| {
+            Box::pin(async move {
+                ctx.tx()
+                    .execute("CREATE TABLE posts (id INTEGER PRIMARY KEY, user_id INTEGER)")
+                    .await?;
+                Ok(())
+            })
+        })
+        .reversible(|ctx| {
+            Box::pin(async move {
+                ctx.tx().execute("DROP TABLE IF EXISTS posts").await?;
+                Ok(())
+            })
+        }),
+    ]
+}
+
+#[tokio::test]
+async fn bookkeeping() {
+    let mut conn = SqliteConnection::connect(&common::sqlite_url("bookkeeping"))
+        .await
+        .unwrap();
+
+    common::bookkeeping(&mut conn, "_sqlx_migrations", "_sqlx_deferred").await;
+}
+
+#[tokio::test]
+async fn lifecycle() {
+    common::lifecycle(
+        &common::sqlite_url("lifecycle"),
+        "_sqlx_migrations",
+        migrations,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn verification() {
+    common::verification(
+        &common::sqlite_url("verification"),
+        "_sqlx_migrations",
+        migrations,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn concurrent_migrators() {
+    let url = common::sqlite_url("concurrent");
+
+    let migrator = || async {
+        let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+        migrator.set_migrations_table("_sqlx_migrations");
+        migrator.add_migrations(migrations());
+        migrator
+    };
+
+    let (first, second) = (migrator().await, migrator().await);
+    let (first, second) = tokio::join!(first.migrate_all(), second.migrate_all());
+
+    // Without a migration lock one of the migrators may fail,
+    // but the migrations are applied exactly once.
+    let summaries = [first, second]
+        .into_iter()
+        .filter_map(Result::ok)
+        .collect::<Vec<_>>();
+    assert!(!summaries.is_empty());
+    assert_eq!(
+        summaries
+            .iter()
+            .filter(|summary| summary.old_version.is_none())
+            .count(),
+        1
+    );
+
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+    assert_eq!(
+        conn.list_migrations("_sqlx_migrations")
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn unique_versions() {
+    let mut conn = SqliteConnection::connect(&common::sqlite_url("unique"))
+        .await
+        .unwrap();
+
+    conn.execute(
+        r#"
+        CREATE TABLE _sqlx_migrations (
+            version BIGINT NOT NULL,
+            name TEXT NOT NULL,
+            applied_on INTEGER NOT NULL,
+            checksum BLOB NOT NULL,
+            execution_time BIGINT NOT NULL
+        )
+        "#,
+    )
+    .await
+    .unwrap();
+
+    common::unique_versions(&mut conn, "_sqlx_migrations").await;
+}
+
+#[tokio::test]
+async fn concurrent_version() {
+    let url = common::sqlite_url("concurrent-version");
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.set_migrations_table("_sqlx_migrations");
+    // Another migrator records the version while the migration is applied.
+    migrator.add_migrations([Migration::new("create_users", |ctx| {
+        Box::pin(async move {
+            ctx.tx()
+                .execute(
+                    r#"
+                    INSERT INTO _sqlx_migrations ( version, name, applied_on, checksum, execution_time )
+                    VALUES ( 1, 'create_users', 0, x'00', 0 )
+                    "#,
+                )
+                .await?;
+            Ok(())
+        })
+    })]);
+
+    match migrator.migrate_all().await {
+        Err(Error::ConcurrentMigration { version }) => assert_eq!(version, 1),
+        res => panic!("expected a concurrent migration, got {res:?}"),
+    }
+
+    // The migration is rolled back.
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+    assert!(conn
+        .list_migrations("_sqlx_migrations")
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn store() {
+    let url = common::sqlite_url("store");
+
+    let migrator = || async {
+        let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+        migrator.set_migrations_table("_sqlx_migrations");
+        migrator.add_migrations(migrations());
+        migrator
+    };
+
+    migrator().await.migrate_all().await.unwrap();
+
+    let mut migrator = migrator().await;
+    let mut store = migrator.store().await.unwrap();
+    assert_eq!(store.table(), "_sqlx_migrations");
+
+    let applied = store.list().await.unwrap();
+    assert_eq!(applied.len(), 2);
+
+    match store.add(applied[1].clone()).await {
+        Err(Error::ConcurrentMigration { version }) => assert_eq!(version, 2),
+        res => panic!("expected a concurrent migration, got {res:?}"),
+    }
+
+    store.rename(2, "renamed").await.unwrap();
+    assert_eq!(store.list().await.unwrap()[1].name, "renamed");
+
+    store.remove(2).await.unwrap();
+    assert_eq!(store.list().await.unwrap().len(), 1);
+
+    store.clear().await.unwrap();
+    assert!(store.list().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn progress() {
+    let reported = Arc::new(Mutex::new(Vec::<Progress>::new()));
+
+    let mut migrator = Migrator::<Sqlite>::connect(&common::sqlite_url("progress"))
+        .await
+        .unwrap();
+    migrator.set_options(MigratorOptions {
+        progress: Some(ProgressHandler::new({
+            let reported = reported.clone();
+            move |progress| reported.lock().unwrap().push(progress.clone())
+        })),
+        ..Default::default()
+    });
+    migrator.add_migrations([Migration::new("backfill", |ctx| {
+        Box::pin(async move {
+            ctx.set_total_steps(2);
+            ctx.step("users");
+            ctx.step("posts");
+            Ok(())
+        })
+    })]);
+
+    migrator.migrate_all().await.unwrap();
+
+    // Nothing is reported while the checksum is calculated.
+    let reported = reported.lock().unwrap();
+    assert_eq!(reported.len(), 2);
+    assert_eq!(reported[0].name, "backfill");
+    assert_eq!(reported[0].step, 1);
+    assert_eq!(reported[0].message, "users");
+    assert_eq!(reported[1].step, 2);
+    assert_eq!(reported[1].total_steps, Some(2));
+    assert_eq!(reported[1].message, "posts");
+}
+
+#[tokio::test]
+async fn analyze_threshold() {
+    let url = common::sqlite_url("analyze");
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.set_options(MigratorOptions {
+        analyze_threshold: Some(2),
+        ..Default::default()
+    });
+    migrator.add_migrations(migrations());
+    migrator.add_migrations([Migration::new("backfill", |ctx| {
+        Box::pin(async move {
+            ctx.tx()
+                .execute(
+                    "CREATE INDEX users_name ON users (name);
+                     CREATE INDEX posts_user_id ON posts (user_id);",
+                )
+                .await?;
+            ctx.tx()
+                .execute("INSERT INTO users (name) VALUES ('a'), ('b'), ('c')")
+                .await?;
+            ctx.tx()
+                .execute("INSERT INTO posts (user_id) VALUES (1)")
+                .await?;
+            Ok(())
+        })
+    })]);
+
+    migrator.migrate_all().await.unwrap();
+
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+    let analyzed: Vec<String> = sqlx::query_scalar("SELECT DISTINCT tbl FROM sqlite_stat1")
+        .fetch_all(&mut conn)
+        .await
+        .unwrap();
+    assert_eq!(analyzed, ["users"]);
+}
+
+#[tokio::test]
+async fn record_runs() {
+    let url = common::sqlite_url("record_runs");
+
+    for _ in 0..2 {
+        let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+        migrator.set_options(MigratorOptions {
+            record_runs: true,
+            ..Default::default()
+        });
+        migrator.add_migrations(migrations());
+        migrator.migrate_all().await.unwrap();
+
+        let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+        migrator.add_migrations(migrations());
+        migrator.revert_all().await.unwrap();
+    }
+
+    let runs = Migrator::<Sqlite>::connect(&url)
+        .await
+        .unwrap()
+        .runs()
+        .await
+        .unwrap();
+
+    let names = migrations()
+        .into_iter()
+        .map(|mig| mig.name().to_string())
+        .collect::<Vec<_>>();
+
+    assert_eq!(runs.len(), names.len() * 2);
+
+    for (idx, run) in runs.iter().enumerate() {
+        let idx = idx % names.len();
+        assert_eq!(run.version, Some(idx as u64 + 1));
+        assert_eq!(run.name, names[idx]);
+        assert!(run.ran_on.is_some());
+    }
+}
+
+#[tokio::test]
+async fn rehearse() {
+    let url = common::sqlite_url("rehearse");
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.add_migrations(migrations());
+    migrator.add_migrations([Migration::new("broken", |ctx| {
+        Box::pin(async move {
+            ctx.tx()
+                .execute("ALTER TABLE missing ADD x INTEGER")
+                .await?;
+            Ok(())
+        })
+    })]);
+
+    let rehearsal = migrator.rehearse().await.unwrap();
+    assert_eq!(rehearsal.applied.len(), migrations().len());
+    assert!(matches!(
+        rehearsal.error,
+        Some(Error::Migration { version, .. }) if version == migrations().len() as u64 + 1
+    ));
+
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+    let tables: i64 = sqlx::query_scalar("SELECT count(*) FROM sqlite_master")
+        .fetch_one(&mut conn)
+        .await
+        .unwrap();
+    assert_eq!(tables, 0);
+}
+
+#[tokio::test]
+async fn rehearse_pending() {
+    let url = common::sqlite_url("rehearse_pending");
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.add_migrations(migrations());
+    migrator.migrate(1).await.unwrap();
+
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+    conn.execute("INSERT INTO users (name) VALUES ('a'), ('a')")
+        .await
+        .unwrap();
+
+    // The failure depends on the data in the database.
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.add_migrations(migrations());
+    migrator.add_migrations([Migration::new("unique_names", |ctx| {
+        Box::pin(async move {
+            ctx.tx()
+                .execute("CREATE UNIQUE INDEX users_name ON users (name)")
+                .await?;
+            Ok(())
+        })
+    })]);
+
+    let rehearsal = migrator.rehearse_pending(3).await.unwrap();
+    assert_eq!(
+        rehearsal
+            .applied
+            .iter()
+            .map(|mig| mig.version)
+            .collect::<Vec<_>>(),
+        [2]
+    );
+    assert!(matches!(
+        rehearsal.error,
+        Some(Error::Migration { version: 3, .. })
+    ));
+
+    let posts: i64 = sqlx::query_scalar("SELECT count(*) FROM sqlite_master WHERE name = 'posts'")
+        .fetch_one(&mut conn)
+        .await
+        .unwrap();
+    assert_eq!(posts, 0);
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.add_migrations(migrations());
+    let status = migrator.status().await.unwrap();
+    assert_eq!(status.iter().filter(|mig| mig.applied.is_some()).count(), 1);
+}
+
+#[tokio::test]
+async fn releases() {
+    let url = common::sqlite_url("releases");
+
+    let migrator = || async {
+        let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+        migrator.add_migrations(migrations());
+        migrator
+    };
+
+    migrator().await.migrate(1).await.unwrap();
+    migrator().await.tag_release("v1").await.unwrap();
+    migrator().await.migrate_all().await.unwrap();
+    migrator().await.tag_release("v2").await.unwrap();
+
+    let summary = migrator().await.revert_to_release("v1").await.unwrap();
+    assert_eq!(summary.new_version, Some(1));
+
+    // Releases are kept when their migrations are reverted.
+    let summary = migrator().await.migrate_to_release("v2").await.unwrap();
+    assert_eq!(summary.new_version, Some(2));
+
+    let summary = migrator().await.revert_to_release("v2").await.unwrap();
+    assert_eq!(summary.old_version, summary.new_version);
+
+    let releases = migrator().await.releases().await.unwrap();
+    assert_eq!(
+        releases
+            .iter()
+            .map(|release| (release.name.as_str(), release.version))
+            .collect::<Vec<_>>(),
+        [("v1", 1), ("v2", 2)]
+    );
+
+    assert!(matches!(
+        migrator().await.migrate_to_release("v3").await,
+        Err(Error::ReleaseNotFound { .. })
+    ));
+}
+
+#[tokio::test]
+async fn irreversible_boundary() {
+    let url = common::sqlite_url("irreversible_boundary");
+
+    let migrator = |cross_boundaries| {
+        let url = url.clone();
+        async move {
+            let mut migrations = migrations();
+            let first = migrations.remove(0).irreversible_boundary();
+            migrations.insert(0, first);
+
+            let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+            migrator.set_options(MigratorOptions {
+                cross_boundaries,
+                ..Default::default()
+            });
+            migrator.add_migrations(migrations);
+            migrator
+        }
+    };
+
+    migrator(false).await.migrate_all().await.unwrap();
+
+    // Reverting after the boundary does not cross it.
+    let summary = migrator(false).await.revert(2).await.unwrap();
+    assert_eq!(summary.new_version, Some(1));
+
+    assert!(matches!(
+        migrator(false).await.revert_all().await,
+        Err(Error::IrreversibleBoundary { migrations }) if migrations.len() == 1
+    ));
+
+    let summary = migrator(true).await.revert_all().await.unwrap();
+    assert_eq!(summary.new_version, None);
+}
+
+#[tokio::test]
+async fn is_behind() {
+    let url = common::sqlite_url("is_behind");
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.add_migrations(migrations());
+    assert!(migrator.is_behind(1).await.unwrap());
+    migrator.migrate(1).await.unwrap();
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.add_migrations(migrations());
+    assert!(migrator.is_behind(0).await.unwrap());
+    assert!(!migrator.is_behind(1).await.unwrap());
+}
+
+// The server runs on the same single-threaded runtime as the migrator,
+// so it only receives the notifications if they do not block the runtime.
+#[cfg(feature = "notify")]
+#[tokio::test]
+async fn webhook() {
+    use sqlx_migrate::notify::Webhook;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = tokio::spawn(async move {
+        let mut events = Vec::new();
+
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !String::from_utf8_lossy(&request).contains("\"event\"") {
+                let read = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..read]);
+            }
+
+            let request = String::from_utf8_lossy(&request);
+            events.push(request.contains("\"succeeded\""));
+
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                .await
+                .unwrap();
+        }
+
+        events
+    });
+
+    let url = common::sqlite_url("webhook");
+    let timeout = std::time::Duration::from_secs(2);
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.set_options(MigratorOptions {
+        webhook: Some(Webhook::new(format!("http://{addr}/")).with_timeout(timeout)),
+        ..Default::default()
+    });
+    migrator.add_migrations(migrations());
+
+    // A blocked runtime would only continue after the requests time out.
+    let started = std::time::Instant::now();
+    migrator.migrate_all().await.unwrap();
+    assert!(started.elapsed() < timeout);
+
+    assert_eq!(server.await.unwrap(), [false, true]);
+}
+
+#[tokio::test]
+async fn squash_rolls_back() {
+    let url = common::sqlite_url("squash_rolls_back");
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.add_migrations(migrations());
+    migrator.migrate_all().await.unwrap();
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.add_migrations(migrations());
+    let err = migrator
+        .squash_with(2, "baseline", |_| Err(Error::Cli("write failed".into())))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Cli(_)));
+
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+    assert_eq!(
+        conn.list_migrations("_sqlx_migrations")
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+    drop(conn);
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.add_migrations(migrations());
+    let summary = migrator.squash(2, "baseline").await.unwrap();
+    assert_eq!(summary.squashed, 2);
+
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+    let applied = conn.list_migrations("_sqlx_migrations").await.unwrap();
+    assert_eq!(applied.len(), 1);
+    assert_eq!(applied[0].name, "baseline");
+}
+
+#[tokio::test]
+async fn drift() {
+    let url = common::sqlite_url("drift");
+
+    let migrator = || async {
+        let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+        migrator.add_migrations(migrations());
+        migrator
+    };
+    let scratch = || async { SqliteConnection::connect("sqlite::memory:").await.unwrap() };
+
+    migrator().await.migrate_all().await.unwrap();
+    assert!(migrator()
+        .await
+        .drift(scratch().await)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // An object altered outside of migrations is missing and unexpected.
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+    conn.execute("DROP TABLE posts").await.unwrap();
+    conn.execute("CREATE TABLE posts (id INTEGER PRIMARY KEY)")
+        .await
+        .unwrap();
+
+    let drift = migrator().await.drift(scratch().await).await.unwrap();
+    assert_eq!(
+        drift,
+        [
+            SchemaDrift::Missing(
+                "CREATE TABLE posts (id INTEGER PRIMARY KEY, user_id INTEGER);".into()
+            ),
+            SchemaDrift::Unexpected("CREATE TABLE posts (id INTEGER PRIMARY KEY);".into()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn drift_bookkeeping() {
+    let url = common::sqlite_url("drift_bookkeeping");
+
+    let migrator = || async {
+        let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+        migrator.set_options(MigratorOptions {
+            record_runs: true,
+            ..Default::default()
+        });
+        migrator.add_migrations(migrations());
+        migrator.add_deferred([Migration::new("backfill_names", |ctx| {
+            Box::pin(async move {
+                ctx.tx()
+                    .execute("UPDATE users SET name = '' WHERE name IS NULL")
+                    .await?;
+                Ok(())
+            })
+        })]);
+        migrator
+    };
+
+    migrator().await.migrate_all().await.unwrap();
+    migrator().await.run_deferred().await.unwrap();
+
+    let release = migrator().await.tag_release("v1").await.unwrap();
+    assert_eq!((release.name.as_str(), release.version), ("v1", 2));
+
+    let scratch = || async { SqliteConnection::connect("sqlite::memory:").await.unwrap() };
+
+    // The bookkeeping tables are not part of the migrated schema.
+    let drift = migrator().await.drift(scratch().await).await.unwrap();
+    assert!(drift.is_empty(), "{drift:?}");
+
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+    conn.execute("CREATE TABLE comments (id INTEGER PRIMARY KEY)")
+        .await
+        .unwrap();
+
+    let drift = migrator().await.drift(scratch().await).await.unwrap();
+    assert_eq!(
+        drift,
+        [SchemaDrift::Unexpected(
+            "CREATE TABLE comments (id INTEGER PRIMARY KEY);".into()
+        )]
+    );
+
+    conn.execute("DROP TABLE comments").await.unwrap();
+
+    let summary = migrator().await.squash(2, "baseline").await.unwrap();
+    assert!(!summary.sql.contains("_sqlx_migrations"), "{}", summary.sql);
+}
+
+#[tokio::test]
+async fn diff_read_only() {
+    let url = common::sqlite_url("diff_read_only");
+    let other_url = common::sqlite_url("diff_read_only_other");
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.add_migrations(migrations());
+    migrator.migrate_all().await.unwrap();
+
+    // Local migrations are not needed, the database is not refused as newer.
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.set_options(MigratorOptions {
+        refuse_newer_database: true,
+        ..Default::default()
+    });
+    let other = SqliteConnection::connect(&other_url).await.unwrap();
+    let diff = migrator.diff(other).await.unwrap();
+    assert_eq!(
+        diff.iter().map(HistoryDiff::version).collect::<Vec<_>>(),
+        [1, 2]
+    );
+    assert!(diff
+        .iter()
+        .all(|diff| matches!(diff, HistoryDiff::OnlyThis(_))));
+
+    // The migrations table is not created in the other database.
+    let mut other = SqliteConnection::connect(&other_url).await.unwrap();
+    let tables: i64 = sqlx::query_scalar("SELECT count(*) FROM sqlite_master")
+        .fetch_one(&mut other)
+        .await
+        .unwrap();
+    assert_eq!(tables, 0);
+}
+
+#[tokio::test]
+async fn prune() {
+    let url = common::sqlite_url("prune");
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.add_migrations(migrations());
+    migrator.migrate_all().await.unwrap();
+
+    let pruning = || async {
+        let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+        migrator.add_migrations(migrations().into_iter().take(1));
+        migrator
+    };
+
+    let pruned = pruning().await.prune().await.unwrap();
+    assert_eq!(
+        pruned.iter().map(|mig| &*mig.name).collect::<Vec<_>>(),
+        ["create_posts"]
+    );
+
+    // Only the bookkeeping rows are removed.
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+    let applied = conn.list_migrations("_sqlx_migrations").await.unwrap();
+    assert_eq!(applied.len(), 1);
+    assert_eq!(
+        conn.table_columns("posts").await.unwrap(),
+        ["id", "user_id"]
+    );
+
+    assert!(pruning().await.prune().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn repair() {
+    let url = common::sqlite_url("repair");
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.add_migrations(migrations());
+    migrator.migrate_all().await.unwrap();
+
+    let renamed = || async {
+        let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+        migrator.add_migrations([Migration::new("create_accounts", |ctx| {
+            Box::pin(async move {
+                ctx.tx()
+                    .execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)")
+                    .await?;
+                Ok(())
+            })
+        })
+        .alias("create_users")]);
+        migrator.add_migrations(migrations().into_iter().skip(1));
+        migrator
+    };
+
+    let summary = renamed().await.repair().await.unwrap();
+    assert_eq!(
+        summary.renamed,
+        [(1, "create_users".into(), "create_accounts".into())]
+    );
+
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+    let applied = conn.list_migrations("_sqlx_migrations").await.unwrap();
+    assert_eq!(applied[0].name, "create_accounts");
+
+    renamed().await.verify().await.unwrap();
+    assert!(renamed().await.repair().await.unwrap().renamed.is_empty());
+}
+
+#[tokio::test]
+async fn import_history() {
+    let url = common::sqlite_url("import_history");
+    let other_url = common::sqlite_url("import_history_other");
+
+    let migrator = |url: String| async move {
+        let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+        migrator.add_migrations(migrations());
+        migrator
+    };
+
+    migrator(url.clone()).await.migrate_all().await.unwrap();
+    let history = migrator(url.clone()).await.export_history().await.unwrap();
+    assert_eq!(history.migrations.len(), 2);
+
+    migrator(other_url.clone())
+        .await
+        .import_history(&history, false)
+        .await
+        .unwrap();
+
+    // The migrations are recorded but not executed.
+    let mut conn = SqliteConnection::connect(&other_url).await.unwrap();
+    let imported = conn.list_migrations("_sqlx_migrations").await.unwrap();
+    assert_eq!(
+        imported
+            .into_iter()
+            .map(HistoryEntry::from)
+            .collect::<Vec<_>>(),
+        history.migrations
+    );
+    assert!(conn.table_columns("users").await.unwrap().is_empty());
+
+    assert!(matches!(
+        migrator(other_url.clone())
+            .await
+            .import_history(&history, false)
+            .await,
+        Err(Error::HistoryExists { applied: 2 })
+    ));
+
+    let mut partial = history.clone();
+    partial.migrations.truncate(1);
+    migrator(other_url.clone())
+        .await
+        .import_history(&partial, true)
+        .await
+        .unwrap();
+    assert_eq!(
+        conn.list_migrations("_sqlx_migrations")
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn local_status() {
+    let migrations = migrations()
+        .into_iter()
+        .map(|mig| mig.with_source_path("migrations/example.rs"))
+        .collect::<Vec<_>>();
+
+    let status = MigrationStatus::local(&migrations);
+    assert_eq!(status.len(), migrations.len());
+
+    for (idx, (status, mig)) in status.iter().zip(&migrations).enumerate() {
+        assert_eq!(status.version, idx as u64 + 1);
+        assert_eq!(status.name, mig.name());
+        assert_eq!(status.reversible, mig.is_reversible());
+        assert!(status.applied.is_none());
+        assert_eq!(
+            status.metadata.source_path.as_deref(),
+            Some("migrations/example.rs")
+        );
+    }
+}
+
+#[cfg(any(feature = "generate", feature = "cli"))]
+#[test]
+fn validate_generated() {
+    use std::fs;
+
+    let dir = std::env::temp_dir().join(format!(
+        "sqlx-migrate-validate-generated-{}",
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    let migrations_dir = dir.join("migrations");
+    fs::create_dir_all(&migrations_dir).unwrap();
+
+    let create = migrations_dir.join("20240101000000_create.migrate.sql");
+    let create_revert = migrations_dir.join("20240101000000_create.revert.sql");
+    let seed = migrations_dir.join("20240102000000_seed.migrate.rs");
+    fs::write(&create, "CREATE TABLE users (id INTEGER);").unwrap();
+    fs::write(&create_revert, "DROP TABLE users;").unwrap();
+    fs::write(&seed, "").unwrap();
+    fs::write(migrations_dir.join("README.md"), "").unwrap();
+
+    let generated_rs = dir.join("generated.rs");
+    fs::write(
+        &generated_rs,
+        format!(
+            "#[path = {:?}]\nmod seed;\nconst UP: &str = include_str!(\n    {:?},\n);\n\
+             const DOWN: &str = include_str!({:?});\n",
+            seed.display().to_string(),
+            create.display().to_string(),
+            create_revert.display().to_string(),
+        ),
+    )
+    .unwrap();
+
+    let stale = sqlx_migrate::validate_generated(&migrations_dir, &generated_rs).unwrap();
+    assert!(stale.is_empty(), "{stale:?}");
+
+    // A removed migration and a new one since the code was generated.
+    fs::remove_file(&seed).unwrap();
+    let added = migrations_dir.join("20240103000000_add_email.migrate.sqlite.sql");
+    fs::write(&added, "ALTER TABLE users ADD COLUMN email TEXT;").unwrap();
+
+    let stale = sqlx_migrate::validate_generated(&migrations_dir, &generated_rs).unwrap();
+    assert_eq!(stale.missing_files, vec![seed]);
+    assert_eq!(stale.ungenerated_files, vec![added]);
+    assert!(stale.to_string().contains("re-run the build script"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn params() {
+    let url = common::sqlite_url("params");
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.set(Params::from_iter([
+        ("table", "tenants"),
+        ("batch_size", "10"),
+    ]));
+    migrator.add_migrations([Migration::new("create_table", |ctx| {
+        Box::pin(async move {
+            let params = ctx.get::<Params>().unwrap();
+            let table = params.get("table").unwrap().to_string();
+            assert_eq!(params.parse::<u64>("batch_size").unwrap(), Some(10));
+            assert!(params.parse::<u64>("table").is_err());
+
+            ctx.tx()
+                .execute(&*format!("CREATE TABLE {table} (id INTEGER PRIMARY KEY)"))
+                .await?;
+            Ok(())
+        })
+    })]);
+
+    migrator.migrate_all().await.unwrap();
+
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+    assert_eq!(conn.table_columns("tenants").await.unwrap(), ["id"]);
+}
+
+#[tokio::test]
+async fn hash_placeholders() {
+    let url = common::sqlite_url("hash_placeholders");
+
+    let migrator = |tenant: &'static str| {
+        let url = url.clone();
+        async move {
+            let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+            migrator.set(Params::from_iter([("tenant", tenant)]));
+            migrator.add_migrations([Migration::new("create_table", |ctx| {
+                Box::pin(async move {
+                    let tenant = ctx.hash_placeholder("tenant").unwrap();
+                    assert_eq!(ctx.hash_placeholder("missing"), None);
+
+                    ctx.tx()
+                        .execute(&*format!(
+                            "CREATE TABLE {tenant}_users (id INTEGER PRIMARY KEY)"
+                        ))
+                        .await?;
+                    Ok(())
+                })
+            })]);
+            migrator
+        }
+    };
+
+    migrator("acme").await.migrate_all().await.unwrap();
+
+    // The checksum does not depend on the tenant.
+    migrator("globex").await.verify().await.unwrap();
+
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+    assert_eq!(conn.table_columns("acme_users").await.unwrap(), ["id"]);
+}
+
+#[tokio::test]
+async fn verify_without_executing() {
+    let url = common::sqlite_url("verify_without_executing");
+
+    let migrator = |name: &'static str| {
+        let url = url.clone();
+        async move {
+            let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+            migrator.add_migrations(migrations());
+            migrator.add_migrations([Migration::new("seed_users", move |ctx| {
+                Box::pin(async move {
+                    let users = sqlx::query("SELECT id FROM users")
+                        .fetch_all(ctx.tx())
+                        .await?;
+                    assert!(users.is_empty());
+
+                    ctx.tx()
+                        .execute(&*format!("INSERT INTO users (name) VALUES ('{name}')"))
+                        .await?;
+                    Ok(())
+                })
+            })]);
+            migrator
+        }
+    };
+
+    migrator("alice").await.migrate_all().await.unwrap();
+    migrator("alice").await.verify().await.unwrap();
+
+    assert!(matches!(
+        migrator("bob").await.verify().await,
+        Err(Error::ChecksumMismatch { version: 3, .. })
+    ));
+
+    // The checksums were calculated without executing the migrations.
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+    let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(&mut conn)
+        .await
+        .unwrap();
+    assert_eq!(users, 1);
+}
+
+#[tokio::test]
+async fn verify_prepared_statements() {
+    let url = common::sqlite_url("verify_prepared_statements");
+
+    let migrator = || async {
+        let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+        migrator.add_migrations(migrations());
+        migrator.add_migrations([Migration::new("describe_users", |ctx| {
+            Box::pin(async move {
+                let statement: sqlx::sqlite::SqliteStatement<'_> =
+                    ctx.tx().prepare("SELECT id, name FROM users").await?;
+                assert_eq!(statement.columns().len(), 2);
+                Ok(())
+            })
+        })]);
+        migrator
+    };
+
+    migrator().await.migrate_all().await.unwrap();
+
+    // Statements are prepared on the connection while checksums are calculated.
+    migrator().await.verify().await.unwrap();
+    assert_eq!(
+        migrator()
+            .await
+            .status()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|mig| mig.applied.is_some())
+            .count(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn foreign_migrations_table() {
+    let url = common::sqlite_url("foreign");
+
+    // The table of sqlx-cli has the same default name.
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+    conn.execute(
+        "CREATE TABLE _sqlx_migrations (
+            version BIGINT PRIMARY KEY,
+            description TEXT NOT NULL,
+            installed_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            success BOOLEAN NOT NULL,
+            checksum BLOB NOT NULL,
+            execution_time BIGINT NOT NULL
+        )",
+    )
+    .await
+    .unwrap();
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.add_migrations(migrations());
+
+    match migrator.migrate_all().await {
+        Err(Error::ForeignMigrationsTable { tool, missing, .. }) => {
+            assert_eq!(tool, Some("sqlx-cli"));
+            assert_eq!(missing, ["name", "applied_on"]);
+        }
+        res => panic!("unexpected result: {res:?}"),
+    }
+
+    // The table is left as it was.
+    assert_eq!(
+        conn.table_columns("_sqlx_migrations").await.unwrap(),
+        [
+            "version",
+            "description",
+            "installed_on",
+            "success",
+            "checksum",
+            "execution_time"
+        ]
+    );
+    assert!(conn.table_columns("users").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn read_only_status() {
+    let url = common::sqlite_url("read_only");
+    let read_only_url = url.replace("mode=rwc", "mode=ro");
+
+    // Create the empty database.
+    SqliteConnection::connect(&url).await.unwrap();
+
+    let mut migrator = Migrator::<Sqlite>::connect(&read_only_url).await.unwrap();
+    migrator.add_migrations(migrations());
+    let missing = migrator.preflight().await.unwrap();
+    assert_eq!(missing.len(), 1);
+    assert_eq!(missing[0].to_string(), "WRITE on DATABASE main");
+    assert_eq!(missing[0].grant, None);
+    let local = migrator.local_status();
+    assert_eq!(local.len(), 2);
+    assert!(local.iter().all(|status| status.applied.is_none()));
+
+    match migrator.status().await {
+        Err(Error::MigrationsTableInaccessible { table, .. }) => {
+            assert_eq!(table, "_sqlx_migrations");
+        }
+        res => panic!("unexpected result: {res:?}"),
+    }
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.add_migrations(migrations());
+    assert!(migrator.preflight().await.unwrap().is_empty());
+    migrator.migrate(1).await.unwrap();
+
+    // Existing tables are read without the privileges to create them.
+    let mut migrator = Migrator::<Sqlite>::connect(&read_only_url).await.unwrap();
+    migrator.add_migrations(migrations());
+    let status = migrator.status().await.unwrap();
+    assert!(status[0].applied.is_some());
+    assert!(status[1].applied.is_none());
+}
+
+#[cfg(feature = "report")]
+#[tokio::test]
+async fn report_tables() {
+    use sqlx_migrate::report;
+
+    let url = common::sqlite_url("report");
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.add_migrations(migrations());
+    let summary = migrator.migrate(1).await.unwrap();
+
+    let table = report::summary_table(&summary);
+    assert!(table.contains("New Version"));
+    assert!(report::irreversible_table(&summary).is_none());
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.add_migrations(migrations());
+    let status = migrator.status().await.unwrap();
+    assert!(status.iter().all(report::status_ok));
+
+    let table = report::status_table(&status, false);
+    assert!(table.contains("create_users"));
+    assert!(table.contains("create_posts"));
+    assert!(!table.contains("Source"));
+    assert!(report::status_table(&status, true).contains("Source"));
+}
+
+#[tokio::test]
+async fn backup_hook() {
+    let url = common::sqlite_url("backup");
+
+    let migrator = || async {
+        let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+        migrator.add_migrations(migrations());
+        migrator.add_migrations([Migration::new("drop_posts", |ctx| {
+            Box::pin(async move {
+                ctx.tx().execute("DROP TABLE posts").await?;
+                Ok(())
+            })
+        })
+        .with_tag(DESTRUCTIVE_TAG)]);
+        migrator
+    };
+
+    // The backup is taken before the migrations of the run are applied.
+    migrator().await.migrate(2).await.unwrap();
+
+    let mut failing = migrator().await;
+    failing.set_backup_hook(|_| Box::pin(async move { Err("disk full".into()) }));
+
+    match failing.migrate_all().await {
+        Err(Error::Backup { version, .. }) => assert_eq!(version, 3),
+        res => panic!("expected a failed backup, got {res:?}"),
+    }
+
+    // Nothing is applied if the backup fails.
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+    assert_eq!(
+        conn.list_migrations("_sqlx_migrations")
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+
+    let mut migrator = migrator().await;
+    migrator.set_backup_hook(|backup| {
+        Box::pin(async move {
+            assert_eq!(backup.version, 3);
+            assert!(backup.migration.is_destructive());
+            backup
+                .conn
+                .execute("CREATE TABLE posts_backup AS SELECT * FROM posts")
+                .await?;
+            Ok(())
+        })
+    });
+
+    migrator.migrate_all().await.unwrap();
+
+    assert_eq!(
+        conn.table_columns("posts_backup").await.unwrap(),
+        ["id", "user_id"]
+    );
+}
+
+#[tokio::test]
+async fn snapshots() {
+    fn migrations_with_snapshot() -> Vec<Migration<Sqlite>> {
+        let mut migrations = migrations();
+        migrations.push(
+            Migration::<Sqlite>::new("lowercase_names", |ctx| {
+                Box::pin(async move {
+                    ctx.tx()
+                        .execute("INSERT INTO users (id, name) VALUES (1, 'Alice'), (2, 'Bob')")
+                        .await?;
+                    ctx.snapshot_table("users").await?;
+                    ctx.tx()
+                        .execute("UPDATE users SET name = lower(name)")
+                        .await?;
+                    Ok(())
+                })
+            })
+            .reversible(|ctx| {
+                Box::pin(async move {
+                    assert_eq!(ctx.restore_snapshot("users").await?, 2);
+                    Ok(())
+                })
+            }),
+        );
+        migrations
+    }
+
+    async fn names(conn: &mut SqliteConnection) -> Vec<String> {
+        sqlx::query_scalar("SELECT name FROM users ORDER BY id")
+            .fetch_all(conn)
+            .await
+            .unwrap()
+    }
+
+    let url = common::sqlite_url("snapshots");
+
+    let migrator = || async {
+        let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+        migrator.add_migrations(migrations_with_snapshot());
+        migrator
+    };
+
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+
+    migrator().await.migrate_all().await.unwrap();
+    assert_eq!(names(&mut conn).await, ["alice", "bob"]);
+    assert_eq!(
+        conn.table_columns("_sqlx_snapshot_users").await.unwrap(),
+        ["id", "name"]
+    );
+
+    migrator().await.revert(3).await.unwrap();
+    assert_eq!(names(&mut conn).await, ["Alice", "Bob"]);
+    assert!(conn
+        .table_columns("_sqlx_snapshot_users")
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn newer_database() {
+    let url = common::sqlite_url("newer");
+
+    let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+    migrator.add_migrations(migrations());
+    migrator.migrate_all().await.unwrap();
+
+    // An older binary only knows the first migration.
+    let older = || async {
+        let mut migrator = Migrator::<Sqlite>::connect(&url).await.unwrap();
+        migrator.set_options(MigratorOptions {
+            refuse_newer_database: true,
+            ..Default::default()
+        });
+        migrator.add_migrations(migrations().into_iter().take(1));
+        migrator
+    };
+
+    match older().await.status().await {
+        Err(Error::NewerDatabase {
+            local_count,
+            db_count,
+        }) => {
+            assert_eq!(local_count, 1);
+            assert_eq!(db_count, 2);
+        }
+        res => panic!("expected a newer database, got {res:?}"),
+    }
+
+    assert!(matches!(
+        older().await.force_version(1).await,
+        Err(Error::NewerDatabase { .. })
+    ));
+
+    let mut conn = SqliteConnection::connect(&url).await.unwrap();
+    assert_eq!(
+        conn.list_migrations("_sqlx_migrations")
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+}
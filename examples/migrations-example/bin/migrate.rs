@@ -1,8 +1,12 @@
+use clap::Parser;
+use sqlx_migrate::cli::{Migrate, Runner};
 use std::path::Path;
 
 fn main() {
-    sqlx_migrate::cli::run(
+    Runner::new(
+        Migrate::parse(),
         Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations"),
         migrations_example::generated::migrations(),
-    );
+    )
+    .run();
 }
@@ -1,8 +1,5 @@
-use barrel::{
-    backend::Pg,
-    types::{self, ReferentialAction},
-};
-use sqlx::{query, query_as, Executor, Postgres};
+use barrel::types::{self, ReferentialAction};
+use sqlx::{query, query_as, Postgres};
 use sqlx_migrate::prelude::*;
 
 /// Executes migration `plush_sharks` in the given migration context.
@@ -25,26 +22,26 @@ pub async fn plush_sharks(ctx: &mut MigrationContext<Postgres>) -> Result<(), Mi
     .fetch_all(ctx.tx())
     .await?;
 
-    let mut m = barrel::Migration::new();
-    m.create_table("plush_sharks", |t| {
-        t.add_column(
-            "owner",
-            types::foreign(
-                "users",
-                "user_id",
-                ReferentialAction::NoAction,
-                ReferentialAction::NoAction,
-            ),
-        );
-        t.add_column("name", types::varchar(255));
-        t.add_column("color", types::text());
-    });
+    ctx.apply_schema(|m| {
+        m.create_table("plush_sharks", |t| {
+            t.add_column(
+                "owner",
+                types::foreign(
+                    "users",
+                    "user_id",
+                    ReferentialAction::NoAction,
+                    ReferentialAction::NoAction,
+                ),
+            );
+            t.add_column("name", types::varchar(255));
+            t.add_column("color", types::text());
+        });
 
-    m.change_table("users", |t| {
-        t.drop_column("owns_plush_sharks");
-    });
-
-    ctx.tx().execute(m.make::<Pg>().as_str()).await?;
+        m.change_table("users", |t| {
+            t.drop_column("owns_plush_sharks");
+        });
+    })
+    .await?;
 
     for (user_id,) in users_with_sharks {
         // Every user gets a very own plush shark.
@@ -1,4 +1,4 @@
-use barrel::{backend::Pg, types};
+use barrel::types;
 use sqlx::{query, query_as, Executor, Postgres};
 use sqlx_migrate::prelude::*;
 
@@ -9,12 +9,12 @@ use sqlx_migrate::prelude::*;
 pub async fn revert_plush_sharks(
     ctx: &mut MigrationContext<Postgres>,
 ) -> Result<(), MigrationError> {
-    let mut m = barrel::Migration::new();
-    m.change_table("users", |t| {
-        t.add_column("owns_plush_sharks", types::boolean().default(false));
-    });
-
-    ctx.tx().execute(m.make::<Pg>().as_ref()).await?;
+    ctx.apply_schema(|m| {
+        m.change_table("users", |t| {
+            t.add_column("owns_plush_sharks", types::boolean().default(false));
+        });
+    })
+    .await?;
 
     let mut users_with_sharks: Vec<i32> = query_as::<_, (i32,)>(
         r#"
@@ -31,26 +31,34 @@ pub fn migrations() -> impl IntoIterator<Item = Migration<sqlx::Postgres>> {
                     let ctx: &mut sqlx_migrate::prelude::MigrationContext<
                         sqlx::Postgres,
                     > = ctx;
-                    ctx.tx()
-                        .execute(
+                    let sql = ctx
+                        .substitute(
                             include_str!(
                                 "/home/tamasfe/work/opensauce/sqlx-migrate/examples/migrations-example/migrations/20211215161742_initial_migration.migrate.sql"
                             ),
-                        )
-                        .await?;
+                        );
+                    ctx.tx().execute(&*sql).await?;
                     Ok(())
                 }),
             )
+            .with_created_at("20211215161742")
+            .with_source_path(
+                "/home/tamasfe/work/opensauce/sqlx-migrate/examples/migrations-example/migrations/20211215161742_initial_migration.migrate.sql",
+            )
+            .with_checksum(
+                b"L\xFEk\xBE\x94\xF6(\xB7$+\xCEMNR\x18t\xBF\xF08\xFE\xE0\x98p+\x81_R\x12\xFF\x9C\x04\xF3"
+                    .as_slice(),
+            )
             .reversible(|ctx| std::boxed::Box::pin(async move {
                 use sqlx::Executor;
                 let ctx: &mut sqlx_migrate::prelude::MigrationContext<sqlx::Postgres> = ctx;
-                ctx.tx()
-                    .execute(
+                let sql = ctx
+                    .substitute(
                         include_str!(
                             "/home/tamasfe/work/opensauce/sqlx-migrate/examples/migrations-example/migrations/20211215161742_initial_migration.revert.sql"
                         ),
-                    )
-                    .await?;
+                    );
+                ctx.tx().execute(&*sql).await?;
                 Ok(())
             })),
         sqlx_migrate::Migration::new(
@@ -62,6 +70,10 @@ pub fn migrations() -> impl IntoIterator<Item = Migration<sqlx::Postgres>> {
                     Ok(())
                 }),
             )
+            .with_created_at("20211215162220")
+            .with_source_path(
+                "/home/tamasfe/work/opensauce/sqlx-migrate/examples/migrations-example/migrations/20211215162220_plush_sharks.migrate.rs",
+            )
             .reversible(|ctx| std::boxed::Box::pin(async move {
                 #[path = "/home/tamasfe/work/opensauce/sqlx-migrate/examples/migrations-example/migrations/20211215162220_plush_sharks.revert.rs"]
                 mod revert_plush_sharks;
@@ -70,3 +82,22 @@ pub fn migrations() -> impl IntoIterator<Item = Migration<sqlx::Postgres>> {
             })),
     ]
 }
+/// The metadata of all the migrations, in the same order as [`migrations`].
+pub const MIGRATIONS: &[sqlx_migrate::MigrationMeta] = &[
+    sqlx_migrate::MigrationMeta {
+        name: "initial_migration",
+        version: 1u64,
+        created_at: "20211215161742",
+        source_path: "/home/tamasfe/work/opensauce/sqlx-migrate/examples/migrations-example/migrations/20211215161742_initial_migration.migrate.sql",
+        is_sql: true,
+        reversible: true,
+    },
+    sqlx_migrate::MigrationMeta {
+        name: "plush_sharks",
+        version: 2u64,
+        created_at: "20211215162220",
+        source_path: "/home/tamasfe/work/opensauce/sqlx-migrate/examples/migrations-example/migrations/20211215162220_plush_sharks.migrate.rs",
+        is_sql: false,
+        reversible: true,
+    },
+];